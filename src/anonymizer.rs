@@ -0,0 +1,301 @@
+use std::collections::hash_map::Entry;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::Aes128;
+use sha2::{Digest, Sha256};
+
+use crate::decoder::{IPPROTO_SCTP, IPPROTO_TCP, IPPROTO_UDP};
+use crate::direction::Endpoint;
+use crate::flow_id::FlowId;
+use crate::flow_information::FlowInformation;
+use crate::generator::Generator;
+
+/// length of a Crypto-PAn key: an AES-128 key, then the block giving the pad
+pub const KEY_LENGTH: usize = 32;
+/// the ports below are the service ones, kept when the ports are truncated
+const EPHEMERAL_PORT_START: u16 = 1024;
+
+/// The anonymization of the SNI.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SniPolicy {
+    /// the SNI is kept as is
+    #[default]
+    Keep,
+    /// the SNI is removed
+    Scrub,
+    /// the SNI is replaced by its keyed SHA-256 hash, in lowercase hexadecimal
+    Hash,
+}
+
+/// A prefix-preserving IP anonymizer (Crypto-PAn), for IPv4 and IPv6.
+/// Two addresses sharing a prefix of n bits are anonymized to two addresses sharing a prefix of n bits,
+/// and the same key always gives the same anonymized address.
+pub struct Anonymizer {
+    /// keep the private, loopback and link-local addresses unchanged
+    pub preserve_private: bool,
+    /// replace the ephemeral TCP, UDP and SCTP ports by 0, keeping the service ones
+    pub truncate_ports: bool,
+    /// anonymization of the SNI
+    pub sni_policy: SniPolicy,
+    cipher: Aes128,
+    pad: [u8; 16],
+}
+
+impl fmt::Debug for Anonymizer {
+    /// The key isn't shown.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Anonymizer")
+            .field("preserve_private", &self.preserve_private)
+            .field("truncate_ports", &self.truncate_ports)
+            .field("sni_policy", &self.sni_policy)
+            .finish()
+    }
+}
+
+impl Anonymizer {
+    /// Provide an anonymizer with the given key, anonymizing every address and keeping the ports and the SNI.
+    pub fn new(key: &[u8; KEY_LENGTH]) -> Self {
+        let cipher = Aes128::new(GenericArray::from_slice(&key[..16]));
+        let mut pad = GenericArray::clone_from_slice(&key[16..]);
+        cipher.encrypt_block(&mut pad);
+        Self {
+            preserve_private: false,
+            truncate_ports: false,
+            sni_policy: SniPolicy::default(),
+            cipher,
+            pad: pad.into(),
+        }
+    }
+
+    /// Returns the anonymized IP address.
+    pub fn anonymize_ip(&self, ip: IpAddr) -> IpAddr {
+        match ip {
+            IpAddr::V4(ipv4) => IpAddr::V4(self.anonymize_ipv4(ipv4)),
+            IpAddr::V6(ipv6) => IpAddr::V6(self.anonymize_ipv6(ipv6)),
+        }
+    }
+
+    /// Returns the anonymized IPv4 address.
+    pub fn anonymize_ipv4(&self, ipv4: Ipv4Addr) -> Ipv4Addr {
+        if self.preserve_private && (ipv4.is_private() || ipv4.is_loopback() || ipv4.is_link_local()) {
+            return ipv4;
+        }
+        Ipv4Addr::from(self.prefix_preserving(u128::from(u32::from(ipv4)), 32) as u32)
+    }
+
+    /// Returns the anonymized IPv6 address.
+    pub fn anonymize_ipv6(&self, ipv6: Ipv6Addr) -> Ipv6Addr {
+        let first_segment = ipv6.segments()[0];
+        // unique local (fc00::/7) and link-local (fe80::/10) addresses
+        let is_private = first_segment & 0xfe00 == 0xfc00 || first_segment & 0xffc0 == 0xfe80;
+        if self.preserve_private && (is_private || ipv6.is_loopback()) {
+            return ipv6;
+        }
+        Ipv6Addr::from(self.prefix_preserving(u128::from(ipv6), 128))
+    }
+
+    /// Returns the anonymized endpoint of a flow of the given transport protocol.
+    pub fn anonymize_endpoint(&self, endpoint: Endpoint, transport_protocol: u8) -> Endpoint {
+        Endpoint {
+            ip: self.anonymize_ip(endpoint.ip),
+            port: self.anonymize_port(endpoint.port, transport_protocol),
+        }
+    }
+
+    /// Returns the anonymized flow id.
+    /// The source and the destination are anonymized alike,
+    /// so the flow ids of both directions of a flow stay equal.
+    pub fn anonymize_flow_id(&self, flow_id: &FlowId) -> FlowId {
+        let source = self.anonymize_endpoint(flow_id.source(), flow_id.transport_protocol);
+        let destination = self.anonymize_endpoint(flow_id.destination(), flow_id.transport_protocol);
+        FlowId {
+            src: source.ip,
+            src_port: source.port,
+            dst: destination.ip,
+            dst_port: destination.port,
+            transport_protocol: flow_id.transport_protocol,
+        }
+    }
+
+    /// Anonymize the endpoints and the SNI of a flow information, and drop its reassembled payloads.
+    pub fn anonymize_flow_information(&self, flow_information: &mut FlowInformation, transport_protocol: u8) {
+        flow_information.client = flow_information
+            .client
+            .map(|client| self.anonymize_endpoint(client, transport_protocol));
+        flow_information.server = flow_information
+            .server
+            .map(|server| self.anonymize_endpoint(server, transport_protocol));
+        flow_information.sni = match self.sni_policy {
+            SniPolicy::Keep => flow_information.sni.take(),
+            SniPolicy::Scrub => None,
+            SniPolicy::Hash => flow_information.sni.as_deref().map(|sni| self.hash(sni)),
+        };
+        flow_information.tcp_stream = None;
+    }
+
+    /// Returns a generator with the anonymized flows of the given one.
+    /// The flows which can't be told apart anymore, such as with truncated ports, are merged.
+    pub fn anonymize_generator(&self, generator: Generator) -> Generator {
+        let mut anonymized = Generator::new();
+        anonymized.direction_policy = generator.direction_policy;
+        anonymized.flow_order = generator.flow_order;
+        for (flow_id, mut flow_information) in generator {
+            let anonymized_flow_id = self.anonymize_flow_id(&flow_id);
+            self.anonymize_flow_information(&mut flow_information, flow_id.transport_protocol);
+            match anonymized.entry(anonymized_flow_id) {
+                Entry::Vacant(entry) => {
+                    entry.insert(flow_information);
+                }
+                Entry::Occupied(mut entry) => {
+                    let flow_id = *entry.key();
+                    entry.get_mut().merge(&flow_id, flow_information, &anonymized_flow_id);
+                }
+            }
+        }
+        anonymized
+    }
+
+    /// Anonymize the bits of an address, from the most significant one,
+    /// each one being flipped by a bit of the encryption of the bits before it.
+    fn prefix_preserving(&self, address: u128, bit_count: u32) -> u128 {
+        let pad = u128::from_be_bytes(self.pad);
+        // the address bits at the beginning of the block
+        let aligned_address = address << (128 - bit_count);
+        let mut flip_list: u128 = 0;
+        for position in 0..bit_count {
+            // the first bits of the address, then the bits of the pad
+            let mask = u128::MAX.checked_shl(128 - position).unwrap_or_default();
+            let input = (aligned_address & mask) | (pad & !mask);
+            let mut block = GenericArray::from(input.to_be_bytes());
+            self.cipher.encrypt_block(&mut block);
+            flip_list |= u128::from(block[0] >> 7) << (bit_count - 1 - position);
+        }
+        address ^ flip_list
+    }
+
+    fn anonymize_port(&self, port: u16, transport_protocol: u8) -> u16 {
+        match transport_protocol {
+            IPPROTO_TCP | IPPROTO_UDP | IPPROTO_SCTP if self.truncate_ports && port >= EPHEMERAL_PORT_START => 0,
+            _ => port,
+        }
+    }
+
+    fn hash(&self, text: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.pad);
+        hasher.update(text.as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use crate::anonymizer::{Anonymizer, SniPolicy, KEY_LENGTH};
+    use crate::flow_id::FlowId;
+    use crate::flow_information::FlowInformation;
+    use crate::generator::Generator;
+    use crate::packet::Packet;
+
+    /// the key of the Crypto-PAn sample
+    fn key() -> [u8; KEY_LENGTH] {
+        [
+            21, 34, 23, 141, 51, 164, 207, 128, 19, 10, 91, 22, 73, 144, 125, 16, 216, 152, 143, 131, 121, 121, 101,
+            39, 98, 87, 76, 45, 42, 132, 34, 2,
+        ]
+    }
+
+    fn common_prefix_length(first: u128, second: u128) -> u32 {
+        (first ^ second).leading_zeros()
+    }
+
+    #[test]
+    fn it_can_anonymize_the_crypto_pan_sample() {
+        let anonymizer = Anonymizer::new(&key());
+        for (ip, anonymized_ip) in [
+            ("128.11.68.132", "135.242.180.132"),
+            ("129.118.74.4", "134.136.186.123"),
+            ("130.132.252.244", "133.68.164.234"),
+            ("141.223.7.43", "141.167.8.160"),
+        ] {
+            assert_eq!(
+                anonymizer.anonymize_ipv4(ip.parse().unwrap()),
+                anonymized_ip.parse::<Ipv4Addr>().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn it_can_preserve_the_ipv6_prefixes() {
+        let anonymizer = Anonymizer::new(&key());
+        let ip_list: Vec<Ipv6Addr> = ["2001:db8::1", "2001:db8::2", "2001:db8:1::1", "2a00:1450::1"]
+            .iter()
+            .map(|ip| ip.parse().unwrap())
+            .collect();
+        for first in &ip_list {
+            for second in &ip_list {
+                let anonymized_first = u128::from(anonymizer.anonymize_ipv6(*first));
+                let anonymized_second = u128::from(anonymizer.anonymize_ipv6(*second));
+                assert_eq!(
+                    common_prefix_length(anonymized_first, anonymized_second),
+                    common_prefix_length(u128::from(*first), u128::from(*second))
+                );
+            }
+        }
+        assert_ne!(anonymizer.anonymize_ipv6(ip_list[0]), ip_list[0]);
+    }
+
+    #[test]
+    fn it_can_preserve_the_private_addresses() {
+        let mut anonymizer = Anonymizer::new(&key());
+        anonymizer.preserve_private = true;
+        for ip in ["10.1.2.3", "192.168.0.1", "127.0.0.1", "fd00::1", "fe80::1"] {
+            let ip: IpAddr = ip.parse().unwrap();
+            assert_eq!(anonymizer.anonymize_ip(ip), ip);
+        }
+        let public_ip: IpAddr = "128.11.68.132".parse().unwrap();
+        assert_ne!(anonymizer.anonymize_ip(public_ip), public_ip);
+    }
+
+    #[test]
+    fn it_can_anonymize_a_generator() {
+        let mut anonymizer = Anonymizer::new(&key());
+        anonymizer.truncate_ports = true;
+        anonymizer.sni_policy = SniPolicy::Hash;
+        let mut generator = Generator::new();
+        for (src_port, sni) in [(42254, "www.example.com"), (42255, "www.example.org")] {
+            let flow_id = FlowId::new(6, "128.11.68.132", "129.118.74.4", src_port, 443);
+            generator.add_packet(flow_id, Packet::new());
+            generator.get_mut(&flow_id).unwrap().sni = Some(sni.to_string());
+        }
+        generator.add_packet(
+            FlowId::new(6, "129.118.74.4", "128.11.68.132", 443, 42254),
+            Packet::new(),
+        );
+
+        let mut anonymized = anonymizer.anonymize_generator(generator);
+        assert_eq!(anonymized.len(), 1);
+        let client_flow_id = FlowId::new(6, "135.242.180.132", "134.136.186.123", 0, 443);
+        let server_flow_id = FlowId::new(6, "134.136.186.123", "135.242.180.132", 443, 0);
+        assert!(anonymized.contains_key(&server_flow_id));
+        let flow_information = anonymized.get_mut(&client_flow_id).unwrap();
+        assert_eq!(flow_information.forward_packet_list.len(), 2);
+        assert_eq!(flow_information.backward_packet_list.len(), 1);
+        assert_eq!(flow_information.client, Some(client_flow_id.source()));
+        assert_eq!(flow_information.sni.as_ref().map(String::len), Some(64));
+    }
+
+    #[test]
+    fn it_can_scrub_the_sni() {
+        let mut anonymizer = Anonymizer::new(&key());
+        anonymizer.sni_policy = SniPolicy::Scrub;
+        let mut flow_information = FlowInformation::new();
+        flow_information.sni = Some("www.example.com".to_string());
+        anonymizer.anonymize_flow_information(&mut flow_information, 6);
+        assert!(flow_information.sni.is_none());
+    }
+}
@@ -0,0 +1,448 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+use libflow::decoder::{IPPROTO_ICMP, IPPROTO_ICMPV6, IPPROTO_SCTP, IPPROTO_TCP, IPPROTO_UDP};
+use libflow::error::{Error, Result};
+use libflow::feature;
+use libflow::flow_id::FlowId;
+use libflow::generator::{self, Generator};
+use libflow::ipfix;
+use libflow::json_lines::{self, JsonLinesWriter};
+use libflow::pcap;
+use libflow::pcapng;
+
+/// Convert, inspect and summarize flow files.
+#[derive(Debug, Parser)]
+#[command(name = "libflow", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Convert a flow file to another format
+    Convert {
+        /// input file: pcap, pcapng, JSON or JSON Lines
+        input: PathBuf,
+        /// output file: JSON, JSON Lines, features CSV or IPFIX
+        output: PathBuf,
+        /// input format, guessed from the file extension by default
+        #[arg(long)]
+        from: Option<Format>,
+        /// output format, guessed from the file extension by default
+        #[arg(long)]
+        to: Option<Format>,
+    },
+    /// Print the flow, packet and byte counts, by protocol, and the top talkers
+    Stats {
+        /// input file: pcap, pcapng, JSON or JSON Lines
+        input: PathBuf,
+        /// input format, guessed from the file extension by default
+        #[arg(long)]
+        from: Option<Format>,
+        /// number of top talkers
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+    },
+    /// Print the packets of a flow
+    Show {
+        /// input file: pcap, pcapng, JSON or JSON Lines
+        input: PathBuf,
+        /// flow id, as src-dst-src_port-dst_port-transport_protocol
+        flow_id: FlowId,
+        /// input format, guessed from the file extension by default
+        #[arg(long)]
+        from: Option<Format>,
+    },
+    /// Write the features of the flows as CSV
+    Features {
+        /// input file: pcap, pcapng, JSON or JSON Lines
+        input: PathBuf,
+        /// output CSV file, the standard output by default
+        output: Option<PathBuf>,
+        /// input format, guessed from the file extension by default
+        #[arg(long)]
+        from: Option<Format>,
+    },
+}
+
+/// A flow file format.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum Format {
+    /// pcap capture
+    Pcap,
+    /// pcapng capture
+    Pcapng,
+    /// generator JSON
+    Json,
+    /// one flow by line JSON
+    Jsonl,
+    /// flow features CSV
+    Csv,
+    /// IPFIX messages
+    Ipfix,
+}
+
+impl Format {
+    /// Returns the format given, or else the one of the file extension.
+    fn resolve(format: Option<Format>, path: &Path) -> Result<Format> {
+        if let Some(format) = format {
+            return Ok(format);
+        }
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("pcap") | Some("cap") => Ok(Format::Pcap),
+            Some("pcapng") => Ok(Format::Pcapng),
+            Some("json") => Ok(Format::Json),
+            Some("jsonl") | Some("ndjson") => Ok(Format::Jsonl),
+            Some("csv") => Ok(Format::Csv),
+            Some("ipfix") => Ok(Format::Ipfix),
+            _ => Err(Error::Validation(format!(
+                "{}: unknown format, see --from and --to",
+                path.display()
+            ))),
+        }
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if let Err(error) = run(cli.command) {
+        eprintln!("libflow: {}", error);
+        process::exit(1);
+    }
+}
+
+fn run(command: Command) -> Result<()> {
+    match command {
+        Command::Convert {
+            input,
+            output,
+            from,
+            to,
+        } => {
+            let generator = read(&input, from)?;
+            write(&generator, &output, to)
+        }
+        Command::Stats { input, from, top } => {
+            Ok(Stats::new(&read(&input, from)?).write(&mut io::stdout().lock(), top)?)
+        }
+        Command::Show { input, flow_id, from } => write_flow(&mut io::stdout().lock(), &read(&input, from)?, &flow_id),
+        Command::Features { input, output, from } => {
+            let generator = read(&input, from)?;
+            match output {
+                Some(output) => feature::try_write_to_csv_file(&generator, output),
+                None => Ok(feature::write_csv(&mut io::stdout().lock(), generator.sorted())?),
+            }
+        }
+    }
+}
+
+/// Read the flows of a file.
+fn read(path: &Path, format: Option<Format>) -> Result<Generator> {
+    match Format::resolve(format, path)? {
+        Format::Pcap => pcap::try_read_from_pcap_file(path),
+        Format::Pcapng => pcapng::try_read_from_pcapng_file(path),
+        Format::Json => generator::try_read_from_file(path),
+        Format::Jsonl => json_lines::read_from_json_lines_file(path)?.collect(),
+        format => Err(Error::Validation(format!("{:?} can't be read", format))),
+    }
+}
+
+/// Write the flows to a file.
+fn write(generator: &Generator, path: &Path, format: Option<Format>) -> Result<()> {
+    match Format::resolve(format, path)? {
+        Format::Json => generator::try_write_to_file(generator, path),
+        Format::Jsonl => {
+            let mut writer = JsonLinesWriter::new(BufWriter::new(File::create(path)?));
+            writer.write_flows(generator.sorted())?;
+            writer.flush()
+        }
+        Format::Csv => feature::try_write_to_csv_file(generator, path),
+        Format::Ipfix => ipfix::try_write_to_ipfix_file(generator, path),
+        format => Err(Error::Validation(format!("{:?} can't be written", format))),
+    }
+}
+
+/// Returns the name of a transport protocol.
+fn protocol_name(transport_protocol: u8) -> String {
+    match transport_protocol {
+        IPPROTO_ICMP => "ICMP".to_string(),
+        IPPROTO_TCP => "TCP".to_string(),
+        IPPROTO_UDP => "UDP".to_string(),
+        IPPROTO_ICMPV6 => "ICMPv6".to_string(),
+        IPPROTO_SCTP => "SCTP".to_string(),
+        _ => transport_protocol.to_string(),
+    }
+}
+
+/// The flow, packet and byte counts of a set of flows.
+#[derive(Debug, Default, Eq, PartialEq)]
+struct Count {
+    flow_count: u64,
+    packet_count: u64,
+    octet_count: u64,
+}
+
+/// The counts of the flows of a generator, by transport protocol, and the bytes sent by IP address.
+#[derive(Debug, Default)]
+struct Stats {
+    total: Count,
+    protocol_map: BTreeMap<u8, Count>,
+    /// bytes sent by IP address, the top talkers first
+    talker_list: Vec<(IpAddr, u64)>,
+}
+
+impl Stats {
+    /// Count the flows of a generator.
+    fn new(generator: &Generator) -> Self {
+        let mut stats = Stats::default();
+        let mut talker_map: HashMap<IpAddr, u64> = HashMap::new();
+        for (flow_id, flow_information) in generator.iter() {
+            let summary = flow_information.summarize();
+            let oriented_flow_id = flow_information.oriented_flow_id(flow_id);
+            let packet_count = summary.forward_packet_count + summary.backward_packet_count;
+            let octet_count = summary.forward_octet_count + summary.backward_octet_count;
+            for count in [
+                &mut stats.total,
+                stats.protocol_map.entry(flow_id.transport_protocol).or_default(),
+            ] {
+                count.flow_count += 1;
+                count.packet_count += packet_count;
+                count.octet_count += octet_count;
+            }
+            *talker_map.entry(oriented_flow_id.src).or_default() += summary.forward_octet_count;
+            *talker_map.entry(oriented_flow_id.dst).or_default() += summary.backward_octet_count;
+        }
+        stats.talker_list = talker_map.into_iter().collect();
+        stats
+            .talker_list
+            .sort_by(|(ip, octet_count), (other_ip, other_octet_count)| {
+                other_octet_count.cmp(octet_count).then(ip.cmp(other_ip))
+            });
+        stats
+    }
+
+    /// Write the counts, then the given number of top talkers.
+    fn write<W: Write>(&self, out: &mut W, top: usize) -> io::Result<()> {
+        writeln!(
+            out,
+            "{} flows, {} packets, {} bytes",
+            self.total.flow_count, self.total.packet_count, self.total.octet_count
+        )?;
+        writeln!(out, "\nprotocol  flows  packets  bytes")?;
+        for (transport_protocol, count) in &self.protocol_map {
+            writeln!(
+                out,
+                "{}  {}  {}  {}",
+                protocol_name(*transport_protocol),
+                count.flow_count,
+                count.packet_count,
+                count.octet_count
+            )?;
+        }
+        writeln!(out, "\ntop talkers  bytes sent")?;
+        for (ip, octet_count) in self.talker_list.iter().take(top) {
+            writeln!(out, "{}  {}", ip, octet_count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Write the packets of a flow, or returns an error if the generator hasn't the flow.
+fn write_flow<W: Write>(out: &mut W, generator: &Generator, flow_id: &FlowId) -> Result<()> {
+    let flow_information = generator
+        .get(flow_id)
+        .ok_or_else(|| Error::Validation(format!("flow {} not found", flow_id)))?;
+
+    writeln!(out, "flow {}", flow_information.oriented_flow_id(flow_id))?;
+    if let Some(sni) = &flow_information.sni {
+        writeln!(out, "sni {}", sni)?;
+    }
+    if let Some(end_reason) = &flow_information.end_reason {
+        writeln!(out, "end reason {:?}", end_reason)?;
+    }
+    writeln!(out, "\nposition  timestamp  direction  length  flags")?;
+    for (packet, forward) in flow_information.packet_sequence() {
+        let flag_list: Vec<String> = packet.flag_list.iter().map(|flag| format!("{:?}", flag)).collect();
+        writeln!(
+            out,
+            "{}  {:.6}  {}  {}  {}",
+            packet.position,
+            packet.timestamp.as_secs_f64(),
+            if forward { "->" } else { "<-" },
+            packet.length,
+            flag_list.join(",")
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::Duration;
+
+    use libflow::flag::Flag;
+    use libflow::flow_id::FlowId;
+    use libflow::generator::Generator;
+    use libflow::packet::Packet;
+
+    use crate::{read, write, write_flow, Count, Format, Stats};
+
+    fn packet(seconds: u64, length: u64, flags: &[Flag]) -> Packet {
+        let mut packet = Packet::new();
+        packet.timestamp = Duration::from_secs(seconds);
+        packet.length = length;
+        packet.position = seconds as usize;
+        packet.flag_list = flags.iter().cloned().collect();
+        packet
+    }
+
+    fn udp_flow_id() -> FlowId {
+        FlowId::new(17, "10.0.0.1", "10.0.0.2", 5353, 53)
+    }
+
+    fn tcp_flow_id() -> FlowId {
+        FlowId::new(6, "10.0.0.3", "10.0.0.2", 42254, 443)
+    }
+
+    /// a UDP query and its response, then a TCP SYN
+    fn generator() -> Generator {
+        let mut generator = Generator::new();
+        generator.add_packet(udp_flow_id(), packet(1, 100, &[]));
+        generator.add_packet(FlowId::new(17, "10.0.0.2", "10.0.0.1", 53, 5353), packet(2, 200, &[]));
+        generator.add_packet(tcp_flow_id(), packet(3, 60, &[Flag::SYN]));
+        generator
+    }
+
+    #[test]
+    fn it_can_resolve_a_format() {
+        assert_eq!(
+            Format::resolve(Some(Format::Json), Path::new("flows.pcap")).unwrap(),
+            Format::Json
+        );
+        assert_eq!(Format::resolve(None, Path::new("flows.PCAP")).unwrap(), Format::Pcap);
+        assert_eq!(Format::resolve(None, Path::new("flows.cap")).unwrap(), Format::Pcap);
+        assert_eq!(Format::resolve(None, Path::new("flows.ndjson")).unwrap(), Format::Jsonl);
+        assert_eq!(Format::resolve(None, Path::new("flows.ipfix")).unwrap(), Format::Ipfix);
+        assert!(Format::resolve(None, Path::new("flows.txt")).is_err());
+        assert!(Format::resolve(None, Path::new("flows")).is_err());
+    }
+
+    #[test]
+    fn it_can_write_then_read_a_flow_file() {
+        let generator = generator();
+        for path in &["target/cli_flows.json", "target/cli_flows.jsonl"] {
+            write(&generator, Path::new(path), None).unwrap();
+            let read_generator = read(Path::new(path), None).unwrap();
+            assert_eq!(read_generator.len(), 2);
+            assert_eq!(
+                read_generator.get(&udp_flow_id()).unwrap().backward_packet_list.len(),
+                1
+            );
+        }
+        // the format given wins over the file extension
+        write(&generator, Path::new("target/cli_flows.txt"), Some(Format::Json)).unwrap();
+        assert_eq!(
+            read(Path::new("target/cli_flows.txt"), Some(Format::Json))
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn it_cannot_read_or_write_an_unsupported_format() {
+        let generator = generator();
+        write(&generator, Path::new("target/cli_flows.csv"), None).unwrap();
+        assert!(read(Path::new("target/cli_flows.csv"), None).is_err());
+        assert!(write(&generator, Path::new("target/cli_flows.pcap"), None).is_err());
+    }
+
+    #[test]
+    fn it_can_count_the_flows() {
+        let stats = Stats::new(&generator());
+        assert_eq!(
+            stats.total,
+            Count {
+                flow_count: 2,
+                packet_count: 3,
+                octet_count: 360
+            }
+        );
+        let protocol_list: Vec<u8> = stats.protocol_map.keys().cloned().collect();
+        assert_eq!(protocol_list, vec![6, 17]);
+        assert_eq!(stats.protocol_map[&17].packet_count, 2);
+        // the top talkers first, then by IP address
+        let talker_list: Vec<(String, u64)> = stats
+            .talker_list
+            .iter()
+            .map(|(ip, octet_count)| (ip.to_string(), *octet_count))
+            .collect();
+        assert_eq!(
+            talker_list,
+            vec![
+                ("10.0.0.2".to_string(), 200),
+                ("10.0.0.1".to_string(), 100),
+                ("10.0.0.3".to_string(), 60),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_can_write_the_stats() {
+        let mut out = Vec::new();
+        Stats::new(&generator()).write(&mut out, 2).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2 flows, 3 packets, 360 bytes\n\
+             \n\
+             protocol  flows  packets  bytes\n\
+             TCP  1  1  60\n\
+             UDP  1  2  300\n\
+             \n\
+             top talkers  bytes sent\n\
+             10.0.0.2  200\n\
+             10.0.0.1  100\n"
+        );
+    }
+
+    #[test]
+    fn it_can_write_a_flow() {
+        let generator = generator();
+        let mut out = Vec::new();
+        // the flow id of either direction gives the flow
+        write_flow(&mut out, &generator, &FlowId::new(17, "10.0.0.2", "10.0.0.1", 53, 5353)).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "flow 10.0.0.1-10.0.0.2-5353-53-17\n\
+             \n\
+             position  timestamp  direction  length  flags\n\
+             1  1.000000  ->  100  \n\
+             2  2.000000  <-  200  \n"
+        );
+
+        let mut out = Vec::new();
+        write_flow(&mut out, &generator, &tcp_flow_id()).unwrap();
+        assert!(String::from_utf8(out).unwrap().ends_with("3  3.000000  ->  60  SYN\n"));
+    }
+
+    #[test]
+    fn it_cannot_write_a_missing_flow() {
+        let mut out = Vec::new();
+        let flow_id = FlowId::new(6, "10.0.0.1", "10.0.0.2", 42254, 443);
+        assert!(write_flow(&mut out, &generator(), &flow_id).is_err());
+        assert!(out.is_empty());
+    }
+}
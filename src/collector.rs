@@ -0,0 +1,638 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use log::debug;
+
+use crate::error::{Error, Result};
+use crate::flag::Flag;
+use crate::flow_id::FlowId;
+use crate::flow_information::FlowInformation;
+use crate::flow_summary::FlowSummary;
+use crate::ipfix::{
+    FieldSpecifier, DESTINATION_IPV4_ADDRESS, DESTINATION_IPV6_ADDRESS, DESTINATION_TRANSPORT_PORT,
+    FLOW_END_MILLISECONDS, FLOW_END_SECONDS, FLOW_END_SYS_UP_TIME, FLOW_START_MILLISECONDS, FLOW_START_SECONDS,
+    FLOW_START_SYS_UP_TIME, IPFIX_VERSION, MESSAGE_HEADER_LENGTH, OCTET_DELTA_COUNT, OCTET_TOTAL_COUNT,
+    OPTIONS_TEMPLATE_SET_ID, PACKET_DELTA_COUNT, PACKET_TOTAL_COUNT, PROTOCOL_IDENTIFIER, REVERSE_ENTERPRISE_NUMBER,
+    SET_HEADER_LENGTH, SOURCE_IPV4_ADDRESS, SOURCE_IPV6_ADDRESS, SOURCE_TRANSPORT_PORT, TCP_CONTROL_BITS,
+    TEMPLATE_SET_ID,
+};
+
+/// NetFlow v5 version number
+pub const NETFLOW_V5_VERSION: u16 = 5;
+/// NetFlow v9 version number
+pub const NETFLOW_V9_VERSION: u16 = 9;
+
+const NETFLOW_V5_HEADER_LENGTH: usize = 24;
+const NETFLOW_V5_RECORD_LENGTH: usize = 48;
+const NETFLOW_V9_HEADER_LENGTH: usize = 20;
+const NETFLOW_V9_TEMPLATE_FLOWSET_ID: u16 = 0;
+const NETFLOW_V9_OPTIONS_TEMPLATE_FLOWSET_ID: u16 = 1;
+/// first set id of the data sets, the lower ones are reserved
+const FIRST_DATA_SET_ID: u16 = 256;
+/// field length of the IPFIX variable-length information elements
+const VARIABLE_LENGTH: u16 = 65535;
+const MAXIMUM_DATAGRAM_LENGTH: usize = 65535;
+
+/// The key of a template: the templates of an exporter are scoped by
+/// its observation domain (IPFIX) or source id (NetFlow v9).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct TemplateKey {
+    exporter: SocketAddr,
+    version: u16,
+    domain_id: u32,
+    template_id: u16,
+}
+
+/// A collector of NetFlow v5, NetFlow v9 and IPFIX messages,
+/// turning the flow records into flow ids and summarized flow information.
+/// The source of a record is its client, so the reverse counters (RFC 5103) are the backward ones.
+/// The templates are cached per exporter, and the data records of unknown templates are skipped.
+#[derive(Debug, Default)]
+pub struct FlowCollector {
+    template_map: HashMap<TemplateKey, Vec<FieldSpecifier>>,
+}
+
+impl FlowCollector {
+    /// Provide a collector without template for now.
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    /// Returns the number of cached templates, of all the exporters.
+    pub fn template_count(&self) -> usize {
+        self.template_map.len()
+    }
+
+    /// Parse a message sent by the given exporter, and returns its flow records.
+    pub fn parse(&mut self, exporter: SocketAddr, data: &[u8]) -> Result<Vec<(FlowId, FlowInformation)>> {
+        let mut reader = Reader::new(data);
+        match reader.read_u16("version")? {
+            NETFLOW_V5_VERSION => parse_netflow_v5(data),
+            NETFLOW_V9_VERSION => self.parse_netflow_v9(exporter, data),
+            IPFIX_VERSION => self.parse_ipfix(exporter, data),
+            version => Err(Error::Parse(format!("unsupported flow export version {}", version))),
+        }
+    }
+
+    /// Receive a message on a bound UDP socket, and returns its flow records.
+    pub fn receive(&mut self, socket: &UdpSocket) -> Result<Vec<(FlowId, FlowInformation)>> {
+        let mut buffer = vec![0; MAXIMUM_DATAGRAM_LENGTH];
+        let (length, exporter) = socket.recv_from(&mut buffer)?;
+        self.parse(exporter, &buffer[..length])
+    }
+
+    fn parse_netflow_v9(&mut self, exporter: SocketAddr, data: &[u8]) -> Result<Vec<(FlowId, FlowInformation)>> {
+        let mut reader = Reader::new(data);
+        let header = reader.read(NETFLOW_V9_HEADER_LENGTH, "NetFlow v9 header")?;
+        let system_uptime = read_unsigned(&header[4..8]);
+        let unix_seconds = read_unsigned(&header[8..12]);
+        let source_id = read_unsigned(&header[16..20]) as u32;
+        let boot_time = Some((unix_seconds * 1000).saturating_sub(system_uptime));
+
+        let mut flow_list = Vec::new();
+        while !reader.is_empty() {
+            let (flowset_id, mut flowset) = reader.read_set("NetFlow v9 flowset")?;
+            match flowset_id {
+                NETFLOW_V9_TEMPLATE_FLOWSET_ID => {
+                    while flowset.remaining() >= 4 {
+                        let template_id = flowset.read_u16("template id")?;
+                        let field_count = flowset.read_u16("field count")?;
+                        let mut field_list = Vec::with_capacity(field_count as usize);
+                        for _ in 0..field_count {
+                            field_list.push(FieldSpecifier {
+                                element_id: flowset.read_u16("field type")?,
+                                length: flowset.read_u16("field length")?,
+                                enterprise_number: 0,
+                            });
+                        }
+                        self.insert_template(exporter, NETFLOW_V9_VERSION, source_id, template_id, field_list);
+                    }
+                }
+                NETFLOW_V9_OPTIONS_TEMPLATE_FLOWSET_ID => {
+                    debug!("NetFlow v9 options template flowset of {} skipped", exporter);
+                }
+                template_id if template_id >= FIRST_DATA_SET_ID => {
+                    let key = TemplateKey {
+                        exporter,
+                        version: NETFLOW_V9_VERSION,
+                        domain_id: source_id,
+                        template_id,
+                    };
+                    self.parse_data_set(key, flowset, boot_time, &mut flow_list)?;
+                }
+                flowset_id => debug!("NetFlow v9 flowset {} of {} skipped", flowset_id, exporter),
+            }
+        }
+        Ok(flow_list)
+    }
+
+    fn parse_ipfix(&mut self, exporter: SocketAddr, data: &[u8]) -> Result<Vec<(FlowId, FlowInformation)>> {
+        let mut reader = Reader::new(data);
+        let header = reader.read(MESSAGE_HEADER_LENGTH, "IPFIX message header")?;
+        let length = read_unsigned(&header[2..4]) as usize;
+        let observation_domain_id = read_unsigned(&header[12..16]) as u32;
+        if length < MESSAGE_HEADER_LENGTH || length > data.len() {
+            return Err(Error::Parse(format!(
+                "IPFIX message length {} doesn't match the {} received bytes",
+                length,
+                data.len()
+            )));
+        }
+        let mut reader = Reader::new(&data[MESSAGE_HEADER_LENGTH..length]);
+
+        let mut flow_list = Vec::new();
+        while !reader.is_empty() {
+            let (set_id, mut set) = reader.read_set("IPFIX set")?;
+            match set_id {
+                TEMPLATE_SET_ID => {
+                    while set.remaining() >= 4 {
+                        let template_id = set.read_u16("template id")?;
+                        let field_count = set.read_u16("field count")?;
+                        let mut field_list = Vec::with_capacity(field_count as usize);
+                        for _ in 0..field_count {
+                            let element_id = set.read_u16("information element id")?;
+                            let length = set.read_u16("field length")?;
+                            let enterprise_number = if element_id & 0x8000 != 0 {
+                                set.read_u32("enterprise number")?
+                            } else {
+                                0
+                            };
+                            field_list.push(FieldSpecifier {
+                                element_id: element_id & 0x7fff,
+                                length,
+                                enterprise_number,
+                            });
+                        }
+                        self.insert_template(exporter, IPFIX_VERSION, observation_domain_id, template_id, field_list);
+                    }
+                }
+                OPTIONS_TEMPLATE_SET_ID => debug!("IPFIX options template set of {} skipped", exporter),
+                template_id if template_id >= FIRST_DATA_SET_ID => {
+                    let key = TemplateKey {
+                        exporter,
+                        version: IPFIX_VERSION,
+                        domain_id: observation_domain_id,
+                        template_id,
+                    };
+                    self.parse_data_set(key, set, None, &mut flow_list)?;
+                }
+                set_id => debug!("IPFIX set {} of {} skipped", set_id, exporter),
+            }
+        }
+        Ok(flow_list)
+    }
+
+    /// Cache a template, or withdraw it if it has no field.
+    fn insert_template(
+        &mut self,
+        exporter: SocketAddr,
+        version: u16,
+        domain_id: u32,
+        template_id: u16,
+        field_list: Vec<FieldSpecifier>,
+    ) {
+        let key = TemplateKey {
+            exporter,
+            version,
+            domain_id,
+            template_id,
+        };
+        if field_list.is_empty() {
+            self.template_map.remove(&key);
+        } else {
+            self.template_map.insert(key, field_list);
+        }
+    }
+
+    /// Decode the records of a data set, until only padding remains.
+    fn parse_data_set(
+        &self,
+        key: TemplateKey,
+        mut set: Reader,
+        boot_time: Option<u64>,
+        flow_list: &mut Vec<(FlowId, FlowInformation)>,
+    ) -> Result<()> {
+        let field_list = match self.template_map.get(&key) {
+            Some(field_list) => field_list,
+            None => {
+                debug!(
+                    "data set of unknown template {} from {} skipped",
+                    key.template_id, key.exporter
+                );
+                return Ok(());
+            }
+        };
+        let minimum_length: usize = field_list
+            .iter()
+            .map(|field| {
+                if field.length == VARIABLE_LENGTH {
+                    1
+                } else {
+                    field.length as usize
+                }
+            })
+            .sum();
+        while minimum_length > 0 && set.remaining() >= minimum_length {
+            if let Some(flow) = decode_record(field_list, &mut set, boot_time)? {
+                flow_list.push(flow);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse a NetFlow v5 message, made of fixed records.
+fn parse_netflow_v5(data: &[u8]) -> Result<Vec<(FlowId, FlowInformation)>> {
+    let mut reader = Reader::new(data);
+    let header = reader.read(NETFLOW_V5_HEADER_LENGTH, "NetFlow v5 header")?;
+    let count = read_unsigned(&header[2..4]);
+    let system_uptime = read_unsigned(&header[4..8]);
+    let export_time = read_unsigned(&header[8..12]) * 1000 + read_unsigned(&header[12..16]) / 1_000_000;
+    let boot_time = export_time.saturating_sub(system_uptime);
+
+    let mut flow_list = Vec::new();
+    for _ in 0..count {
+        let record = reader.read(NETFLOW_V5_RECORD_LENGTH, "NetFlow v5 record")?;
+        let flow_id = FlowId {
+            src: IpAddr::V4(Ipv4Addr::new(record[0], record[1], record[2], record[3])),
+            dst: IpAddr::V4(Ipv4Addr::new(record[4], record[5], record[6], record[7])),
+            src_port: read_unsigned(&record[32..34]) as u16,
+            dst_port: read_unsigned(&record[34..36]) as u16,
+            transport_protocol: record[38],
+        };
+        let mut summary = FlowSummary::new();
+        summary.forward_packet_count = read_unsigned(&record[16..20]);
+        summary.forward_octet_count = read_unsigned(&record[20..24]);
+        summary.start = Duration::from_millis(boot_time + read_unsigned(&record[24..28]));
+        summary.end = Duration::from_millis(boot_time + read_unsigned(&record[28..32]));
+        summary.forward_flag_list = Flag::from_bits(record[37] as u16);
+        flow_list.push((flow_id, summarized_flow_information(&flow_id, summary)));
+    }
+    Ok(flow_list)
+}
+
+/// Decode a data record with its template, and returns its flow unless it has no IP addresses.
+fn decode_record(
+    field_list: &[FieldSpecifier],
+    reader: &mut Reader,
+    boot_time: Option<u64>,
+) -> Result<Option<(FlowId, FlowInformation)>> {
+    let mut src = None;
+    let mut dst = None;
+    let mut src_port = 0;
+    let mut dst_port = 0;
+    let mut transport_protocol = 0;
+    let mut summary = FlowSummary::new();
+
+    for field in field_list {
+        let length = if field.length == VARIABLE_LENGTH {
+            match reader.read_u8("variable length")? {
+                255 => reader.read_u16("variable length")? as usize,
+                length => length as usize,
+            }
+        } else {
+            field.length as usize
+        };
+        let value = reader.read(length, "field value")?;
+        match (field.enterprise_number, field.element_id) {
+            (0, SOURCE_IPV4_ADDRESS) | (0, SOURCE_IPV6_ADDRESS) => src = read_ip_addr(value),
+            (0, DESTINATION_IPV4_ADDRESS) | (0, DESTINATION_IPV6_ADDRESS) => dst = read_ip_addr(value),
+            (0, SOURCE_TRANSPORT_PORT) => src_port = read_unsigned(value) as u16,
+            (0, DESTINATION_TRANSPORT_PORT) => dst_port = read_unsigned(value) as u16,
+            (0, PROTOCOL_IDENTIFIER) => transport_protocol = read_unsigned(value) as u8,
+            (0, PACKET_DELTA_COUNT) | (0, PACKET_TOTAL_COUNT) => summary.forward_packet_count = read_unsigned(value),
+            (0, OCTET_DELTA_COUNT) | (0, OCTET_TOTAL_COUNT) => summary.forward_octet_count = read_unsigned(value),
+            (0, TCP_CONTROL_BITS) => summary.forward_flag_list = Flag::from_bits(read_unsigned(value) as u16),
+            (0, FLOW_START_SECONDS) => summary.start = Duration::from_secs(read_unsigned(value)),
+            (0, FLOW_END_SECONDS) => summary.end = Duration::from_secs(read_unsigned(value)),
+            (0, FLOW_START_MILLISECONDS) => summary.start = Duration::from_millis(read_unsigned(value)),
+            (0, FLOW_END_MILLISECONDS) => summary.end = Duration::from_millis(read_unsigned(value)),
+            (0, FLOW_START_SYS_UP_TIME) => {
+                if let Some(boot_time) = boot_time {
+                    summary.start = Duration::from_millis(boot_time.saturating_add(read_unsigned(value)));
+                }
+            }
+            (0, FLOW_END_SYS_UP_TIME) => {
+                if let Some(boot_time) = boot_time {
+                    summary.end = Duration::from_millis(boot_time.saturating_add(read_unsigned(value)));
+                }
+            }
+            (REVERSE_ENTERPRISE_NUMBER, PACKET_DELTA_COUNT) => summary.backward_packet_count = read_unsigned(value),
+            (REVERSE_ENTERPRISE_NUMBER, OCTET_DELTA_COUNT) => summary.backward_octet_count = read_unsigned(value),
+            (REVERSE_ENTERPRISE_NUMBER, TCP_CONTROL_BITS) => {
+                summary.backward_flag_list = Flag::from_bits(read_unsigned(value) as u16)
+            }
+            _ => {}
+        }
+    }
+
+    match (src, dst) {
+        (Some(src), Some(dst)) if src.is_ipv4() == dst.is_ipv4() => {
+            let flow_id = FlowId {
+                src,
+                dst,
+                src_port,
+                dst_port,
+                transport_protocol,
+            };
+            Ok(Some((flow_id, summarized_flow_information(&flow_id, summary))))
+        }
+        _ => {
+            debug!("flow record without consistent IP addresses skipped");
+            Ok(None)
+        }
+    }
+}
+
+/// Provide the flow information of a flow record, from its source to its destination.
+fn summarized_flow_information(flow_id: &FlowId, summary: FlowSummary) -> FlowInformation {
+    let mut flow_information = FlowInformation::new();
+    flow_information.client = Some(flow_id.source());
+    flow_information.server = Some(flow_id.destination());
+    flow_information.summary = Some(summary);
+    flow_information
+}
+
+fn read_ip_addr(value: &[u8]) -> Option<IpAddr> {
+    match value.len() {
+        4 => Some(IpAddr::V4(Ipv4Addr::new(value[0], value[1], value[2], value[3]))),
+        16 => {
+            let mut octets = [0; 16];
+            octets.copy_from_slice(value);
+            Some(IpAddr::V6(Ipv6Addr::from(octets)))
+        }
+        _ => None,
+    }
+}
+
+/// Read a big-endian unsigned integer, of reduced-size encoding if shorter than 8 bytes.
+fn read_unsigned(value: &[u8]) -> u64 {
+    value.iter().fold(0, |number, byte| number << 8 | *byte as u64)
+}
+
+/// A reader of the message bytes, failing on truncated fields.
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.offset
+    }
+
+    fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    fn read(&mut self, length: usize, field: &str) -> Result<&'a [u8]> {
+        if self.remaining() < length {
+            return Err(Error::Parse(format!(
+                "{} truncated: {} bytes expected, {} available",
+                field,
+                length,
+                self.remaining()
+            )));
+        }
+        let bytes = &self.data[self.offset..self.offset + length];
+        self.offset += length;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self, field: &str) -> Result<u8> {
+        Ok(self.read(1, field)?[0])
+    }
+
+    fn read_u16(&mut self, field: &str) -> Result<u16> {
+        Ok(read_unsigned(self.read(2, field)?) as u16)
+    }
+
+    fn read_u32(&mut self, field: &str) -> Result<u32> {
+        Ok(read_unsigned(self.read(4, field)?) as u32)
+    }
+
+    /// Read a set (or flowset) header, and returns its id with a reader of its content.
+    fn read_set(&mut self, field: &str) -> Result<(u16, Reader<'a>)> {
+        let set_id = self.read_u16(field)?;
+        let length = self.read_u16(field)? as usize;
+        if length < SET_HEADER_LENGTH {
+            return Err(Error::Parse(format!("{} length {} is too short", field, length)));
+        }
+        Ok((set_id, Reader::new(self.read(length - SET_HEADER_LENGTH, field)?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{SocketAddr, UdpSocket};
+    use std::time::Duration;
+
+    use crate::collector::FlowCollector;
+    use crate::error::Error;
+    use crate::flag::Flag;
+    use crate::flow_id::FlowId;
+    use crate::flow_information::FlowInformation;
+    use crate::generator::Generator;
+    use crate::ipfix::IpfixExporter;
+    use crate::packet::Packet;
+
+    fn exporter_address() -> SocketAddr {
+        "192.0.2.1:4739".parse().unwrap()
+    }
+
+    fn packet(millis: u64, flags: &[Flag]) -> Packet {
+        let mut packet = Packet::new();
+        packet.timestamp = Duration::from_millis(millis);
+        packet.length = 60;
+        packet.flag_list = flags.iter().cloned().collect();
+        packet
+    }
+
+    fn tcp_flow() -> (FlowId, FlowInformation) {
+        let mut flow_information = FlowInformation::new();
+        flow_information.forward_packet_list.push(packet(1_000, &[Flag::SYN]));
+        flow_information
+            .backward_packet_list
+            .push(packet(1_010, &[Flag::SYN, Flag::ACK]));
+        flow_information.forward_packet_list.push(packet(1_020, &[Flag::ACK]));
+        (FlowId::new(6, "10.0.0.1", "10.0.0.2", 42254, 443), flow_information)
+    }
+
+    fn ipv6_flow() -> (FlowId, FlowInformation) {
+        let mut flow_information = FlowInformation::new();
+        flow_information.forward_packet_list.push(packet(2_000, &[]));
+        (
+            FlowId::new(17, "2001:db8::1", "2001:db8::2", 5353, 53),
+            flow_information,
+        )
+    }
+
+    fn netflow_v5_message() -> Vec<u8> {
+        let mut message = vec![0, 5, 0, 1];
+        message.extend_from_slice(&10_000u32.to_be_bytes()); // system uptime
+        message.extend_from_slice(&1_600_000_000u32.to_be_bytes()); // unix seconds
+        message.extend_from_slice(&0u32.to_be_bytes()); // unix nanoseconds
+        message.extend_from_slice(&[0; 8]); // sequence, engine and sampling
+        message.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2, 0, 0, 0, 0, 0, 1, 0, 2]);
+        message.extend_from_slice(&3u32.to_be_bytes()); // packets
+        message.extend_from_slice(&180u32.to_be_bytes()); // octets
+        message.extend_from_slice(&4_000u32.to_be_bytes()); // first
+        message.extend_from_slice(&9_000u32.to_be_bytes()); // last
+        message.extend_from_slice(&[0xa5, 0x0e, 0x01, 0xbb, 0, 0x12, 6, 0]);
+        message.extend_from_slice(&[0; 8]);
+        message
+    }
+
+    fn netflow_v9_message() -> Vec<u8> {
+        let mut message = vec![0, 9, 0, 2];
+        message.extend_from_slice(&10_000u32.to_be_bytes()); // system uptime
+        message.extend_from_slice(&1_600_000_000u32.to_be_bytes()); // unix seconds
+        message.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 42]); // sequence and source id
+
+        // template flowset: addresses, ports, protocol, packets (4 bytes), first and last switched
+        message.extend_from_slice(&[0, 0, 0, 40, 1, 0, 0, 8]);
+        for (field_type, length) in &[(8u16, 4u16), (12, 4), (7, 2), (11, 2), (4, 1), (2, 4), (22, 4), (21, 4)] {
+            message.extend_from_slice(&field_type.to_be_bytes());
+            message.extend_from_slice(&length.to_be_bytes());
+        }
+        // data flowset: one record of 25 bytes, padded to 32 bits
+        message.extend_from_slice(&[1, 0, 0, 32]);
+        message.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2, 0, 53, 0x14, 0xe9, 17, 0, 0, 0, 2]);
+        message.extend_from_slice(&4_000u32.to_be_bytes());
+        message.extend_from_slice(&9_000u32.to_be_bytes());
+        message.extend_from_slice(&[0, 0, 0]);
+        message
+    }
+
+    #[test]
+    fn test_new() {
+        let collector = FlowCollector::new();
+        assert_eq!(collector.template_count(), 0);
+    }
+
+    #[test]
+    fn it_can_collect_the_flows_of_an_ipfix_exporter() {
+        let (tcp_flow_id, tcp_flow_information) = tcp_flow();
+        let mut exporter = IpfixExporter::new(3);
+        let message_list = exporter.encode(vec![tcp_flow(), ipv6_flow()], Duration::from_secs(1_600_000_000));
+
+        let mut collector = FlowCollector::new();
+        let flow_list = collector.parse(exporter_address(), &message_list[0]).unwrap();
+        assert_eq!(collector.template_count(), 2);
+        assert_eq!(flow_list.len(), 2);
+
+        let (flow_id, flow_information) = &flow_list[0];
+        assert_eq!(flow_id.to_string(), tcp_flow_id.to_string());
+        assert_eq!(flow_information.client, Some(tcp_flow_id.source()));
+        assert_eq!(flow_information.summary, Some(tcp_flow_information.summarize()));
+        let summary = flow_information.summarize();
+        assert_eq!(summary.start, Duration::from_millis(1_000));
+        assert_eq!(summary.end, Duration::from_millis(1_020));
+        assert_eq!(summary.forward_packet_count, 2);
+        assert_eq!(summary.backward_octet_count, 60);
+        assert_eq!(summary.backward_flag_list.len(), 2);
+        assert_eq!(flow_list[1].0.to_string(), "2001:db8::1-2001:db8::2-5353-53-17");
+    }
+
+    #[test]
+    fn it_can_cache_the_templates_per_exporter() {
+        let mut exporter = IpfixExporter::new(0);
+        exporter.template_interval = 0;
+        let first = exporter.encode(vec![tcp_flow()], Duration::default()).remove(0);
+        let second = exporter.encode(vec![tcp_flow()], Duration::default()).remove(0);
+
+        let mut collector = FlowCollector::new();
+        let other_exporter: SocketAddr = "192.0.2.2:4739".parse().unwrap();
+        assert!(collector.parse(exporter_address(), &second).unwrap().is_empty());
+        assert_eq!(collector.parse(exporter_address(), &first).unwrap().len(), 1);
+        assert_eq!(collector.parse(exporter_address(), &second).unwrap().len(), 1);
+        assert!(collector.parse(other_exporter, &second).unwrap().is_empty());
+    }
+
+    #[test]
+    fn it_can_parse_a_netflow_v5_message() {
+        let mut collector = FlowCollector::new();
+        let flow_list = collector.parse(exporter_address(), &netflow_v5_message()).unwrap();
+        assert_eq!(flow_list.len(), 1);
+
+        let (flow_id, flow_information) = &flow_list[0];
+        assert_eq!(flow_id.to_string(), "10.0.0.1-10.0.0.2-42254-443-6");
+        let summary = flow_information.summarize();
+        assert_eq!(summary.start, Duration::from_millis(1_599_999_994_000));
+        assert_eq!(summary.end, Duration::from_millis(1_599_999_999_000));
+        assert_eq!(summary.forward_packet_count, 3);
+        assert_eq!(summary.forward_octet_count, 180);
+        assert_eq!(
+            summary.forward_flag_list.iter().cloned().collect::<Vec<_>>(),
+            vec![Flag::ACK, Flag::SYN]
+        );
+    }
+
+    #[test]
+    fn it_can_parse_a_netflow_v9_message() {
+        let mut collector = FlowCollector::new();
+        let flow_list = collector.parse(exporter_address(), &netflow_v9_message()).unwrap();
+        assert_eq!(collector.template_count(), 1);
+        assert_eq!(flow_list.len(), 1);
+
+        let (flow_id, flow_information) = &flow_list[0];
+        assert_eq!(flow_id.to_string(), "10.0.0.1-10.0.0.2-53-5353-17");
+        let summary = flow_information.summarize();
+        assert_eq!(summary.start, Duration::from_millis(1_599_999_994_000));
+        assert_eq!(summary.end, Duration::from_millis(1_599_999_999_000));
+        assert_eq!(summary.forward_packet_count, 2);
+    }
+
+    #[test]
+    fn it_can_saturate_an_oversized_first_switched_field() {
+        let mut message = vec![0, 9, 0, 2];
+        message.extend_from_slice(&10_000u32.to_be_bytes()); // system uptime
+        message.extend_from_slice(&1_600_000_000u32.to_be_bytes()); // unix seconds
+        message.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 42]); // sequence and source id
+
+        // template flowset: addresses, then first switched on 8 bytes
+        message.extend_from_slice(&[0, 0, 0, 20, 1, 0, 0, 3, 0, 8, 0, 4, 0, 12, 0, 4, 0, 22, 0, 8]);
+        // data flowset: one record of 16 bytes
+        message.extend_from_slice(&[1, 0, 0, 20, 10, 0, 0, 1, 10, 0, 0, 2]);
+        message.extend_from_slice(&u64::MAX.to_be_bytes());
+
+        let mut collector = FlowCollector::new();
+        let flow_list = collector.parse(exporter_address(), &message).unwrap();
+        assert_eq!(flow_list.len(), 1);
+        assert_eq!(flow_list[0].1.summarize().start, Duration::from_millis(u64::MAX));
+    }
+
+    #[test]
+    fn it_can_receive_flows_on_a_udp_socket() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let exporter_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        exporter_socket.connect(socket.local_addr().unwrap()).unwrap();
+        IpfixExporter::new(0)
+            .export_to_socket(&exporter_socket, vec![tcp_flow(), ipv6_flow()])
+            .unwrap();
+
+        let mut collector = FlowCollector::new();
+        let generator: Generator = collector.receive(&socket).unwrap().into_iter().collect();
+        assert_eq!(generator.len(), 2);
+    }
+
+    #[test]
+    fn it_cannot_parse_an_unsupported_or_truncated_message() {
+        let mut collector = FlowCollector::new();
+        assert!(matches!(
+            collector.parse(exporter_address(), &[0, 7, 0, 0]),
+            Err(Error::Parse(_))
+        ));
+        let message = netflow_v5_message();
+        assert!(matches!(
+            collector.parse(exporter_address(), &message[..message.len() - 1]),
+            Err(Error::Parse(_))
+        ));
+    }
+}
@@ -0,0 +1,858 @@
+use std::collections::BTreeSet;
+use std::convert::TryInto;
+use std::error;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::flag::Flag;
+use crate::flow_id::FlowId;
+use crate::packet::{Packet, TcpOptions};
+
+/// BSD loopback encapsulation
+pub const LINKTYPE_NULL: u32 = 0;
+/// IEEE 802.3 Ethernet
+pub const LINKTYPE_ETHERNET: u32 = 1;
+/// Raw IP, the frame begins with an IPv4 or IPv6 header
+pub const LINKTYPE_RAW: u32 = 101;
+/// Linux "cooked" capture encapsulation
+pub const LINKTYPE_LINUX_SLL: u32 = 113;
+/// Raw IPv4
+pub const LINKTYPE_IPV4: u32 = 228;
+/// Raw IPv6
+pub const LINKTYPE_IPV6: u32 = 229;
+
+/// IPv4 ether type
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+/// IPv6 ether type
+pub const ETHERTYPE_IPV6: u16 = 0x86DD;
+/// IEEE 802.1Q VLAN tag ether type
+pub const ETHERTYPE_VLAN: u16 = 0x8100;
+/// IEEE 802.1ad (QinQ) service VLAN tag ether type
+pub const ETHERTYPE_QINQ: u16 = 0x88A8;
+/// pre-standard QinQ service VLAN tag ether type
+pub const ETHERTYPE_QINQ_LEGACY: u16 = 0x9100;
+
+/// ICMP protocol number
+pub const IPPROTO_ICMP: u8 = 1;
+/// TCP protocol number
+pub const IPPROTO_TCP: u8 = 6;
+/// UDP protocol number
+pub const IPPROTO_UDP: u8 = 17;
+/// ICMPv6 protocol number
+pub const IPPROTO_ICMPV6: u8 = 58;
+/// SCTP protocol number
+pub const IPPROTO_SCTP: u8 = 132;
+
+const IPV6_HOP_BY_HOP_OPTIONS: u8 = 0;
+const IPV6_ROUTING: u8 = 43;
+const IPV6_FRAGMENT: u8 = 44;
+const IPV6_AUTHENTICATION: u8 = 51;
+const IPV6_DESTINATION_OPTIONS: u8 = 60;
+const IPV6_MOBILITY: u8 = 135;
+const IPV6_HOST_IDENTITY: u8 = 139;
+const IPV6_SHIM6: u8 = 140;
+
+const ETHERNET_HEADER_LENGTH: usize = 14;
+const VLAN_TAG_LENGTH: usize = 4;
+const LINUX_SLL_HEADER_LENGTH: usize = 16;
+const NULL_HEADER_LENGTH: usize = 4;
+const IPV4_MINIMUM_HEADER_LENGTH: usize = 20;
+const IPV6_HEADER_LENGTH: usize = 40;
+const TCP_MINIMUM_HEADER_LENGTH: usize = 20;
+const UDP_HEADER_LENGTH: usize = 8;
+const ICMP_MINIMUM_HEADER_LENGTH: usize = 4;
+const SCTP_COMMON_HEADER_LENGTH: usize = 12;
+
+const TCP_OPTION_END: u8 = 0;
+const TCP_OPTION_NO_OPERATION: u8 = 1;
+const TCP_OPTION_MSS: u8 = 2;
+const TCP_OPTION_WINDOW_SCALE: u8 = 3;
+const TCP_OPTION_SACK_PERMITTED: u8 = 4;
+const TCP_OPTION_SACK: u8 = 5;
+const TCP_OPTION_TIMESTAMP: u8 = 8;
+
+/// The reason why a frame can't be decoded.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DecodeError {
+    /// the link layer type isn't supported
+    UnsupportedLinkType(u32),
+    /// the frame doesn't carry an IPv4 or IPv6 datagram (ether type)
+    UnsupportedNetworkProtocol(u16),
+    /// a header is shorter than its expected length
+    Truncated {
+        /// the header name
+        header: &'static str,
+        /// the expected length (number of bytes)
+        expected: usize,
+        /// the available length (number of bytes)
+        actual: usize,
+    },
+    /// a header field has an inconsistent value
+    Malformed {
+        /// the header name
+        header: &'static str,
+        /// the inconsistency
+        reason: &'static str,
+    },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnsupportedLinkType(link_type) => write!(f, "unsupported link type {}", link_type),
+            DecodeError::UnsupportedNetworkProtocol(ether_type) => {
+                write!(f, "unsupported network protocol {:#06x}", ether_type)
+            }
+            DecodeError::Truncated {
+                header,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "truncated {} header: {} bytes expected, {} available",
+                header, expected, actual
+            ),
+            DecodeError::Malformed { header, reason } => write!(f, "malformed {} header: {}", header, reason),
+        }
+    }
+}
+
+impl error::Error for DecodeError {}
+
+/// The layer 3 part of a decoded frame.
+struct Network<'a> {
+    src: IpAddr,
+    dst: IpAddr,
+    transport_protocol: u8,
+    header_length: usize,
+    payload_length: usize,
+    /// fragment header fields, for a fragment of a datagram
+    fragment: Option<FragmentHeader>,
+    /// transport header and payload (as captured)
+    segment: &'a [u8],
+}
+
+impl Network<'_> {
+    /// Returns `true` for a fragment which doesn't start the datagram, without transport header.
+    fn is_later_fragment(&self) -> bool {
+        self.fragment.as_ref().is_some_and(|fragment| fragment.offset != 0)
+    }
+}
+
+/// The fragmentation fields of an IPv4 header or of an IPv6 fragment header.
+struct FragmentHeader {
+    identification: u32,
+    /// position of the fragment data in the datagram payload (number of bytes)
+    offset: usize,
+    more_fragments: bool,
+    /// protocol of the fragmentable part
+    protocol: u8,
+    /// length of the headers repeated in each fragment, before the fragment header for IPv6
+    unfragmentable_length: usize,
+    /// position of the fragment data in the captured datagram
+    data_position: usize,
+    /// position of the IPv6 next header field pointing to the fragment header
+    next_header_position: Option<usize>,
+}
+
+/// A fragment of an IPv4 or IPv6 datagram, to be reassembled.
+pub(crate) struct Fragment<'a> {
+    pub(crate) src: IpAddr,
+    pub(crate) dst: IpAddr,
+    /// protocol of the fragmentable part
+    pub(crate) protocol: u8,
+    pub(crate) identification: u32,
+    /// position of the data in the datagram payload (number of bytes)
+    pub(crate) offset: usize,
+    pub(crate) more_fragments: bool,
+    /// headers repeated in each fragment, up to the IPv6 fragment header
+    pub(crate) header: &'a [u8],
+    /// position of the IPv6 next header field pointing to the fragment header, in the header
+    pub(crate) next_header_position: Option<usize>,
+    pub(crate) data: &'a [u8],
+}
+
+/// Decode a captured frame of the given link type into its flow id and packet.
+/// The supported layers are
+/// Ethernet II with 802.1Q and QinQ tags, Linux cooked capture, BSD loopback and raw IP,
+/// IPv4 with options and IPv6 with its extension header chain,
+/// then TCP, UDP, ICMP, ICMPv6 and SCTP.
+/// For ICMP and ICMPv6, the source port holds the type and the destination port the code.
+/// The packet's timestamp, length and position depend on the capture and stay unset.
+pub fn decode(link_type: u32, data: &[u8]) -> Result<(FlowId, Packet), DecodeError> {
+    decode_with_payload(link_type, data).map(|(flow_id, packet, _)| (flow_id, packet))
+}
+
+/// Decode a captured frame as `decode` does, also returning the TCP or UDP payload (as captured).
+/// The payload is empty for the other transport protocols and for a later fragment.
+pub fn decode_with_payload(link_type: u32, data: &[u8]) -> Result<(FlowId, Packet, &[u8]), DecodeError> {
+    let (network_protocol, _, network) = decode_network(link_type, data)?;
+
+    let mut flow_id = FlowId {
+        src: network.src,
+        src_port: 0,
+        dst: network.dst,
+        dst_port: 0,
+        transport_protocol: network.transport_protocol,
+    };
+    let mut packet = Packet::new();
+    packet.network_protocol = network_protocol;
+    packet.network_header_length = Some(network.header_length);
+    packet.network_payload_length = Some(network.payload_length);
+
+    let mut payload: &[u8] = &[];
+    if !network.is_later_fragment() {
+        payload = decode_transport(network.transport_protocol, network.segment, &mut flow_id, &mut packet)?;
+    }
+    Ok((flow_id, packet, payload))
+}
+
+/// Decode a captured frame up to its fragment, if it's a fragment of an IPv4 or IPv6 datagram.
+pub(crate) fn decode_fragment(link_type: u32, data: &[u8]) -> Result<Option<Fragment<'_>>, DecodeError> {
+    let (_, datagram, network) = decode_network(link_type, data)?;
+    let fragment = match network.fragment {
+        Some(fragment) => fragment,
+        None => return Ok(None),
+    };
+    let end = network.header_length + network.payload_length;
+    if datagram.len() < end {
+        return Err(DecodeError::Truncated {
+            header: "IP fragment",
+            expected: end,
+            actual: datagram.len(),
+        });
+    }
+    Ok(Some(Fragment {
+        src: network.src,
+        dst: network.dst,
+        protocol: fragment.protocol,
+        identification: fragment.identification,
+        offset: fragment.offset,
+        more_fragments: fragment.more_fragments,
+        header: &datagram[..fragment.unfragmentable_length],
+        next_header_position: fragment.next_header_position,
+        data: &datagram[fragment.data_position..end],
+    }))
+}
+
+/// Returns the length of the link layer header of a captured frame, VLAN tags included.
+pub(crate) fn link_header_length(link_type: u32, data: &[u8]) -> Result<usize, DecodeError> {
+    decode_link(link_type, data).map(|(_, datagram)| data.len() - datagram.len())
+}
+
+/// Returns the network protocol (ether type), the datagram carried by the frame and its layer 3 part.
+fn decode_network(link_type: u32, data: &[u8]) -> Result<(u16, &[u8], Network<'_>), DecodeError> {
+    let (network_protocol, datagram) = decode_link(link_type, data)?;
+    let network = match network_protocol {
+        ETHERTYPE_IPV4 => decode_ipv4(datagram)?,
+        ETHERTYPE_IPV6 => decode_ipv6(datagram)?,
+        _ => return Err(DecodeError::UnsupportedNetworkProtocol(network_protocol)),
+    };
+    Ok((network_protocol, datagram, network))
+}
+
+/// Returns the network protocol (ether type) and the datagram carried by the frame.
+fn decode_link(link_type: u32, data: &[u8]) -> Result<(u16, &[u8]), DecodeError> {
+    let (mut ether_type, mut datagram) = match link_type {
+        LINKTYPE_ETHERNET => {
+            let header = header(data, ETHERNET_HEADER_LENGTH, "Ethernet")?;
+            (read_u16(header, 12), &data[ETHERNET_HEADER_LENGTH..])
+        }
+        LINKTYPE_LINUX_SLL => {
+            let header = header(data, LINUX_SLL_HEADER_LENGTH, "Linux cooked capture")?;
+            (read_u16(header, 14), &data[LINUX_SLL_HEADER_LENGTH..])
+        }
+        LINKTYPE_NULL => {
+            header(data, NULL_HEADER_LENGTH, "BSD loopback")?;
+            let datagram = &data[NULL_HEADER_LENGTH..];
+            (ip_version_to_ether_type(datagram)?, datagram)
+        }
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => (ip_version_to_ether_type(data)?, data),
+        _ => return Err(DecodeError::UnsupportedLinkType(link_type)),
+    };
+
+    // 802.1Q and QinQ tags, potentially stacked
+    while ether_type == ETHERTYPE_VLAN || ether_type == ETHERTYPE_QINQ || ether_type == ETHERTYPE_QINQ_LEGACY {
+        let tag = header(datagram, VLAN_TAG_LENGTH, "802.1Q")?;
+        ether_type = read_u16(tag, 2);
+        datagram = &datagram[VLAN_TAG_LENGTH..];
+    }
+    Ok((ether_type, datagram))
+}
+
+fn decode_ipv4(datagram: &[u8]) -> Result<Network<'_>, DecodeError> {
+    let fixed_header = header(datagram, IPV4_MINIMUM_HEADER_LENGTH, "IPv4")?;
+    if fixed_header[0] >> 4 != 4 {
+        return Err(malformed("IPv4", "version isn't 4"));
+    }
+    let header_length = ((fixed_header[0] & 0x0F) as usize) * 4;
+    if header_length < IPV4_MINIMUM_HEADER_LENGTH {
+        return Err(malformed("IPv4", "header length is lower than 20 bytes"));
+    }
+    // the options follow the fixed header
+    header(datagram, header_length, "IPv4")?;
+    let total_length = read_u16(fixed_header, 2) as usize;
+    if total_length < header_length {
+        return Err(malformed("IPv4", "total length is lower than the header length"));
+    }
+    let flags_and_offset = read_u16(fixed_header, 6);
+    let fragment_offset = (flags_and_offset & 0x1FFF) as usize * 8;
+    let more_fragments = flags_and_offset & 0x2000 != 0;
+    let src: [u8; 4] = fixed_header[12..16].try_into().unwrap();
+    let dst: [u8; 4] = fixed_header[16..20].try_into().unwrap();
+
+    Ok(Network {
+        src: IpAddr::V4(Ipv4Addr::from(src)),
+        dst: IpAddr::V4(Ipv4Addr::from(dst)),
+        transport_protocol: fixed_header[9],
+        header_length,
+        payload_length: total_length - header_length,
+        fragment: if fragment_offset != 0 || more_fragments {
+            Some(FragmentHeader {
+                identification: read_u16(fixed_header, 4) as u32,
+                offset: fragment_offset,
+                more_fragments,
+                protocol: fixed_header[9],
+                unfragmentable_length: header_length,
+                data_position: header_length,
+                next_header_position: None,
+            })
+        } else {
+            None
+        },
+        // the link layer may pad the datagram
+        segment: &datagram[header_length..total_length.min(datagram.len())],
+    })
+}
+
+fn decode_ipv6(datagram: &[u8]) -> Result<Network<'_>, DecodeError> {
+    let fixed_header = header(datagram, IPV6_HEADER_LENGTH, "IPv6")?;
+    if fixed_header[0] >> 4 != 6 {
+        return Err(malformed("IPv6", "version isn't 6"));
+    }
+    // a null payload length announces a jumbogram, the captured length is used
+    let payload_length = match read_u16(fixed_header, 4) as usize {
+        0 => datagram.len() - IPV6_HEADER_LENGTH,
+        payload_length => payload_length,
+    };
+    let src: [u8; 16] = fixed_header[8..24].try_into().unwrap();
+    let dst: [u8; 16] = fixed_header[24..40].try_into().unwrap();
+
+    // walk the extension header chain up to the transport header
+    let mut next_header = fixed_header[6];
+    let mut next_header_position = 6;
+    let mut header_length = IPV6_HEADER_LENGTH;
+    let mut fragment = None;
+    let mut later_fragment = false;
+    loop {
+        let extension_length = match next_header {
+            IPV6_HOP_BY_HOP_OPTIONS
+            | IPV6_ROUTING
+            | IPV6_DESTINATION_OPTIONS
+            | IPV6_MOBILITY
+            | IPV6_HOST_IDENTITY
+            | IPV6_SHIM6 => {
+                let extension = header(&datagram[header_length..], 2, "IPv6 extension")?;
+                (extension[1] as usize + 1) * 8
+            }
+            IPV6_AUTHENTICATION => {
+                let extension = header(&datagram[header_length..], 2, "IPv6 authentication")?;
+                (extension[1] as usize + 2) * 4
+            }
+            IPV6_FRAGMENT => {
+                let extension = header(&datagram[header_length..], 8, "IPv6 fragment")?;
+                let offset = (read_u16(extension, 2) >> 3) as usize * 8;
+                later_fragment = offset != 0;
+                fragment = Some(FragmentHeader {
+                    identification: read_u32(extension, 4),
+                    offset,
+                    more_fragments: extension[3] & 0x01 != 0,
+                    protocol: extension[0],
+                    unfragmentable_length: header_length,
+                    data_position: header_length + 8,
+                    next_header_position: Some(next_header_position),
+                });
+                8
+            }
+            _ => break,
+        };
+        let extension = header(&datagram[header_length..], extension_length, "IPv6 extension")?;
+        next_header = extension[0];
+        next_header_position = header_length;
+        header_length += extension_length;
+        if later_fragment {
+            break;
+        }
+    }
+    if header_length - IPV6_HEADER_LENGTH > payload_length {
+        return Err(malformed("IPv6", "extension headers are longer than the payload"));
+    }
+
+    let end = (IPV6_HEADER_LENGTH + payload_length).min(datagram.len());
+    Ok(Network {
+        src: IpAddr::V6(Ipv6Addr::from(src)),
+        dst: IpAddr::V6(Ipv6Addr::from(dst)),
+        transport_protocol: next_header,
+        header_length,
+        payload_length: payload_length - (header_length - IPV6_HEADER_LENGTH),
+        fragment,
+        segment: &datagram[header_length..end],
+    })
+}
+
+/// Decode the transport header into the flow id and the packet, and returns the TCP or UDP payload.
+fn decode_transport<'a>(
+    transport_protocol: u8,
+    segment: &'a [u8],
+    flow_id: &mut FlowId,
+    packet: &mut Packet,
+) -> Result<&'a [u8], DecodeError> {
+    let mut payload: &[u8] = &[];
+    match transport_protocol {
+        IPPROTO_TCP => {
+            let tcp_header = header(segment, TCP_MINIMUM_HEADER_LENGTH, "TCP")?;
+            let data_offset = ((tcp_header[12] >> 4) as usize) * 4;
+            if data_offset < TCP_MINIMUM_HEADER_LENGTH {
+                return Err(malformed("TCP", "data offset is lower than 20 bytes"));
+            }
+            flow_id.src_port = read_u16(tcp_header, 0);
+            flow_id.dst_port = read_u16(tcp_header, 2);
+            packet.sequence_number = Some(read_u32(tcp_header, 4));
+            packet.window = Some(read_u16(tcp_header, 14));
+            packet.flag_list = tcp_flag_list(tcp_header[12], tcp_header[13]);
+            if packet.flag_list.contains(&Flag::ACK) {
+                packet.acknowledgment_number = Some(read_u32(tcp_header, 8));
+            }
+            if packet.flag_list.contains(&Flag::URG) {
+                packet.urgent_pointer = Some(read_u16(tcp_header, 18));
+            }
+            packet.tcp_payload_length = packet
+                .network_payload_length
+                .map(|network_payload_length| network_payload_length.saturating_sub(data_offset));
+            if data_offset > TCP_MINIMUM_HEADER_LENGTH {
+                let option_end = data_offset.min(segment.len());
+                packet.tcp_options = Some(tcp_options(&segment[TCP_MINIMUM_HEADER_LENGTH..option_end]));
+            }
+            payload = segment.get(data_offset..).unwrap_or_default();
+        }
+        IPPROTO_UDP => {
+            let udp_header = header(segment, UDP_HEADER_LENGTH, "UDP")?;
+            flow_id.src_port = read_u16(udp_header, 0);
+            flow_id.dst_port = read_u16(udp_header, 2);
+            payload = &segment[UDP_HEADER_LENGTH..];
+        }
+        IPPROTO_ICMP | IPPROTO_ICMPV6 => {
+            let icmp_header = header(segment, ICMP_MINIMUM_HEADER_LENGTH, "ICMP")?;
+            flow_id.src_port = icmp_header[0] as u16;
+            flow_id.dst_port = icmp_header[1] as u16;
+        }
+        IPPROTO_SCTP => {
+            let sctp_header = header(segment, SCTP_COMMON_HEADER_LENGTH, "SCTP")?;
+            flow_id.src_port = read_u16(sctp_header, 0);
+            flow_id.dst_port = read_u16(sctp_header, 2);
+        }
+        _ => {}
+    }
+    Ok(payload)
+}
+
+/// Convert the TCP header bytes 12 (NS) and 13 (other flags) to a flag list.
+fn tcp_flag_list(byte_12: u8, byte_13: u8) -> BTreeSet<Flag> {
+    Flag::from_bits(u16::from(byte_12 & 0x01) << 8 | u16::from(byte_13))
+}
+
+/// Parse the TCP options, up to the end of option list or a malformed option.
+fn tcp_options(mut data: &[u8]) -> TcpOptions {
+    let mut tcp_options = TcpOptions::new();
+    while let Some(&kind) = data.first() {
+        match kind {
+            TCP_OPTION_END => break,
+            TCP_OPTION_NO_OPERATION => {
+                data = &data[1..];
+                continue;
+            }
+            _ => {}
+        }
+        let length = match data.get(1) {
+            Some(&length) if length >= 2 && length as usize <= data.len() => length as usize,
+            _ => break,
+        };
+        let value = &data[2..length];
+        match (kind, value.len()) {
+            (TCP_OPTION_MSS, 2) => tcp_options.mss = Some(read_u16(value, 0)),
+            (TCP_OPTION_WINDOW_SCALE, 1) => tcp_options.window_scale = Some(value[0]),
+            (TCP_OPTION_SACK_PERMITTED, 0) => tcp_options.sack_permitted = true,
+            (TCP_OPTION_SACK, _) => {
+                tcp_options.sack_block_list = value
+                    .chunks_exact(8)
+                    .map(|block| (read_u32(block, 0), read_u32(block, 4)))
+                    .collect()
+            }
+            (TCP_OPTION_TIMESTAMP, 8) => {
+                tcp_options.timestamp_value = Some(read_u32(value, 0));
+                tcp_options.timestamp_echo_reply = Some(read_u32(value, 4));
+            }
+            _ => {}
+        }
+        data = &data[length..];
+    }
+    tcp_options
+}
+
+fn ip_version_to_ether_type(datagram: &[u8]) -> Result<u16, DecodeError> {
+    match header(datagram, 1, "IP")?[0] >> 4 {
+        4 => Ok(ETHERTYPE_IPV4),
+        6 => Ok(ETHERTYPE_IPV6),
+        _ => Err(malformed("IP", "version is neither 4 nor 6")),
+    }
+}
+
+/// Returns the first `length` bytes of the data, if available.
+fn header<'a>(data: &'a [u8], length: usize, name: &'static str) -> Result<&'a [u8], DecodeError> {
+    data.get(..length).ok_or(DecodeError::Truncated {
+        header: name,
+        expected: length,
+        actual: data.len(),
+    })
+}
+
+fn malformed(header: &'static str, reason: &'static str) -> DecodeError {
+    DecodeError::Malformed { header, reason }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use crate::decoder::{
+        decode, decode_with_payload, tcp_options, DecodeError, ETHERTYPE_IPV4, ETHERTYPE_IPV6, LINKTYPE_ETHERNET,
+        LINKTYPE_LINUX_SLL, LINKTYPE_NULL, LINKTYPE_RAW,
+    };
+    use crate::flag::Flag;
+
+    fn ethernet_header(ether_type: u16) -> Vec<u8> {
+        let mut header = vec![
+            // ethernet: dst, src
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB,
+        ];
+        header.extend_from_slice(&ether_type.to_be_bytes());
+        header
+    }
+
+    fn ipv4_udp_datagram() -> Vec<u8> {
+        let mut datagram = vec![
+            // IPv4: version 4, IHL 5, total length 32, TTL 64, UDP
+            0x45, 0x00, 0x00, 0x20, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00,
+            // source 10.0.0.1, destination 10.0.0.2
+            10, 0, 0, 1, 10, 0, 0, 2,
+        ];
+        // UDP: source port 5353, destination port 53, length 12
+        datagram.extend_from_slice(&[0x14, 0xE9, 0x00, 0x35, 0x00, 0x0C, 0x00, 0x00]);
+        datagram.extend_from_slice(b"ping");
+        datagram
+    }
+
+    fn ethernet_ipv4_udp_frame() -> Vec<u8> {
+        let mut frame = ethernet_header(0x0800);
+        frame.extend(ipv4_udp_datagram());
+        frame
+    }
+
+    fn ipv6_header(payload_length: u16, next_header: u8) -> Vec<u8> {
+        let mut header = vec![0x60, 0x00, 0x00, 0x00];
+        header.extend_from_slice(&payload_length.to_be_bytes());
+        header.extend_from_slice(&[next_header, 0x40]);
+        header.extend_from_slice(&[0x20, 0x01, 0x0D, 0xB8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        header.extend_from_slice(&[0x20, 0x01, 0x0D, 0xB8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+        header
+    }
+
+    fn tcp_syn_header() -> Vec<u8> {
+        // TCP: source port 42254, destination port 443, data offset 6, SYN with ECE and CWR, window 64240,
+        // then a MSS option
+        vec![
+            0xA5, 0x0E, 0x01, 0xBB, 0, 0, 0, 1, 0, 0, 0, 0, 0x60, 0xC2, 0xFA, 0xF0, 0, 0, 0, 0, 0x02, 0x04, 0x05, 0xB4,
+        ]
+    }
+
+    fn raw_ipv6_tcp_frame() -> Vec<u8> {
+        let mut frame = ipv6_header(24, 6);
+        frame.extend(tcp_syn_header());
+        frame
+    }
+
+    #[test]
+    fn it_can_decode_an_ethernet_ipv4_udp_frame() {
+        let (flow_id, packet) = decode(LINKTYPE_ETHERNET, &ethernet_ipv4_udp_frame()).unwrap();
+        assert_eq!(flow_id.src, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(flow_id.dst, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)));
+        assert_eq!(flow_id.src_port, 5353);
+        assert_eq!(flow_id.dst_port, 53);
+        assert_eq!(flow_id.transport_protocol, 17);
+        assert_eq!(packet.network_protocol, ETHERTYPE_IPV4);
+        assert_eq!(packet.network_header_length, Some(20));
+        assert_eq!(packet.network_payload_length, Some(12));
+        assert_eq!(packet.window, None);
+        assert!(packet.flag_list.is_empty());
+    }
+
+    #[test]
+    fn it_can_decode_the_transport_payload() {
+        let frame = ethernet_ipv4_udp_frame();
+        let (_, _, payload) = decode_with_payload(LINKTYPE_ETHERNET, &frame).unwrap();
+        assert_eq!(payload, b"ping");
+        // the TCP options aren't part of the payload
+        let frame = raw_ipv6_tcp_frame();
+        let (_, _, payload) = decode_with_payload(LINKTYPE_RAW, &frame).unwrap();
+        assert!(payload.is_empty());
+    }
+
+    #[test]
+    fn it_can_decode_a_padded_ethernet_frame() {
+        let mut frame = ethernet_ipv4_udp_frame();
+        frame.extend_from_slice(&[0; 14]);
+        let (_, packet) = decode(LINKTYPE_ETHERNET, &frame).unwrap();
+        assert_eq!(packet.network_payload_length, Some(12));
+    }
+
+    #[test]
+    fn it_can_decode_a_qinq_frame() {
+        let mut frame = ethernet_header(0x88A8);
+        // service tag, VLAN 100, then customer tag, VLAN 200, then IPv4
+        frame.extend_from_slice(&[0x00, 0x64, 0x81, 0x00, 0x00, 0xC8, 0x08, 0x00]);
+        frame.extend(ipv4_udp_datagram());
+        let (flow_id, packet) = decode(LINKTYPE_ETHERNET, &frame).unwrap();
+        assert_eq!(flow_id.dst_port, 53);
+        assert_eq!(packet.network_protocol, ETHERTYPE_IPV4);
+    }
+
+    #[test]
+    fn it_can_decode_an_ipv4_datagram_with_options() {
+        let mut frame = ethernet_header(0x0800);
+        // IPv4: IHL 6, total length 36, TTL 64, UDP
+        frame.extend_from_slice(&[0x46, 0x00, 0x00, 0x24, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00]);
+        frame.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        // router alert option
+        frame.extend_from_slice(&[0x94, 0x04, 0x00, 0x00]);
+        frame.extend_from_slice(&[0x14, 0xE9, 0x00, 0x35, 0x00, 0x0C, 0x00, 0x00]);
+        frame.extend_from_slice(b"ping");
+        let (flow_id, packet) = decode(LINKTYPE_ETHERNET, &frame).unwrap();
+        assert_eq!(flow_id.src_port, 5353);
+        assert_eq!(flow_id.dst_port, 53);
+        assert_eq!(packet.network_header_length, Some(24));
+        assert_eq!(packet.network_payload_length, Some(12));
+    }
+
+    #[test]
+    fn it_can_decode_a_later_ipv4_fragment_without_ports() {
+        let mut datagram = ipv4_udp_datagram();
+        // fragment offset 185 (1480 bytes)
+        datagram[6] = 0x00;
+        datagram[7] = 0xB9;
+        let (flow_id, packet) = decode(LINKTYPE_RAW, &datagram).unwrap();
+        assert_eq!(flow_id.transport_protocol, 17);
+        assert_eq!(flow_id.src_port, 0);
+        assert_eq!(flow_id.dst_port, 0);
+        assert_eq!(packet.network_payload_length, Some(12));
+    }
+
+    #[test]
+    fn it_can_decode_a_raw_ipv6_tcp_frame() {
+        let (flow_id, packet) = decode(LINKTYPE_RAW, &raw_ipv6_tcp_frame()).unwrap();
+        assert_eq!(flow_id.src, "2001:db8::1".parse::<IpAddr>().unwrap());
+        assert_eq!(flow_id.dst, "2001:db8::2".parse::<IpAddr>().unwrap());
+        assert_eq!(flow_id.src_port, 42254);
+        assert_eq!(flow_id.dst_port, 443);
+        assert_eq!(flow_id.transport_protocol, 6);
+        assert_eq!(packet.network_protocol, ETHERTYPE_IPV6);
+        assert_eq!(packet.network_header_length, Some(40));
+        assert_eq!(packet.network_payload_length, Some(24));
+        assert_eq!(packet.window, Some(64240));
+        assert_eq!(packet.sequence_number, Some(1));
+        assert_eq!(packet.acknowledgment_number, None);
+        assert_eq!(packet.urgent_pointer, None);
+        assert_eq!(packet.tcp_payload_length, Some(0));
+        assert_eq!(
+            packet.tcp_options.as_ref().and_then(|tcp_options| tcp_options.mss),
+            Some(1460)
+        );
+        let flag_list: BTreeSet<Flag> = vec![Flag::CWR, Flag::ECE, Flag::SYN].into_iter().collect();
+        assert_eq!(packet.flag_list, flag_list);
+    }
+
+    #[test]
+    fn it_can_decode_the_tcp_fields_and_options() {
+        // TCP: ACK and URG, acknowledgment number 42, urgent pointer 7, data offset 13,
+        // then MSS, NOP, window scale, SACK permitted, a SACK block, timestamps and end of option list
+        let mut tcp_header = vec![
+            0xA5, 0x0E, 0x01, 0xBB, 0, 0, 0, 1, 0, 0, 0, 42, 0xD0, 0x30, 0xFA, 0xF0, 0, 0, 0, 7,
+        ];
+        tcp_header.extend_from_slice(&[0x02, 0x04, 0x05, 0xB4, 0x01, 0x03, 0x03, 0x07, 0x04, 0x02]);
+        tcp_header.extend_from_slice(&[0x05, 0x0A, 0, 0, 0, 100, 0, 0, 0, 200]);
+        tcp_header.extend_from_slice(&[0x08, 0x0A, 0, 0, 0x30, 0x39, 0, 0, 0, 0, 0x00, 0x00]);
+        let mut frame = ipv6_header(tcp_header.len() as u16, 6);
+        frame.extend(tcp_header);
+
+        let (_, packet) = decode(LINKTYPE_RAW, &frame).unwrap();
+        assert_eq!(packet.acknowledgment_number, Some(42));
+        assert_eq!(packet.urgent_pointer, Some(7));
+        let tcp_options = packet.tcp_options.unwrap();
+        assert_eq!(tcp_options.mss, Some(1460));
+        assert_eq!(tcp_options.window_scale, Some(7));
+        assert!(tcp_options.sack_permitted);
+        assert_eq!(tcp_options.sack_block_list, vec![(100, 200)]);
+        assert_eq!(tcp_options.timestamp_value, Some(12345));
+        assert_eq!(tcp_options.timestamp_echo_reply, Some(0));
+    }
+
+    #[test]
+    fn it_can_skip_a_malformed_tcp_option() {
+        // a MSS then an option longer than the header
+        let options = tcp_options(&[0x02, 0x04, 0x05, 0xB4, 0x03, 0x09, 0x07, 0x00]);
+        assert_eq!(options.mss, Some(1460));
+        assert_eq!(options.window_scale, None);
+    }
+
+    #[test]
+    fn it_can_decode_an_ipv6_extension_header_chain() {
+        // hop-by-hop options, then destination options, then TCP
+        let mut frame = ipv6_header(8 + 16 + 24, 0);
+        frame.extend_from_slice(&[60, 0, 0x05, 0x02, 0, 0, 1, 0]);
+        frame.extend_from_slice(&[6, 1, 0x01, 0x0C, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        frame.extend(tcp_syn_header());
+        let (flow_id, packet) = decode(LINKTYPE_RAW, &frame).unwrap();
+        assert_eq!(flow_id.transport_protocol, 6);
+        assert_eq!(flow_id.dst_port, 443);
+        assert_eq!(packet.network_header_length, Some(64));
+        assert_eq!(packet.network_payload_length, Some(24));
+    }
+
+    #[test]
+    fn it_can_decode_a_later_ipv6_fragment_without_ports() {
+        let mut frame = ipv6_header(8 + 8, 44);
+        // fragment header: UDP, offset 1480 bytes, identification 1
+        frame.extend_from_slice(&[17, 0, 0x05, 0xC8, 0, 0, 0, 1]);
+        frame.extend_from_slice(&[0xDE, 0xAD, 0xBE, 0xEF, 0xDE, 0xAD, 0xBE, 0xEF]);
+        let (flow_id, packet) = decode(LINKTYPE_RAW, &frame).unwrap();
+        assert_eq!(flow_id.transport_protocol, 17);
+        assert_eq!(flow_id.src_port, 0);
+        assert_eq!(packet.network_header_length, Some(48));
+        assert_eq!(packet.network_payload_length, Some(8));
+    }
+
+    #[test]
+    fn it_can_decode_icmp_type_and_code_as_ports() {
+        let mut datagram = ipv4_udp_datagram();
+        datagram[9] = 1;
+        // destination unreachable, port unreachable
+        datagram[20] = 3;
+        datagram[21] = 3;
+        let (flow_id, _) = decode(LINKTYPE_RAW, &datagram).unwrap();
+        assert_eq!(flow_id.transport_protocol, 1);
+        assert_eq!(flow_id.src_port, 3);
+        assert_eq!(flow_id.dst_port, 3);
+
+        // ICMPv6 echo request
+        let mut frame = ipv6_header(8, 58);
+        frame.extend_from_slice(&[128, 0, 0, 0, 0, 1, 0, 1]);
+        let (flow_id, _) = decode(LINKTYPE_RAW, &frame).unwrap();
+        assert_eq!(flow_id.transport_protocol, 58);
+        assert_eq!(flow_id.src_port, 128);
+        assert_eq!(flow_id.dst_port, 0);
+    }
+
+    #[test]
+    fn it_can_decode_an_sctp_frame_of_a_linux_cooked_capture() {
+        let mut frame = vec![0, 0, 0, 1, 0, 6, 0, 0x11, 0x22, 0x33, 0x44, 0x55, 0, 0, 0x08, 0x00];
+        frame.extend_from_slice(&[0x45, 0x00, 0x00, 0x20, 0x00, 0x00, 0x40, 0x00, 0x40, 132, 0x00, 0x00]);
+        frame.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        // SCTP: source port 2905, destination port 2906, verification tag, checksum
+        frame.extend_from_slice(&[0x0B, 0x59, 0x0B, 0x5A, 0, 0, 0, 1, 0, 0, 0, 0]);
+        let (flow_id, _) = decode(LINKTYPE_LINUX_SLL, &frame).unwrap();
+        assert_eq!(flow_id.transport_protocol, 132);
+        assert_eq!(flow_id.src_port, 2905);
+        assert_eq!(flow_id.dst_port, 2906);
+    }
+
+    #[test]
+    fn it_can_decode_a_bsd_loopback_frame() {
+        let mut frame = vec![2, 0, 0, 0];
+        frame.extend(ipv4_udp_datagram());
+        let (flow_id, _) = decode(LINKTYPE_NULL, &frame).unwrap();
+        assert_eq!(flow_id.dst_port, 53);
+    }
+
+    #[test]
+    fn it_cannot_decode_a_truncated_frame() {
+        let frame = ethernet_ipv4_udp_frame();
+        assert_eq!(
+            decode(LINKTYPE_ETHERNET, &frame[..30]).err(),
+            Some(DecodeError::Truncated {
+                header: "IPv4",
+                expected: 20,
+                actual: 16
+            })
+        );
+        assert_eq!(
+            decode(LINKTYPE_ETHERNET, &frame[..40]).err(),
+            Some(DecodeError::Truncated {
+                header: "UDP",
+                expected: 8,
+                actual: 6
+            })
+        );
+    }
+
+    #[test]
+    fn it_cannot_decode_a_malformed_header() {
+        let mut datagram = ipv4_udp_datagram();
+        datagram[0] = 0x44;
+        assert!(matches!(
+            decode(LINKTYPE_RAW, &datagram),
+            Err(DecodeError::Malformed { header: "IPv4", .. })
+        ));
+
+        let mut frame = raw_ipv6_tcp_frame();
+        frame[40 + 12] = 0x40;
+        assert!(matches!(
+            decode(LINKTYPE_RAW, &frame),
+            Err(DecodeError::Malformed { header: "TCP", .. })
+        ));
+    }
+
+    #[test]
+    fn it_cannot_decode_an_unsupported_protocol() {
+        assert_eq!(
+            decode(147, &ethernet_ipv4_udp_frame()).err(),
+            Some(DecodeError::UnsupportedLinkType(147))
+        );
+        let mut frame = ethernet_header(0x0806);
+        frame.extend_from_slice(&[0; 28]);
+        assert_eq!(
+            decode(LINKTYPE_ETHERNET, &frame).err(),
+            Some(DecodeError::UnsupportedNetworkProtocol(0x0806))
+        );
+    }
+
+    #[test]
+    fn test_decode_error_display() {
+        let error = DecodeError::Truncated {
+            header: "TCP",
+            expected: 20,
+            actual: 3,
+        };
+        assert_eq!(
+            error.to_string(),
+            "truncated TCP header: 20 bytes expected, 3 available"
+        );
+    }
+}
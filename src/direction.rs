@@ -0,0 +1,127 @@
+use std::fmt;
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::flag::Flag;
+use crate::flow_id::FlowId;
+use crate::packet::Packet;
+
+/// An endpoint of a flow: an IP address and a port (0 if not relevant for the protocol).
+#[derive(Clone, Copy, Debug, Eq, Hash, Deserialize, PartialEq, Serialize)]
+pub struct Endpoint {
+    /// IP address
+    pub ip: IpAddr,
+    /// port
+    pub port: u16,
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.ip {
+            IpAddr::V4(ip) => write!(f, "{}:{}", ip, self.port),
+            IpAddr::V6(ip) => write!(f, "[{}]:{}", ip, self.port),
+        }
+    }
+}
+
+/// The policy choosing the client of a flow, from its first packet.
+/// The packets sent by the client are the forward ones,
+/// the packets sent by the server the backward ones.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Deserialize, PartialEq, Serialize)]
+pub enum DirectionPolicy {
+    /// the source of the first packet is the client
+    #[default]
+    FirstPacket,
+    /// the sender of a SYN, or the receiver of a SYN-ACK, is the client,
+    /// the source of the first packet otherwise
+    SynSender,
+    /// the endpoint with the lower port is the server,
+    /// the source of the first packet is the client on equal ports
+    LowerPortServer,
+}
+
+impl DirectionPolicy {
+    /// Returns the client and the server of a flow, given its first packet.
+    pub fn resolve(self, flow_id: &FlowId, packet: &Packet) -> (Endpoint, Endpoint) {
+        let source = flow_id.source();
+        let destination = flow_id.destination();
+        let source_is_client = match self {
+            DirectionPolicy::FirstPacket => true,
+            DirectionPolicy::SynSender => {
+                !(packet.flag_list.contains(&Flag::SYN) && packet.flag_list.contains(&Flag::ACK))
+            }
+            DirectionPolicy::LowerPortServer => flow_id.src_port >= flow_id.dst_port,
+        };
+        if source_is_client {
+            (source, destination)
+        } else {
+            (destination, source)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::direction::{DirectionPolicy, Endpoint};
+    use crate::flag::Flag;
+    use crate::flow_id::FlowId;
+    use crate::packet::Packet;
+
+    fn syn_ack_packet() -> Packet {
+        let mut packet = Packet::new();
+        packet.flag_list.insert(Flag::SYN);
+        packet.flag_list.insert(Flag::ACK);
+        packet
+    }
+
+    fn server_flow_id() -> FlowId {
+        FlowId::new(6, "10.0.0.2", "10.0.0.1", 443, 42254)
+    }
+
+    #[test]
+    fn test_default() {
+        assert_eq!(DirectionPolicy::default(), DirectionPolicy::FirstPacket);
+    }
+
+    #[test]
+    fn test_display() {
+        let flow_id = FlowId::new(6, "2001:db8::1", "2001:db8::2", 42254, 443);
+        assert_eq!(flow_id.source().to_string(), "[2001:db8::1]:42254");
+        assert_eq!(server_flow_id().destination().to_string(), "10.0.0.1:42254");
+    }
+
+    #[test]
+    fn it_can_resolve_the_first_packet_source_as_client() {
+        let (client, server) = DirectionPolicy::FirstPacket.resolve(&server_flow_id(), &syn_ack_packet());
+        assert_eq!(client.port, 443);
+        assert_eq!(server.port, 42254);
+    }
+
+    #[test]
+    fn it_can_resolve_the_syn_sender_as_client() {
+        let (client, server) = DirectionPolicy::SynSender.resolve(&server_flow_id(), &syn_ack_packet());
+        assert_eq!(
+            client,
+            Endpoint {
+                ip: "10.0.0.1".parse().unwrap(),
+                port: 42254
+            }
+        );
+        assert_eq!(server.port, 443);
+
+        let (client, _) = DirectionPolicy::SynSender.resolve(&server_flow_id(), &Packet::new());
+        assert_eq!(client.port, 443);
+    }
+
+    #[test]
+    fn it_can_resolve_the_lower_port_as_server() {
+        let (client, server) = DirectionPolicy::LowerPortServer.resolve(&server_flow_id(), &Packet::new());
+        assert_eq!(client.port, 42254);
+        assert_eq!(server.port, 443);
+
+        let flow_id = FlowId::new(17, "10.0.0.2", "10.0.0.1", 5353, 5353);
+        let (client, _) = DirectionPolicy::LowerPortServer.resolve(&flow_id, &Packet::new());
+        assert_eq!(client.ip, flow_id.src);
+    }
+}
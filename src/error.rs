@@ -0,0 +1,97 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::net::AddrParseError;
+
+use crate::decoder::DecodeError;
+use crate::tls::TlsError;
+
+/// The crate error, returned by the fallible functions.
+#[derive(Debug)]
+pub enum Error {
+    /// a file or a socket can't be read or written
+    Io(io::Error),
+    /// an input, such as an IP address or a frame, can't be parsed
+    Parse(String),
+    /// a value can't be serialized to JSON, or deserialized from JSON
+    Serialization(serde_json::Error),
+    /// a value is parsed but inconsistent
+    Validation(String),
+}
+
+/// A result with the crate error.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "I/O error: {}", error),
+            Error::Parse(message) => write!(f, "parse error: {}", message),
+            Error::Serialization(error) => write!(f, "serialization error: {}", error),
+            Error::Validation(message) => write!(f, "validation error: {}", message),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            Error::Serialization(error) => Some(error),
+            Error::Parse(_) | Error::Validation(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Serialization(error)
+    }
+}
+
+impl From<AddrParseError> for Error {
+    fn from(error: AddrParseError) -> Self {
+        Error::Parse(error.to_string())
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(error: DecodeError) -> Self {
+        Error::Parse(error.to_string())
+    }
+}
+
+impl From<TlsError> for Error {
+    fn from(error: TlsError) -> Self {
+        Error::Parse(error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as _;
+    use std::io::{self, ErrorKind};
+
+    use crate::decoder::DecodeError;
+    use crate::error::Error;
+
+    #[test]
+    fn test_display() {
+        let error = Error::from(io::Error::new(ErrorKind::NotFound, "no such file"));
+        assert_eq!(error.to_string(), "I/O error: no such file");
+        assert!(error.source().is_some());
+
+        let error = Error::from(DecodeError::UnsupportedLinkType(42));
+        assert_eq!(error.to_string(), "parse error: unsupported link type 42");
+        assert!(error.source().is_none());
+
+        let error = Error::Validation("mixed IP versions".to_string());
+        assert_eq!(error.to_string(), "validation error: mixed IP versions");
+    }
+}
@@ -0,0 +1,852 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::decoder::{IPPROTO_TCP, IPPROTO_UDP};
+use crate::error::Result;
+use crate::flag::Flag;
+use crate::flow_id::FlowId;
+use crate::flow_information::FlowInformation;
+use crate::generator::Generator;
+use crate::packet::Packet;
+
+/// length of a TCP header without options
+const TCP_MINIMUM_HEADER_LENGTH: usize = 20;
+/// length of the UDP header
+const UDP_HEADER_LENGTH: usize = 8;
+/// maximum gap between two packets of a bulk
+const BULK_GAP: Duration = Duration::from_secs(1);
+/// minimum number of packets of a bulk
+const BULK_PACKET_COUNT: usize = 4;
+/// minimum gap between two subflows
+const SUBFLOW_GAP: Duration = Duration::from_secs(1);
+/// minimum gap between two active periods
+const ACTIVITY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The CICFlowMeter CSV column names, in order, without the trailing "Label" column.
+pub const COLUMN_LIST: [&str; 83] = [
+    "Flow ID",
+    "Src IP",
+    "Src Port",
+    "Dst IP",
+    "Dst Port",
+    "Protocol",
+    "Timestamp",
+    "Flow Duration",
+    "Total Fwd Packet",
+    "Total Bwd packets",
+    "Total Length of Fwd Packet",
+    "Total Length of Bwd Packet",
+    "Fwd Packet Length Max",
+    "Fwd Packet Length Min",
+    "Fwd Packet Length Mean",
+    "Fwd Packet Length Std",
+    "Bwd Packet Length Max",
+    "Bwd Packet Length Min",
+    "Bwd Packet Length Mean",
+    "Bwd Packet Length Std",
+    "Flow Bytes/s",
+    "Flow Packets/s",
+    "Flow IAT Mean",
+    "Flow IAT Std",
+    "Flow IAT Max",
+    "Flow IAT Min",
+    "Fwd IAT Total",
+    "Fwd IAT Mean",
+    "Fwd IAT Std",
+    "Fwd IAT Max",
+    "Fwd IAT Min",
+    "Bwd IAT Total",
+    "Bwd IAT Mean",
+    "Bwd IAT Std",
+    "Bwd IAT Max",
+    "Bwd IAT Min",
+    "Fwd PSH Flags",
+    "Bwd PSH Flags",
+    "Fwd URG Flags",
+    "Bwd URG Flags",
+    "Fwd Header Length",
+    "Bwd Header Length",
+    "Fwd Packets/s",
+    "Bwd Packets/s",
+    "Packet Length Min",
+    "Packet Length Max",
+    "Packet Length Mean",
+    "Packet Length Std",
+    "Packet Length Variance",
+    "FIN Flag Count",
+    "SYN Flag Count",
+    "RST Flag Count",
+    "PSH Flag Count",
+    "ACK Flag Count",
+    "URG Flag Count",
+    "CWR Flag Count",
+    "ECE Flag Count",
+    "Down/Up Ratio",
+    "Average Packet Size",
+    "Fwd Segment Size Avg",
+    "Bwd Segment Size Avg",
+    "Fwd Bytes/Bulk Avg",
+    "Fwd Packet/Bulk Avg",
+    "Fwd Bulk Rate Avg",
+    "Bwd Bytes/Bulk Avg",
+    "Bwd Packet/Bulk Avg",
+    "Bwd Bulk Rate Avg",
+    "Subflow Fwd Packets",
+    "Subflow Fwd Bytes",
+    "Subflow Bwd Packets",
+    "Subflow Bwd Bytes",
+    "FWD Init Win Bytes",
+    "Bwd Init Win Bytes",
+    "Fwd Act Data Pkts",
+    "Fwd Seg Size Min",
+    "Active Mean",
+    "Active Std",
+    "Active Max",
+    "Active Min",
+    "Idle Mean",
+    "Idle Std",
+    "Idle Max",
+    "Idle Min",
+];
+
+/// The summary statistics of a list of values.
+/// The standard deviation is the sample one, as computed by CICFlowMeter.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Statistic {
+    /// sum of the values
+    pub total: f64,
+    /// minimum value, 0 without value
+    pub min: f64,
+    /// maximum value, 0 without value
+    pub max: f64,
+    /// mean value, 0 without value
+    pub mean: f64,
+    /// sample standard deviation, 0 with less than 2 values
+    pub std: f64,
+}
+
+impl Statistic {
+    /// Compute the statistics of a list of values.
+    pub fn new(value_list: &[f64]) -> Self {
+        if value_list.is_empty() {
+            return Self::default();
+        }
+        let count = value_list.len() as f64;
+        let total: f64 = value_list.iter().sum();
+        let mean = total / count;
+        let std = if value_list.len() > 1 {
+            (value_list.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / (count - 1.0)).sqrt()
+        } else {
+            0.0
+        };
+        Self {
+            total,
+            min: value_list.iter().cloned().fold(f64::INFINITY, f64::min),
+            max: value_list.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+            mean,
+            std,
+        }
+    }
+}
+
+/// The averages of the bulks of a direction.
+/// A bulk is a sequence of at least 4 packets with a payload in the same direction,
+/// less than 1 second apart and not interrupted by a packet with a payload in the other direction.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Bulk {
+    /// average number of bytes by bulk
+    pub bytes_per_bulk: f64,
+    /// average number of packets by bulk
+    pub packets_per_bulk: f64,
+    /// average number of bytes per second during the bulks
+    pub rate: f64,
+}
+
+/// The statistical features of a flow, as computed by CICFlowMeter.
+/// The forward direction is the one of the client.
+/// The length of a packet is its TCP or UDP payload length, and its header length the TCP or UDP header one,
+/// the layer 3 payload and no header for the other transport protocols.
+/// The times are in microseconds, the rates per second.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct FlowFeature {
+    /// flow id, from the client to the server
+    pub flow_id: FlowId,
+    /// timestamp of the first packet
+    pub timestamp: Duration,
+    /// duration between the first and the last packet
+    pub duration: f64,
+    /// number of forward packets
+    pub fwd_packet_count: usize,
+    /// number of backward packets
+    pub bwd_packet_count: usize,
+    /// forward packet lengths
+    pub fwd_length: Statistic,
+    /// backward packet lengths
+    pub bwd_length: Statistic,
+    /// packet lengths of both directions
+    pub length: Statistic,
+    /// bytes per second
+    pub bytes_per_second: f64,
+    /// packets per second
+    pub packets_per_second: f64,
+    /// forward packets per second
+    pub fwd_packets_per_second: f64,
+    /// backward packets per second
+    pub bwd_packets_per_second: f64,
+    /// inter-arrival times of both directions
+    pub iat: Statistic,
+    /// forward inter-arrival times
+    pub fwd_iat: Statistic,
+    /// backward inter-arrival times
+    pub bwd_iat: Statistic,
+    /// number of forward packets with the PSH flag
+    pub fwd_psh_count: usize,
+    /// number of backward packets with the PSH flag
+    pub bwd_psh_count: usize,
+    /// number of forward packets with the URG flag
+    pub fwd_urg_count: usize,
+    /// number of backward packets with the URG flag
+    pub bwd_urg_count: usize,
+    /// sum of the forward header lengths
+    pub fwd_header_length: u64,
+    /// sum of the backward header lengths
+    pub bwd_header_length: u64,
+    /// number of packets with the FIN flag
+    pub fin_count: usize,
+    /// number of packets with the SYN flag
+    pub syn_count: usize,
+    /// number of packets with the RST flag
+    pub rst_count: usize,
+    /// number of packets with the PSH flag
+    pub psh_count: usize,
+    /// number of packets with the ACK flag
+    pub ack_count: usize,
+    /// number of packets with the URG flag
+    pub urg_count: usize,
+    /// number of packets with the CWR flag
+    pub cwr_count: usize,
+    /// number of packets with the ECE flag
+    pub ece_count: usize,
+    /// number of backward packets by forward packet
+    pub down_up_ratio: f64,
+    /// forward bulks
+    pub fwd_bulk: Bulk,
+    /// backward bulks
+    pub bwd_bulk: Bulk,
+    /// number of subflows, separated by more than 1 second without packet
+    pub subflow_count: usize,
+    /// window of the first forward packet, -1 if unknown
+    pub fwd_init_window: i32,
+    /// window of the first backward packet, -1 if unknown
+    pub bwd_init_window: i32,
+    /// number of forward packets with a payload
+    pub fwd_act_data_count: usize,
+    /// minimum forward header length
+    pub fwd_seg_size_min: u64,
+    /// active periods, separated by more than 5 seconds without packet
+    pub active: Statistic,
+    /// idle periods, longer than 5 seconds without packet
+    pub idle: Statistic,
+}
+
+impl FlowFeature {
+    /// Compute the features of a flow.
+    pub fn new(flow_id: &FlowId, flow_information: &FlowInformation) -> Self {
+        let forward_list = &flow_information.forward_packet_list;
+        let backward_list = &flow_information.backward_packet_list;
+        let packet_list = flow_information.packet_sequence();
+        let transport_protocol = flow_id.transport_protocol;
+        let header_length = |packet: &Packet| header_length(packet, transport_protocol);
+        let payload_length = |packet: &Packet| payload_length(packet, transport_protocol);
+
+        let mut feature = FlowFeature {
+            flow_id: flow_information.oriented_flow_id(flow_id),
+            fwd_packet_count: forward_list.len(),
+            bwd_packet_count: backward_list.len(),
+            fwd_length: Statistic::new(&length_list(forward_list.iter(), transport_protocol)),
+            bwd_length: Statistic::new(&length_list(backward_list.iter(), transport_protocol)),
+            length: Statistic::new(&length_list(
+                packet_list.iter().map(|(packet, _)| *packet),
+                transport_protocol,
+            )),
+            iat: Statistic::new(&iat_list(packet_list.iter().map(|(packet, _)| *packet))),
+            fwd_iat: Statistic::new(&iat_list(forward_list.iter())),
+            bwd_iat: Statistic::new(&iat_list(backward_list.iter())),
+            fwd_psh_count: flag_count(forward_list.iter(), Flag::PSH),
+            bwd_psh_count: flag_count(backward_list.iter(), Flag::PSH),
+            fwd_urg_count: flag_count(forward_list.iter(), Flag::URG),
+            bwd_urg_count: flag_count(backward_list.iter(), Flag::URG),
+            fwd_header_length: forward_list.iter().map(header_length).sum(),
+            bwd_header_length: backward_list.iter().map(header_length).sum(),
+            fwd_bulk: bulk(&packet_list, true, transport_protocol),
+            bwd_bulk: bulk(&packet_list, false, transport_protocol),
+            fwd_init_window: init_window(forward_list),
+            bwd_init_window: init_window(backward_list),
+            fwd_act_data_count: forward_list.iter().filter(|packet| payload_length(packet) > 0).count(),
+            fwd_seg_size_min: forward_list.iter().map(header_length).min().unwrap_or_default(),
+            ..Default::default()
+        };
+
+        let packet_iter = || packet_list.iter().map(|(packet, _)| *packet);
+        feature.fin_count = flag_count(packet_iter(), Flag::FIN);
+        feature.syn_count = flag_count(packet_iter(), Flag::SYN);
+        feature.rst_count = flag_count(packet_iter(), Flag::RST);
+        feature.psh_count = flag_count(packet_iter(), Flag::PSH);
+        feature.ack_count = flag_count(packet_iter(), Flag::ACK);
+        feature.urg_count = flag_count(packet_iter(), Flag::URG);
+        feature.cwr_count = flag_count(packet_iter(), Flag::CWR);
+        feature.ece_count = flag_count(packet_iter(), Flag::ECE);
+
+        if let (Some((first, _)), Some((last, _))) = (packet_list.first(), packet_list.last()) {
+            feature.timestamp = first.timestamp;
+            feature.duration = microseconds(last.timestamp.saturating_sub(first.timestamp));
+            feature.subflow_count = 1 + gap_count(packet_iter(), SUBFLOW_GAP);
+            let (active_list, idle_list) = activity_list(packet_iter());
+            feature.active = Statistic::new(&active_list);
+            feature.idle = Statistic::new(&idle_list);
+        }
+        if feature.duration > 0.0 {
+            let seconds = feature.duration / 1_000_000.0;
+            feature.bytes_per_second = feature.length.total / seconds;
+            feature.packets_per_second = packet_list.len() as f64 / seconds;
+            feature.fwd_packets_per_second = feature.fwd_packet_count as f64 / seconds;
+            feature.bwd_packets_per_second = feature.bwd_packet_count as f64 / seconds;
+        }
+        if feature.fwd_packet_count > 0 {
+            feature.down_up_ratio = feature.bwd_packet_count as f64 / feature.fwd_packet_count as f64;
+        }
+        feature
+    }
+
+    /// Returns the values of the features, in the order of the column list.
+    pub fn to_record(&self) -> Vec<String> {
+        let flow_id = &self.flow_id;
+        let subflow_count = self.subflow_count.max(1) as f64;
+        let packet_count = self.fwd_packet_count + self.bwd_packet_count;
+        let average_packet_size = if packet_count > 0 {
+            self.length.total / packet_count as f64
+        } else {
+            0.0
+        };
+        let mut record = vec![
+            flow_id.to_string(),
+            flow_id.src.to_string(),
+            flow_id.src_port.to_string(),
+            flow_id.dst.to_string(),
+            flow_id.dst_port.to_string(),
+            flow_id.transport_protocol.to_string(),
+            format_timestamp(self.timestamp),
+            self.duration.to_string(),
+            self.fwd_packet_count.to_string(),
+            self.bwd_packet_count.to_string(),
+            self.fwd_length.total.to_string(),
+            self.bwd_length.total.to_string(),
+        ];
+        for statistic in [&self.fwd_length, &self.bwd_length] {
+            record.extend(
+                [statistic.max, statistic.min, statistic.mean, statistic.std]
+                    .iter()
+                    .map(f64::to_string),
+            );
+        }
+        record.push(self.bytes_per_second.to_string());
+        record.push(self.packets_per_second.to_string());
+        record.extend(
+            [self.iat.mean, self.iat.std, self.iat.max, self.iat.min]
+                .iter()
+                .map(f64::to_string),
+        );
+        for statistic in [&self.fwd_iat, &self.bwd_iat] {
+            record.extend(
+                [
+                    statistic.total,
+                    statistic.mean,
+                    statistic.std,
+                    statistic.max,
+                    statistic.min,
+                ]
+                .iter()
+                .map(f64::to_string),
+            );
+        }
+        record.extend(
+            [
+                self.fwd_psh_count,
+                self.bwd_psh_count,
+                self.fwd_urg_count,
+                self.bwd_urg_count,
+            ]
+            .iter()
+            .map(usize::to_string),
+        );
+        record.push(self.fwd_header_length.to_string());
+        record.push(self.bwd_header_length.to_string());
+        record.push(self.fwd_packets_per_second.to_string());
+        record.push(self.bwd_packets_per_second.to_string());
+        record.extend(
+            [
+                self.length.min,
+                self.length.max,
+                self.length.mean,
+                self.length.std,
+                self.length.std * self.length.std,
+            ]
+            .iter()
+            .map(f64::to_string),
+        );
+        record.extend(
+            [
+                self.fin_count,
+                self.syn_count,
+                self.rst_count,
+                self.psh_count,
+                self.ack_count,
+                self.urg_count,
+                self.cwr_count,
+                self.ece_count,
+            ]
+            .iter()
+            .map(usize::to_string),
+        );
+        record.push(self.down_up_ratio.to_string());
+        record.push(average_packet_size.to_string());
+        record.push(self.fwd_length.mean.to_string());
+        record.push(self.bwd_length.mean.to_string());
+        for bulk in [&self.fwd_bulk, &self.bwd_bulk] {
+            record.extend(
+                [bulk.bytes_per_bulk, bulk.packets_per_bulk, bulk.rate]
+                    .iter()
+                    .map(f64::to_string),
+            );
+        }
+        record.extend(
+            [
+                self.fwd_packet_count as f64 / subflow_count,
+                self.fwd_length.total / subflow_count,
+                self.bwd_packet_count as f64 / subflow_count,
+                self.bwd_length.total / subflow_count,
+            ]
+            .iter()
+            .map(f64::to_string),
+        );
+        record.push(self.fwd_init_window.to_string());
+        record.push(self.bwd_init_window.to_string());
+        record.push(self.fwd_act_data_count.to_string());
+        record.push(self.fwd_seg_size_min.to_string());
+        for statistic in [&self.active, &self.idle] {
+            record.extend(
+                [statistic.mean, statistic.std, statistic.max, statistic.min]
+                    .iter()
+                    .map(f64::to_string),
+            );
+        }
+        record
+    }
+}
+
+/// Write the features of the flows as CSV, with the CICFlowMeter header.
+pub fn write_csv<'a, W, I>(writer: &mut W, flow_iter: I) -> io::Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = (&'a FlowId, &'a FlowInformation)>,
+{
+    writeln!(writer, "{}", COLUMN_LIST.join(","))?;
+    for (flow_id, flow_information) in flow_iter {
+        writeln!(
+            writer,
+            "{}",
+            FlowFeature::new(flow_id, flow_information).to_record().join(",")
+        )?;
+    }
+    Ok(())
+}
+
+/// Write the features of the flows of a generator to a CSV file.
+/// Panics if the file can't be written, see `try_write_to_csv_file`.
+pub fn write_to_csv_file<P: AsRef<Path>>(generator: &Generator, path: P) {
+    try_write_to_csv_file(generator, path).unwrap()
+}
+
+/// Write the features of the flows of a generator to a CSV file,
+/// or returns an error if the file can't be written.
+pub fn try_write_to_csv_file<P: AsRef<Path>>(generator: &Generator, path: P) -> Result<()> {
+    // open the file with buffer.
+    let file = File::create(path)?;
+    let mut writer = BufWriter::new(file);
+
+    write_csv(&mut writer, generator.sorted())?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn microseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1_000_000.0
+}
+
+/// Returns the layer 3 payload length of a packet,
+/// or its length without the layer 3 header if unknown, such as in a JSON dump.
+fn network_payload_length(packet: &Packet) -> usize {
+    packet
+        .network_payload_length
+        .unwrap_or_else(|| (packet.length as usize).saturating_sub(packet.network_header_length.unwrap_or_default()))
+}
+
+/// Returns the TCP or UDP header length of a packet, as CICFlowMeter does.
+/// Without TCP payload length, the TCP header is assumed to have no option.
+fn header_length(packet: &Packet, transport_protocol: u8) -> u64 {
+    let network_payload_length = network_payload_length(packet);
+    match transport_protocol {
+        IPPROTO_TCP => match packet.tcp_payload_length {
+            Some(tcp_payload_length) => network_payload_length.saturating_sub(tcp_payload_length) as u64,
+            None => network_payload_length.min(TCP_MINIMUM_HEADER_LENGTH) as u64,
+        },
+        IPPROTO_UDP => network_payload_length.min(UDP_HEADER_LENGTH) as u64,
+        _ => 0,
+    }
+}
+
+/// Returns the TCP or UDP payload length of a packet, as CICFlowMeter does.
+fn payload_length(packet: &Packet, transport_protocol: u8) -> u64 {
+    network_payload_length(packet) as u64 - header_length(packet, transport_protocol)
+}
+
+fn length_list<'a>(packet_iter: impl Iterator<Item = &'a Packet>, transport_protocol: u8) -> Vec<f64> {
+    packet_iter
+        .map(|packet| payload_length(packet, transport_protocol) as f64)
+        .collect()
+}
+
+fn iat_list<'a>(packet_iter: impl Iterator<Item = &'a Packet>) -> Vec<f64> {
+    let timestamp_list: Vec<Duration> = packet_iter.map(|packet| packet.timestamp).collect();
+    timestamp_list
+        .windows(2)
+        .map(|pair| microseconds(pair[1].saturating_sub(pair[0])))
+        .collect()
+}
+
+/// Returns the number of gaps longer than the given one between time ordered packets.
+fn gap_count<'a>(packet_iter: impl Iterator<Item = &'a Packet>, gap: Duration) -> usize {
+    let timestamp_list: Vec<Duration> = packet_iter.map(|packet| packet.timestamp).collect();
+    timestamp_list
+        .windows(2)
+        .filter(|pair| pair[1].saturating_sub(pair[0]) > gap)
+        .count()
+}
+
+fn flag_count<'a>(packet_iter: impl Iterator<Item = &'a Packet>, flag: Flag) -> usize {
+    packet_iter.filter(|packet| packet.flag_list.contains(&flag)).count()
+}
+
+fn init_window(packet_list: &[Packet]) -> i32 {
+    packet_list
+        .first()
+        .and_then(|packet| packet.window)
+        .map_or(-1, i32::from)
+}
+
+/// Returns the active and the idle periods of time ordered packets.
+fn activity_list<'a>(mut packet_iter: impl Iterator<Item = &'a Packet>) -> (Vec<f64>, Vec<f64>) {
+    let mut active_list = Vec::new();
+    let mut idle_list = Vec::new();
+    let first_timestamp = match packet_iter.next() {
+        Some(packet) => packet.timestamp,
+        None => return (active_list, idle_list),
+    };
+    let mut active_start = first_timestamp;
+    let mut last_timestamp = first_timestamp;
+    for packet in packet_iter {
+        let gap = packet.timestamp.saturating_sub(last_timestamp);
+        if gap > ACTIVITY_TIMEOUT {
+            if last_timestamp > active_start {
+                active_list.push(microseconds(last_timestamp - active_start));
+            }
+            idle_list.push(microseconds(gap));
+            active_start = packet.timestamp;
+        }
+        last_timestamp = last_timestamp.max(packet.timestamp);
+    }
+    if last_timestamp > active_start {
+        active_list.push(microseconds(last_timestamp - active_start));
+    }
+    (active_list, idle_list)
+}
+
+/// Returns the bulk averages of a direction, given the time ordered packets of both directions.
+fn bulk(packet_list: &[(&Packet, bool)], forward: bool, transport_protocol: u8) -> Bulk {
+    let mut bulk_count = 0;
+    let mut packet_count = 0;
+    let mut byte_count = 0.0;
+    let mut duration = Duration::default();
+
+    // the bulk in progress: first and last timestamps, number of packets and bytes
+    let mut current: Option<(Duration, Duration, usize, f64)> = None;
+    let mut close = |current: &mut Option<(Duration, Duration, usize, f64)>| {
+        if let Some((start, end, count, bytes)) = current.take() {
+            if count >= BULK_PACKET_COUNT {
+                bulk_count += 1;
+                packet_count += count;
+                byte_count += bytes;
+                duration += end.saturating_sub(start);
+            }
+        }
+    };
+    for (packet, packet_forward) in packet_list {
+        let length = payload_length(packet, transport_protocol);
+        if length == 0 {
+            continue;
+        }
+        if *packet_forward != forward {
+            close(&mut current);
+            continue;
+        }
+        match &mut current {
+            Some((_, end, count, bytes)) if packet.timestamp.saturating_sub(*end) <= BULK_GAP => {
+                *end = packet.timestamp;
+                *count += 1;
+                *bytes += length as f64;
+            }
+            _ => {
+                close(&mut current);
+                current = Some((packet.timestamp, packet.timestamp, 1, length as f64));
+            }
+        }
+    }
+    close(&mut current);
+
+    if bulk_count == 0 {
+        return Bulk::default();
+    }
+    Bulk {
+        bytes_per_bulk: byte_count / bulk_count as f64,
+        packets_per_bulk: packet_count as f64 / bulk_count as f64,
+        rate: if duration > Duration::default() {
+            byte_count / duration.as_secs_f64()
+        } else {
+            0.0
+        },
+    }
+}
+
+/// Format a timestamp as CICFlowMeter does ("dd/MM/yyyy hh:mm:ss AM"), in UTC.
+fn format_timestamp(timestamp: Duration) -> String {
+    let seconds = timestamp.as_secs();
+    let days = (seconds / 86_400) as i64;
+    let second_of_day = seconds % 86_400;
+    let (hour, minute, second) = (second_of_day / 3600, second_of_day % 3600 / 60, second_of_day % 60);
+
+    // civil date from the number of days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let hour_12 = if hour % 12 == 0 { 12 } else { hour % 12 };
+    let meridiem = if hour < 12 { "AM" } else { "PM" };
+    format!(
+        "{:02}/{:02}/{:04} {:02}:{:02}:{:02} {}",
+        day, month, year, hour_12, minute, second, meridiem
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+
+    use crate::decoder::IPPROTO_TCP;
+    use crate::direction::DirectionPolicy;
+    use crate::feature::{
+        format_timestamp, header_length, payload_length, write_to_csv_file, FlowFeature, Statistic, COLUMN_LIST,
+    };
+    use crate::flag::Flag;
+    use crate::flow_id::FlowId;
+    use crate::flow_information::FlowInformation;
+    use crate::generator::Generator;
+    use crate::packet::Packet;
+
+    fn client_flow_id() -> FlowId {
+        FlowId::new(6, "10.0.0.1", "10.0.0.2", 42254, 443)
+    }
+
+    fn server_flow_id() -> FlowId {
+        FlowId::new(6, "10.0.0.2", "10.0.0.1", 443, 42254)
+    }
+
+    fn packet(millis: u64, length: u64, flags: &[Flag]) -> Packet {
+        let mut packet = Packet::new();
+        packet.timestamp = Duration::from_millis(1_595_325_117_000 + millis);
+        packet.length = length;
+        packet.window = Some(1000 + length as u16);
+        // Ethernet, IPv4 and TCP headers without options
+        packet.network_header_length = Some(20);
+        packet.network_payload_length = Some(length.saturating_sub(34) as usize);
+        packet.tcp_payload_length = Some(length.saturating_sub(54) as usize);
+        packet.flag_list = flags.iter().cloned().collect();
+        packet
+    }
+
+    /// a handshake caught from its SYN-ACK, a bulk of 4 forward packets, then an idle period
+    fn flow_information() -> FlowInformation {
+        let mut flow_information = FlowInformation::new();
+        let policy = DirectionPolicy::SynSender;
+        flow_information.push_packet(&server_flow_id(), packet(0, 54, &[Flag::SYN, Flag::ACK]), policy);
+        flow_information.push_packet(&client_flow_id(), packet(100, 54, &[Flag::ACK]), policy);
+        for millis in &[200, 300, 400, 500] {
+            flow_information.push_packet(&client_flow_id(), packet(*millis, 154, &[Flag::ACK, Flag::PSH]), policy);
+        }
+        flow_information.push_packet(&server_flow_id(), packet(10_500, 54, &[Flag::FIN, Flag::ACK]), policy);
+        flow_information
+    }
+
+    /// the length columns of the flow, as CICFlowMeter computes them from the TCP payload and header lengths
+    fn cicflowmeter_record() -> Vec<(&'static str, f64)> {
+        vec![
+            ("Total Length of Fwd Packet", 400.0),
+            ("Total Length of Bwd Packet", 0.0),
+            ("Fwd Packet Length Max", 100.0),
+            ("Fwd Packet Length Min", 0.0),
+            ("Fwd Packet Length Mean", 80.0),
+            ("Fwd Packet Length Std", 44.721359549995796),
+            ("Bwd Packet Length Max", 0.0),
+            ("Flow Bytes/s", 38.095238095238095),
+            ("Fwd Header Length", 100.0),
+            ("Bwd Header Length", 40.0),
+            ("Packet Length Max", 100.0),
+            ("Packet Length Mean", 57.142857142857146),
+            ("Average Packet Size", 57.142857142857146),
+            ("Fwd Segment Size Avg", 80.0),
+            ("Fwd Bytes/Bulk Avg", 400.0),
+            ("Subflow Fwd Bytes", 200.0),
+            ("Fwd Act Data Pkts", 4.0),
+            ("Fwd Seg Size Min", 20.0),
+        ]
+    }
+
+    #[test]
+    fn test_default() {
+        let statistic = Statistic::new(&[]);
+        assert_eq!(statistic, Statistic::default());
+    }
+
+    #[test]
+    fn it_can_compute_statistics() {
+        let statistic = Statistic::new(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_eq!(statistic.total, 40.0);
+        assert_eq!(statistic.min, 2.0);
+        assert_eq!(statistic.max, 9.0);
+        assert_eq!(statistic.mean, 5.0);
+        assert!((statistic.std - (32.0f64 / 7.0).sqrt()).abs() < 1e-9);
+        assert_eq!(Statistic::new(&[3.0]).std, 0.0);
+    }
+
+    #[test]
+    fn it_can_compute_the_features_of_a_flow() {
+        let feature = FlowFeature::new(&server_flow_id(), &flow_information());
+
+        assert_eq!(feature.flow_id.src_port, 42254);
+        assert_eq!(feature.duration, 10_500_000.0);
+        assert_eq!(feature.fwd_packet_count, 5);
+        assert_eq!(feature.bwd_packet_count, 2);
+        assert_eq!(feature.fwd_length.total, 400.0);
+        assert_eq!(feature.fwd_length.min, 0.0);
+        assert_eq!(feature.bwd_length.mean, 0.0);
+        assert_eq!(feature.iat.max, 10_000_000.0);
+        assert_eq!(feature.fwd_iat.total, 400_000.0);
+        assert_eq!(feature.bwd_iat.total, 10_500_000.0);
+        assert_eq!(feature.fwd_psh_count, 4);
+        assert_eq!(feature.syn_count, 1);
+        assert_eq!(feature.fin_count, 1);
+        assert_eq!(feature.ack_count, 7);
+        assert_eq!(feature.fwd_header_length, 100);
+        assert_eq!(feature.fwd_init_window, 1054);
+        assert_eq!(feature.bwd_init_window, 1054);
+        assert_eq!(feature.fwd_act_data_count, 4);
+        assert_eq!(feature.fwd_bulk.packets_per_bulk, 4.0);
+        assert_eq!(feature.fwd_bulk.bytes_per_bulk, 400.0);
+        assert!((feature.fwd_bulk.rate - 400.0 / 0.3).abs() < 1e-6);
+        assert_eq!(feature.bwd_bulk.packets_per_bulk, 0.0);
+        assert_eq!(feature.subflow_count, 2);
+        assert_eq!(feature.active.max, 500_000.0);
+        assert_eq!(feature.idle.total, 10_000_000.0);
+        assert_eq!(feature.down_up_ratio, 0.4);
+    }
+
+    #[test]
+    fn it_can_produce_a_record_for_each_column() {
+        let record = FlowFeature::new(&server_flow_id(), &flow_information()).to_record();
+        assert_eq!(record.len(), COLUMN_LIST.len());
+        assert_eq!(record[0], "10.0.0.1-10.0.0.2-42254-443-6");
+        assert_eq!(record[6], "21/07/2020 09:51:57 AM");
+        assert_eq!(
+            record[COLUMN_LIST.iter().position(|c| *c == "Subflow Fwd Packets").unwrap()],
+            "2.5"
+        );
+
+        let empty = FlowFeature::new(&client_flow_id(), &FlowInformation::new()).to_record();
+        assert_eq!(empty.len(), COLUMN_LIST.len());
+        assert_eq!(
+            empty[COLUMN_LIST.iter().position(|c| *c == "FWD Init Win Bytes").unwrap()],
+            "-1"
+        );
+    }
+
+    #[test]
+    fn it_can_compute_the_cicflowmeter_lengths() {
+        let record = FlowFeature::new(&server_flow_id(), &flow_information()).to_record();
+        for (column, expected_value) in cicflowmeter_record() {
+            let value: f64 = record[COLUMN_LIST.iter().position(|c| *c == column).unwrap()]
+                .parse()
+                .unwrap();
+            assert!((value - expected_value).abs() < 1e-9, "{}: {}", column, value);
+        }
+    }
+
+    #[test]
+    fn it_can_compute_the_lengths_without_the_transport_fields() {
+        let mut packet = packet(0, 154, &[Flag::ACK]);
+        packet.tcp_payload_length = None;
+        assert_eq!(header_length(&packet, IPPROTO_TCP), 20);
+        assert_eq!(payload_length(&packet, IPPROTO_TCP), 100);
+
+        // a JSON dump without layer 3 payload length
+        packet.network_payload_length = None;
+        assert_eq!(header_length(&packet, IPPROTO_TCP), 20);
+        assert_eq!(payload_length(&packet, IPPROTO_TCP), 114);
+    }
+
+    #[test]
+    fn it_can_format_a_timestamp() {
+        assert_eq!(format_timestamp(Duration::from_secs(0)), "01/01/1970 12:00:00 AM");
+        assert_eq!(
+            format_timestamp(Duration::from_secs(951_827_696)),
+            "29/02/2000 12:34:56 PM"
+        );
+    }
+
+    #[test]
+    fn it_can_write_a_csv_file() {
+        let mut generator = Generator::new();
+        generator.add(server_flow_id(), flow_information());
+        let file = "target/write_features.csv";
+        write_to_csv_file(&generator, file);
+
+        let content = fs::read_to_string(file).unwrap();
+        let line_list: Vec<&str> = content.lines().collect();
+        assert_eq!(line_list.len(), 2);
+        assert!(line_list[0].starts_with("Flow ID,Src IP,Src Port,"));
+        assert_eq!(line_list[1].split(',').count(), COLUMN_LIST.len());
+    }
+}
@@ -0,0 +1,581 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::decoder::{IPPROTO_ICMP, IPPROTO_ICMPV6, IPPROTO_SCTP, IPPROTO_TCP, IPPROTO_UDP};
+use crate::error::{Error, Result};
+use crate::flag::Flag;
+use crate::flow_id::FlowId;
+use crate::flow_information::FlowInformation;
+
+/// The endpoint of a flow a predicate applies to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Side {
+    /// the client or the server
+    Any,
+    /// the client, source of the forward packets
+    Src,
+    /// the server, source of the backward packets
+    Dst,
+}
+
+/// A comparison operator.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Comparison {
+    /// `<`
+    Lt,
+    /// `<=`
+    Le,
+    /// `=` or `==`
+    Eq,
+    /// `!=`
+    Ne,
+    /// `>=`
+    Ge,
+    /// `>`
+    Gt,
+}
+
+impl Comparison {
+    /// Returns `true` if the value compares to the reference one.
+    pub fn compare<T: PartialOrd>(self, value: T, reference: T) -> bool {
+        match self {
+            Comparison::Lt => value < reference,
+            Comparison::Le => value <= reference,
+            Comparison::Eq => value == reference,
+            Comparison::Ne => value != reference,
+            Comparison::Ge => value >= reference,
+            Comparison::Gt => value > reference,
+        }
+    }
+}
+
+/// A flow filter, parsed from an expression such as
+/// `tcp and dst port 443 and src net 10.0.0.0/8 and packets > 10`.
+///
+/// The predicates are:
+/// - `tcp`, `udp`, `icmp`, `icmpv6`, `sctp` or `proto <number>`: the transport protocol,
+/// - `ipv4` or `ipv6`: the IP version,
+/// - `[src|dst] host <ip>`, `[src|dst] net <ip>/<prefix length>`: an address or a CIDR block,
+/// - `[src|dst] port <port>` or `[src|dst] port <first port>-<last port>`: a port or a port range,
+/// - `sni <glob>`: the SNI, matched case-insensitively with `*` and `?` wildcards,
+/// - `packets`, `bytes` or `duration` (in seconds), then an operator and a number: the flow counters,
+/// - `flag <flag>`: a TCP flag set by a packet, such as `flag SYN`,
+/// - `length`, then an operator and a number: the length of a packet.
+///
+/// They are combined with `and`, `or`, `not` and parentheses,
+/// `src` being the client and `dst` the server of the flow.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Filter {
+    /// both filters match
+    And(Box<Filter>, Box<Filter>),
+    /// one of the filters matches
+    Or(Box<Filter>, Box<Filter>),
+    /// the filter doesn't match
+    Not(Box<Filter>),
+    /// transport protocol
+    Protocol(u8),
+    /// IP version, 4 or 6
+    IpVersion(u8),
+    /// IP address of an endpoint
+    Host(Side, IpAddr),
+    /// CIDR block of an endpoint: the network address and the prefix length
+    Net(Side, IpAddr, u8),
+    /// port range of an endpoint, bounds included
+    Port(Side, u16, u16),
+    /// SNI glob
+    Sni(String),
+    /// number of packets, in both directions
+    PacketCount(Comparison, u64),
+    /// number of bytes, in both directions
+    ByteCount(Comparison, u64),
+    /// duration, from the first to the last packet
+    Duration(Comparison, Duration),
+    /// TCP flag of a packet
+    Flag(Flag),
+    /// length of a packet
+    Length(Comparison, u64),
+}
+
+impl Filter {
+    /// Returns `true` if the flow matches the filter.
+    pub fn matches(&self, flow_id: &FlowId, flow_information: &FlowInformation) -> bool {
+        match self {
+            Filter::And(first, second) => {
+                first.matches(flow_id, flow_information) && second.matches(flow_id, flow_information)
+            }
+            Filter::Or(first, second) => {
+                first.matches(flow_id, flow_information) || second.matches(flow_id, flow_information)
+            }
+            Filter::Not(filter) => !filter.matches(flow_id, flow_information),
+            Filter::Protocol(transport_protocol) => flow_id.transport_protocol == *transport_protocol,
+            Filter::IpVersion(4) => flow_id.src.is_ipv4(),
+            Filter::IpVersion(_) => flow_id.src.is_ipv6(),
+            Filter::Host(side, ip) => {
+                endpoint_matches(*side, flow_id, flow_information, |endpoint_ip, _| endpoint_ip == *ip)
+            }
+            Filter::Net(side, network, prefix_length) => {
+                endpoint_matches(*side, flow_id, flow_information, |endpoint_ip, _| {
+                    is_in_network(endpoint_ip, *network, *prefix_length)
+                })
+            }
+            Filter::Port(side, first_port, last_port) => {
+                endpoint_matches(*side, flow_id, flow_information, |_, port| {
+                    (*first_port..=*last_port).contains(&port)
+                })
+            }
+            Filter::Sni(glob) => flow_information.sni.as_deref().is_some_and(|sni| {
+                glob_matches(
+                    glob.to_ascii_lowercase().as_bytes(),
+                    sni.to_ascii_lowercase().as_bytes(),
+                )
+            }),
+            Filter::PacketCount(comparison, packet_count) => {
+                let summary = flow_information.summarize();
+                comparison.compare(
+                    summary.forward_packet_count + summary.backward_packet_count,
+                    *packet_count,
+                )
+            }
+            Filter::ByteCount(comparison, octet_count) => {
+                let summary = flow_information.summarize();
+                comparison.compare(summary.forward_octet_count + summary.backward_octet_count, *octet_count)
+            }
+            Filter::Duration(comparison, duration) => {
+                let summary = flow_information.summarize();
+                comparison.compare(summary.end.saturating_sub(summary.start), *duration)
+            }
+            Filter::Flag(flag) => {
+                let summary = flow_information.summarize();
+                summary.forward_flag_list.contains(flag) || summary.backward_flag_list.contains(flag)
+            }
+            Filter::Length(comparison, length) => flow_information
+                .forward_packet_list
+                .iter()
+                .chain(flow_information.backward_packet_list.iter())
+                .any(|packet| comparison.compare(packet.length, *length)),
+        }
+    }
+
+    /// Returns a predicate of the filter, to filter a stream of flows,
+    /// such as the ones of a JSON Lines reader or the finished ones of a tracker.
+    pub fn predicate(&self) -> impl Fn(&(FlowId, FlowInformation)) -> bool + '_ {
+        move |(flow_id, flow_information)| self.matches(flow_id, flow_information)
+    }
+}
+
+impl FromStr for Filter {
+    type Err = Error;
+
+    /// Parse a filter expression.
+    fn from_str(text: &str) -> Result<Self> {
+        let mut parser = Parser {
+            token_list: tokenize(text)?,
+            position: 0,
+        };
+        let filter = parser.parse_or()?;
+        match parser.next() {
+            None => Ok(filter),
+            Some(token) => Err(Error::Parse(format!("filter: unexpected {:?}", token))),
+        }
+    }
+}
+
+/// Returns `true` if the client, the server, or any of them, matches.
+fn endpoint_matches<F: Fn(IpAddr, u16) -> bool>(
+    side: Side,
+    flow_id: &FlowId,
+    flow_information: &FlowInformation,
+    predicate: F,
+) -> bool {
+    let oriented_flow_id = flow_information.oriented_flow_id(flow_id);
+    let src_matches = || predicate(oriented_flow_id.src, oriented_flow_id.src_port);
+    let dst_matches = || predicate(oriented_flow_id.dst, oriented_flow_id.dst_port);
+    match side {
+        Side::Any => src_matches() || dst_matches(),
+        Side::Src => src_matches(),
+        Side::Dst => dst_matches(),
+    }
+}
+
+/// Returns `true` if the IP address belongs to the CIDR block,
+/// `false` if the prefix length is longer than the address.
+fn is_in_network(ip: IpAddr, network: IpAddr, prefix_length: u8) -> bool {
+    let (ip, network, bit_count) = match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => (u128::from(u32::from(ip)), u128::from(u32::from(network)), 32u32),
+        (IpAddr::V6(ip), IpAddr::V6(network)) => (u128::from(ip), u128::from(network), 128),
+        _ => return false,
+    };
+    let shift = match bit_count.checked_sub(u32::from(prefix_length)) {
+        Some(shift) => shift,
+        None => return false,
+    };
+    ip.checked_shr(shift).unwrap_or_default() == network.checked_shr(shift).unwrap_or_default()
+}
+
+/// Returns `true` if the text matches the glob, `*` matching any sequence of characters and `?` any character.
+fn glob_matches(glob: &[u8], text: &[u8]) -> bool {
+    let (mut glob_position, mut text_position) = (0, 0);
+    // the position of the last star in the glob, and the text position it matched up to
+    let mut star: Option<(usize, usize)> = None;
+    while text_position < text.len() {
+        match glob.get(glob_position) {
+            Some(b'*') => {
+                star = Some((glob_position, text_position));
+                glob_position += 1;
+            }
+            Some(&character) if character == b'?' || character == text[text_position] => {
+                glob_position += 1;
+                text_position += 1;
+            }
+            _ => match star {
+                Some((star_glob_position, star_text_position)) => {
+                    glob_position = star_glob_position + 1;
+                    text_position = star_text_position + 1;
+                    star = Some((star_glob_position, text_position));
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[glob_position..].iter().all(|character| *character == b'*')
+}
+
+/// Split a filter expression into words, parentheses and comparison operators,
+/// or returns an error if a quoted word isn't terminated.
+fn tokenize(text: &str) -> Result<Vec<String>> {
+    let mut token_list = Vec::new();
+    let mut character_iter = text.chars().peekable();
+    while let Some(character) = character_iter.next() {
+        match character {
+            _ if character.is_whitespace() => {}
+            '(' | ')' => token_list.push(character.to_string()),
+            '<' | '>' | '=' | '!' => {
+                let mut token = character.to_string();
+                if character_iter.peek() == Some(&'=') {
+                    token.push(character_iter.next().unwrap_or_default());
+                }
+                token_list.push(token);
+            }
+            '"' => {
+                let mut token = String::new();
+                loop {
+                    match character_iter.next() {
+                        Some('"') => break,
+                        Some(character) => token.push(character),
+                        None => return Err(Error::Parse(format!("filter: \"{} isn't terminated", token))),
+                    }
+                }
+                token_list.push(token);
+            }
+            _ => {
+                let mut token = character.to_string();
+                while let Some(&character) = character_iter.peek() {
+                    if character.is_whitespace() || "()<>=!\"".contains(character) {
+                        break;
+                    }
+                    token.push(character);
+                    character_iter.next();
+                }
+                token_list.push(token);
+            }
+        }
+    }
+    Ok(token_list)
+}
+
+/// A recursive descent parser of the filter expressions, `not` binding tighter than `and`, then `or`.
+struct Parser {
+    token_list: Vec<String>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<String> {
+        let token = self.token_list.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.token_list.get(self.position).map(String::as_str)
+    }
+
+    fn expect_value(&mut self, keyword: &str) -> Result<String> {
+        self.next()
+            .ok_or_else(|| Error::Parse(format!("filter: a value is expected after {:?}", keyword)))
+    }
+
+    fn parse_or(&mut self) -> Result<Filter> {
+        let mut filter = self.parse_and()?;
+        while self.peek() == Some("or") {
+            self.position += 1;
+            filter = Filter::Or(Box::new(filter), Box::new(self.parse_and()?));
+        }
+        Ok(filter)
+    }
+
+    fn parse_and(&mut self) -> Result<Filter> {
+        let mut filter = self.parse_not()?;
+        while self.peek() == Some("and") {
+            self.position += 1;
+            filter = Filter::And(Box::new(filter), Box::new(self.parse_not()?));
+        }
+        Ok(filter)
+    }
+
+    fn parse_not(&mut self) -> Result<Filter> {
+        if self.peek() == Some("not") {
+            self.position += 1;
+            return Ok(Filter::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_predicate()
+    }
+
+    fn parse_predicate(&mut self) -> Result<Filter> {
+        let keyword = self
+            .next()
+            .ok_or_else(|| Error::Parse("filter: unexpected end".to_string()))?;
+        let side = match keyword.as_str() {
+            "src" => Side::Src,
+            "dst" => Side::Dst,
+            _ => Side::Any,
+        };
+        let keyword = if side == Side::Any {
+            keyword
+        } else {
+            self.expect_value(&keyword)?
+        };
+
+        let filter = match keyword.as_str() {
+            "(" => {
+                let filter = self.parse_or()?;
+                if self.next().as_deref() != Some(")") {
+                    return Err(Error::Parse("filter: ')' is expected".to_string()));
+                }
+                filter
+            }
+            "tcp" => Filter::Protocol(IPPROTO_TCP),
+            "udp" => Filter::Protocol(IPPROTO_UDP),
+            "icmp" => Filter::Protocol(IPPROTO_ICMP),
+            "icmpv6" => Filter::Protocol(IPPROTO_ICMPV6),
+            "sctp" => Filter::Protocol(IPPROTO_SCTP),
+            "proto" => Filter::Protocol(parse_value(&self.expect_value(&keyword)?)?),
+            "ipv4" => Filter::IpVersion(4),
+            "ipv6" => Filter::IpVersion(6),
+            "host" => Filter::Host(side, parse_value(&self.expect_value(&keyword)?)?),
+            "net" => {
+                let value = self.expect_value(&keyword)?;
+                let (network, prefix_length) = value
+                    .split_once('/')
+                    .ok_or_else(|| Error::Parse(format!("filter: {:?} isn't a CIDR block", value)))?;
+                let network: IpAddr = parse_value(network)?;
+                let prefix_length: u8 = parse_value(prefix_length)?;
+                if prefix_length > if network.is_ipv4() { 32 } else { 128 } {
+                    return Err(Error::Parse(format!("filter: {:?} prefix length is too long", value)));
+                }
+                Filter::Net(side, network, prefix_length)
+            }
+            "port" => {
+                let value = self.expect_value(&keyword)?;
+                let (first_port, last_port) = match value.split_once('-') {
+                    Some((first_port, last_port)) => (parse_value(first_port)?, parse_value(last_port)?),
+                    None => (parse_value(&value)?, parse_value(&value)?),
+                };
+                if first_port > last_port {
+                    return Err(Error::Parse(format!("filter: {:?} port range is inverted", value)));
+                }
+                Filter::Port(side, first_port, last_port)
+            }
+            "sni" => Filter::Sni(self.expect_value(&keyword)?),
+            "packets" => {
+                let comparison = self.parse_comparison(&keyword)?;
+                Filter::PacketCount(comparison, parse_value(&self.expect_value(&keyword)?)?)
+            }
+            "bytes" => {
+                let comparison = self.parse_comparison(&keyword)?;
+                Filter::ByteCount(comparison, parse_value(&self.expect_value(&keyword)?)?)
+            }
+            "duration" => {
+                let comparison = self.parse_comparison(&keyword)?;
+                let seconds: f64 = parse_value(&self.expect_value(&keyword)?)?;
+                let duration = Duration::try_from_secs_f64(seconds)
+                    .map_err(|error| Error::Parse(format!("filter: {:?}: {}", seconds, error)))?;
+                Filter::Duration(comparison, duration)
+            }
+            "flag" => {
+                let value = self.expect_value(&keyword)?.to_ascii_uppercase();
+                let flag = Flag::from_bits(u16::MAX)
+                    .into_iter()
+                    .find(|flag| format!("{:?}", flag) == value)
+                    .ok_or_else(|| Error::Parse(format!("filter: unknown TCP flag {:?}", value)))?;
+                Filter::Flag(flag)
+            }
+            "length" => {
+                let comparison = self.parse_comparison(&keyword)?;
+                Filter::Length(comparison, parse_value(&self.expect_value(&keyword)?)?)
+            }
+            _ => return Err(Error::Parse(format!("filter: unknown keyword {:?}", keyword))),
+        };
+        match (side, &filter) {
+            (Side::Any, _) | (_, Filter::Host(..)) | (_, Filter::Net(..)) | (_, Filter::Port(..)) => Ok(filter),
+            _ => Err(Error::Parse(format!("filter: {:?} can't follow src or dst", keyword))),
+        }
+    }
+
+    fn parse_comparison(&mut self, keyword: &str) -> Result<Comparison> {
+        match self.expect_value(keyword)?.as_str() {
+            "<" => Ok(Comparison::Lt),
+            "<=" => Ok(Comparison::Le),
+            "=" | "==" => Ok(Comparison::Eq),
+            "!=" => Ok(Comparison::Ne),
+            ">=" => Ok(Comparison::Ge),
+            ">" => Ok(Comparison::Gt),
+            token => Err(Error::Parse(format!("filter: {:?} isn't a comparison operator", token))),
+        }
+    }
+}
+
+fn parse_value<T: FromStr>(value: &str) -> Result<T>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|error| Error::Parse(format!("filter: {:?}: {}", value, error)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::error::Error;
+    use crate::filter::{glob_matches, Comparison, Filter, Side};
+    use crate::flag::Flag;
+    use crate::flow_id::FlowId;
+    use crate::flow_information::FlowInformation;
+    use crate::generator::Generator;
+    use crate::packet::Packet;
+
+    fn packet(seconds: u64, length: u64, flags: &[Flag]) -> Packet {
+        let mut packet = Packet::new();
+        packet.timestamp = Duration::from_secs(seconds);
+        packet.length = length;
+        packet.flag_list = flags.iter().cloned().collect();
+        packet
+    }
+
+    fn https_flow() -> (FlowId, FlowInformation) {
+        let flow_id = FlowId::new(6, "10.1.2.3", "93.184.216.34", 42254, 443);
+        let mut flow_information = FlowInformation::new();
+        for seconds in 0..12 {
+            flow_information.push_packet(&flow_id, packet(seconds, 100, &[Flag::ACK]), Default::default());
+        }
+        flow_information.sni = Some("www.Example.com".to_string());
+        (flow_id, flow_information)
+    }
+
+    fn matches(filter: &str, (flow_id, flow_information): &(FlowId, FlowInformation)) -> bool {
+        filter.parse::<Filter>().unwrap().matches(flow_id, flow_information)
+    }
+
+    #[test]
+    fn it_can_parse_a_filter() {
+        let filter: Filter = "tcp and (dst port 443 or port 8000-8080) and not packets<=10"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            filter,
+            Filter::And(
+                Box::new(Filter::And(
+                    Box::new(Filter::Protocol(6)),
+                    Box::new(Filter::Or(
+                        Box::new(Filter::Port(Side::Dst, 443, 443)),
+                        Box::new(Filter::Port(Side::Any, 8000, 8080))
+                    ))
+                )),
+                Box::new(Filter::Not(Box::new(Filter::PacketCount(Comparison::Le, 10))))
+            )
+        );
+    }
+
+    #[test]
+    fn it_cannot_parse_a_bad_filter() {
+        for filter in [
+            "",
+            "tcp and",
+            "(tcp",
+            "tcp udp",
+            "src tcp",
+            "port http",
+            "port 500-100",
+            "sni \"www.example.com",
+            "net 10.0.0.0/33",
+            "packets 10",
+            "flag XYZ",
+            "foo",
+        ] {
+            assert!(
+                matches!(filter.parse::<Filter>(), Err(Error::Parse(_))),
+                "{:?} is parsed",
+                filter
+            );
+        }
+    }
+
+    #[test]
+    fn it_can_match_the_flow_id() {
+        let flow = https_flow();
+        assert!(matches("tcp and dst port 443 and src net 10.0.0.0/8", &flow));
+        assert!(matches("ipv4 and host 93.184.216.34 and src host 10.1.2.3", &flow));
+        assert!(matches("port 40000-50000 and proto 6", &flow));
+        assert!(!matches("udp or src port 443 or dst net 10.0.0.0/8", &flow));
+        assert!(!matches("ipv6 or net 2001:db8::/32", &flow));
+        let (flow_id, flow_information) = &flow;
+        let filter = Filter::Net(Side::Src, "10.1.2.3".parse().unwrap(), 33);
+        assert!(!filter.matches(flow_id, flow_information));
+    }
+
+    #[test]
+    fn it_can_match_the_flow_information_and_the_packets() {
+        let flow = https_flow();
+        assert!(matches("packets > 10 and bytes = 1200 and duration >= 11", &flow));
+        assert!(matches("sni *.example.com and sni \"WWW.EXAMPLE.???\"", &flow));
+        assert!(matches("flag ack and length == 100", &flow));
+        assert!(!matches("packets<12 or duration>11.5 or sni example.com", &flow));
+        assert!(!matches("flag SYN or length > 100", &flow));
+    }
+
+    #[test]
+    fn it_can_match_a_glob() {
+        assert!(glob_matches(b"*", b""));
+        assert!(glob_matches(b"a*b*c", b"aXbYbZc"));
+        assert!(glob_matches(b"?b*", b"abc"));
+        assert!(!glob_matches(b"a*d", b"abc"));
+        assert!(!glob_matches(b"abc", b"abcd"));
+    }
+
+    #[test]
+    fn it_can_retain_the_matching_flows() {
+        let flow_list = || {
+            vec![
+                https_flow(),
+                (
+                    FlowId::new(17, "10.1.2.3", "10.0.0.53", 5353, 53),
+                    FlowInformation::new(),
+                ),
+            ]
+        };
+        let filter: Filter = "tcp and sni *example*".parse().unwrap();
+        let (flow_id, _) = https_flow();
+
+        let generator: Generator = flow_list().into_iter().filter(filter.predicate()).collect();
+        assert_eq!(generator.len(), 1);
+        assert!(generator.contains_key(&flow_id));
+
+        let mut generator: Generator = flow_list().into_iter().collect();
+        generator.retain_matching(&filter);
+        assert_eq!(generator.len(), 1);
+        assert!(generator.contains_key(&flow_id));
+    }
+}
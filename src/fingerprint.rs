@@ -0,0 +1,338 @@
+use md5::{Digest, Md5};
+use sha2::Sha256;
+
+use crate::tls::{ClientHello, ServerHello, TlsError, EXTENSION_ALPN, EXTENSION_SERVER_NAME};
+
+/// transport protocol part of the JA4 fingerprints (TCP)
+const JA4_PROTOCOL: char = 't';
+/// truncated hash of an empty list in the JA4 fingerprints
+const JA4_EMPTY_HASH: &str = "000000000000";
+
+/// Returns the JA3 string of a ClientHello:
+/// version, cipher suites, extensions, supported groups and EC point formats,
+/// in decimal and without GREASE values.
+pub fn ja3(client_hello: &ClientHello) -> Result<String, TlsError> {
+    let extension_type_list: Vec<u16> = client_hello
+        .extension_list
+        .iter()
+        .map(|extension| extension.extension_type)
+        .collect();
+    let ec_point_format_list: Vec<u16> = client_hello
+        .ec_point_format_list()?
+        .into_iter()
+        .map(u16::from)
+        .collect();
+    Ok(format!(
+        "{},{},{},{},{}",
+        client_hello.version,
+        decimal_list(&client_hello.cipher_suite_list),
+        decimal_list(&extension_type_list),
+        decimal_list(&client_hello.supported_group_list()?),
+        decimal_list(&ec_point_format_list)
+    ))
+}
+
+/// Returns the JA3S string of a ServerHello:
+/// version, cipher suite and extensions, in decimal.
+pub fn ja3s(server_hello: &ServerHello) -> String {
+    let extension_type_list: Vec<u16> = server_hello
+        .extension_list
+        .iter()
+        .map(|extension| extension.extension_type)
+        .collect();
+    format!(
+        "{},{},{}",
+        server_hello.version,
+        server_hello.cipher_suite,
+        decimal_list(&extension_type_list)
+    )
+}
+
+/// Returns the JA4 fingerprint of a ClientHello received over TCP.
+pub fn ja4(client_hello: &ClientHello) -> Result<String, TlsError> {
+    let version = client_hello
+        .supported_version_list()?
+        .into_iter()
+        .filter(|version| !is_grease(*version))
+        .max()
+        .unwrap_or(client_hello.version);
+    let server_name = if client_hello.extension(EXTENSION_SERVER_NAME).is_some() {
+        'd'
+    } else {
+        'i'
+    };
+    let mut cipher_suite_list = without_grease(&client_hello.cipher_suite_list);
+    let extension_type_list: Vec<u16> = client_hello
+        .extension_list
+        .iter()
+        .map(|extension| extension.extension_type)
+        .filter(|extension_type| !is_grease(*extension_type))
+        .collect();
+    let alpn = client_hello.alpn_list()?.into_iter().next();
+
+    cipher_suite_list.sort_unstable();
+    let mut hashed_extension_list: Vec<u16> = extension_type_list
+        .iter()
+        .cloned()
+        .filter(|extension_type| *extension_type != EXTENSION_SERVER_NAME && *extension_type != EXTENSION_ALPN)
+        .collect();
+    hashed_extension_list.sort_unstable();
+    let mut extensions = hex_list(&hashed_extension_list);
+    let signature_algorithm_list = client_hello.signature_algorithm_list()?;
+    if !extensions.is_empty() && !signature_algorithm_list.is_empty() {
+        extensions = format!("{}_{}", extensions, hex_list(&signature_algorithm_list));
+    }
+
+    Ok(format!(
+        "{}{}{}{:02}{:02}{}_{}_{}",
+        JA4_PROTOCOL,
+        version_code(version),
+        server_name,
+        cipher_suite_list.len().min(99),
+        extension_type_list.len().min(99),
+        alpn_code(alpn.as_deref()),
+        truncated_sha256(&hex_list(&cipher_suite_list)),
+        truncated_sha256(&extensions)
+    ))
+}
+
+/// Returns the JA4S fingerprint of a ServerHello received over TCP.
+pub fn ja4s(server_hello: &ServerHello) -> Result<String, TlsError> {
+    let version = server_hello.supported_version()?.unwrap_or(server_hello.version);
+    let extension_type_list: Vec<u16> = server_hello
+        .extension_list
+        .iter()
+        .map(|extension| extension.extension_type)
+        .collect();
+    let alpn = server_hello.alpn()?;
+    Ok(format!(
+        "{}{}{:02}{}_{:04x}_{}",
+        JA4_PROTOCOL,
+        version_code(version),
+        extension_type_list.len().min(99),
+        alpn_code(alpn.as_deref()),
+        server_hello.cipher_suite,
+        truncated_sha256(&hex_list(&extension_type_list))
+    ))
+}
+
+/// Returns the MD5 hash of a JA3 or JA3S string, in lowercase hexadecimal.
+pub fn md5_hex(text: &str) -> String {
+    format!("{:x}", Md5::digest(text.as_bytes()))
+}
+
+/// Returns `true` for a GREASE value (RFC 8701), such as 0x0a0a or 0xfafa.
+pub fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+}
+
+fn without_grease(value_list: &[u16]) -> Vec<u16> {
+    value_list.iter().cloned().filter(|value| !is_grease(*value)).collect()
+}
+
+fn decimal_list(value_list: &[u16]) -> String {
+    without_grease(value_list)
+        .iter()
+        .map(u16::to_string)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn hex_list(value_list: &[u16]) -> String {
+    value_list
+        .iter()
+        .map(|value| format!("{:04x}", value))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Returns the first 12 hexadecimal characters of the SHA-256 hash, or zeros for an empty text.
+fn truncated_sha256(text: &str) -> String {
+    if text.is_empty() {
+        return JA4_EMPTY_HASH.to_string();
+    }
+    format!("{:x}", Sha256::digest(text.as_bytes()))[..12].to_string()
+}
+
+/// Returns the two characters of a TLS version in the JA4 fingerprints.
+fn version_code(version: u16) -> &'static str {
+    match version {
+        0x0304 => "13",
+        0x0303 => "12",
+        0x0302 => "11",
+        0x0301 => "10",
+        0x0300 => "s3",
+        0x0002 => "s2",
+        0xfeff => "d1",
+        0xfefd => "d2",
+        0xfefc => "d3",
+        _ => "00",
+    }
+}
+
+/// Returns the first and last characters of the first ALPN protocol,
+/// or of its hexadecimal representation if they aren't alphanumeric.
+fn alpn_code(alpn: Option<&[u8]>) -> String {
+    match alpn {
+        Some([first, .., last]) | Some([first @ last]) => {
+            if first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric() {
+                format!("{}{}", *first as char, *last as char)
+            } else {
+                format!("{:x}{:x}", first >> 4, last & 0x0f)
+            }
+        }
+        _ => "00".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fingerprint::{alpn_code, is_grease, ja3, ja3s, ja4, ja4s, md5_hex};
+    use crate::tls::tests::{client_hello_body, server_hello_body, server_name_extension, with_u16_length};
+    use crate::tls::{
+        ClientHello, ServerHello, EXTENSION_ALPN, EXTENSION_EC_POINT_FORMATS, EXTENSION_SERVER_NAME,
+        EXTENSION_SIGNATURE_ALGORITHMS, EXTENSION_SUPPORTED_GROUPS, EXTENSION_SUPPORTED_VERSIONS,
+    };
+
+    fn u16_vector(value_list: &[u16]) -> Vec<u8> {
+        let bytes: Vec<u8> = value_list
+            .iter()
+            .flat_map(|value| value.to_be_bytes().to_vec())
+            .collect();
+        with_u16_length(&bytes)
+    }
+
+    fn alpn_extension(protocol: &str) -> Vec<u8> {
+        let mut list = vec![protocol.len() as u8];
+        list.extend_from_slice(protocol.as_bytes());
+        with_u16_length(&list)
+    }
+
+    /// the ClientHello of the JA3 README example, with GREASE values added
+    fn ja3_client_hello() -> ClientHello {
+        let body = client_hello_body(
+            769,
+            &[0x0a0a, 47, 53, 5, 10, 49161, 49162, 49171, 49172, 50, 56, 19, 4],
+            &[
+                (0xdada, vec![]),
+                (EXTENSION_SERVER_NAME, server_name_extension("www.example.com")),
+                (EXTENSION_SUPPORTED_GROUPS, u16_vector(&[0x2a2a, 23, 24, 25])),
+                (EXTENSION_EC_POINT_FORMATS, vec![1, 0]),
+            ],
+        );
+        ClientHello::parse(&body).unwrap()
+    }
+
+    /// the ClientHello of the JA4 technical details example (Chrome)
+    fn ja4_client_hello() -> ClientHello {
+        let cipher_suite_list = [
+            0x1a1a, 0x1301, 0x1302, 0x1303, 0xc02b, 0xc02f, 0xc02c, 0xc030, 0xcca9, 0xcca8, 0xc013, 0xc014, 0x009c,
+            0x009d, 0x002f, 0x0035,
+        ];
+        let signature_algorithm_list = [0x0403, 0x0804, 0x0401, 0x0503, 0x0805, 0x0501, 0x0806, 0x0601];
+        let body = client_hello_body(
+            0x0303,
+            &cipher_suite_list,
+            &[
+                (0x3a3a, vec![]),
+                (0x0033, vec![0, 0]),
+                (0x002d, vec![1, 1]),
+                (EXTENSION_SIGNATURE_ALGORITHMS, u16_vector(&signature_algorithm_list)),
+                (0x0017, vec![]),
+                (EXTENSION_SERVER_NAME, server_name_extension("www.google.com")),
+                (0x0023, vec![]),
+                (
+                    EXTENSION_SUPPORTED_VERSIONS,
+                    vec![6, 0x6a, 0x6a, 0x03, 0x04, 0x03, 0x03],
+                ),
+                (0x0012, vec![]),
+                (
+                    EXTENSION_SUPPORTED_GROUPS,
+                    u16_vector(&[0x4a4a, 0x001d, 0x0017, 0x0018]),
+                ),
+                (0x001b, vec![2, 0, 2]),
+                (0x0005, vec![1, 0, 0, 0, 0]),
+                (0xff01, vec![0]),
+                (EXTENSION_EC_POINT_FORMATS, vec![1, 0]),
+                (0x4469, vec![0, 3, 2, 0x68, 0x32]),
+                (EXTENSION_ALPN, alpn_extension("h2")),
+                (0x0015, vec![0; 8]),
+                (0xfafa, vec![0]),
+            ],
+        );
+        ClientHello::parse(&body).unwrap()
+    }
+
+    #[test]
+    fn it_can_detect_grease_values() {
+        assert!(is_grease(0x0a0a));
+        assert!(is_grease(0xfafa));
+        assert!(!is_grease(0x0a1a));
+        assert!(!is_grease(0x1301));
+    }
+
+    #[test]
+    fn it_can_compute_the_ja3_reference_vector() {
+        let ja3 = ja3(&ja3_client_hello()).unwrap();
+        assert_eq!(
+            ja3,
+            "769,47-53-5-10-49161-49162-49171-49172-50-56-19-4,0-10-11,23-24-25,0"
+        );
+        assert_eq!(md5_hex(&ja3), "ada70206e40642a3e4461f35503241d5");
+    }
+
+    #[test]
+    fn it_can_compute_a_ja3s() {
+        let body = server_hello_body(
+            769,
+            47,
+            &[
+                (0xff01, vec![0]),
+                (EXTENSION_SERVER_NAME, vec![]),
+                (EXTENSION_EC_POINT_FORMATS, vec![1, 0]),
+                (0x0023, vec![]),
+                (0x0005, vec![]),
+                (EXTENSION_ALPN, alpn_extension("http/1.1")),
+            ],
+        );
+        let server_hello = ServerHello::parse(&body).unwrap();
+        let ja3s = ja3s(&server_hello);
+        assert_eq!(ja3s, "769,47,65281-0-11-35-5-16");
+        assert_eq!(md5_hex(&ja3s), "836ce314215654b5b1f85f97c73e506f");
+        assert_eq!(ja4s(&server_hello).unwrap(), "t1006h1_002f_9bd66850b8f2");
+    }
+
+    #[test]
+    fn it_can_compute_the_ja4_reference_vector() {
+        assert_eq!(
+            ja4(&ja4_client_hello()).unwrap(),
+            "t13d1516h2_8daaf6152771_e5627efa2ab1"
+        );
+    }
+
+    #[test]
+    fn it_can_compute_a_ja4_without_extension() {
+        let client_hello = ClientHello::parse(&client_hello_body(0x0303, &[0x002f], &[])).unwrap();
+        assert_eq!(ja4(&client_hello).unwrap(), "t12i010000_ba72b8082249_000000000000");
+    }
+
+    #[test]
+    fn it_can_compute_a_ja4s() {
+        let body = server_hello_body(
+            0x0303,
+            0x1301,
+            &[(EXTENSION_SUPPORTED_VERSIONS, vec![0x03, 0x04]), (0x0033, vec![0; 4])],
+        );
+        let server_hello = ServerHello::parse(&body).unwrap();
+        assert_eq!(ja4s(&server_hello).unwrap(), "t130200_1301_a56c5b993250");
+    }
+
+    #[test]
+    fn it_can_code_an_alpn() {
+        assert_eq!(alpn_code(Some(b"h2")), "h2");
+        assert_eq!(alpn_code(Some(b"http/1.1")), "h1");
+        assert_eq!(alpn_code(Some(b"h")), "hh");
+        assert_eq!(alpn_code(Some(&[0xab, 0xcd])), "ad");
+        assert_eq!(alpn_code(None), "00");
+    }
+}
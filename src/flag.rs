@@ -1,3 +1,5 @@
+use std::collections::BTreeSet;
+
 use serde::{Deserialize, Serialize};
 
 /// TCP flags enum
@@ -31,3 +33,55 @@ pub enum Flag {
     SYN,
     URG,
 }
+
+impl Flag {
+    /// Returns the bit of the flag in the TCP header flags, NS being the 9th bit
+    /// as in the tcpControlBits IPFIX information element.
+    pub fn bit(&self) -> u16 {
+        match self {
+            Flag::FIN => 0x0001,
+            Flag::SYN => 0x0002,
+            Flag::RST => 0x0004,
+            Flag::PSH => 0x0008,
+            Flag::ACK => 0x0010,
+            Flag::URG => 0x0020,
+            Flag::ECE => 0x0040,
+            Flag::CWR => 0x0080,
+            Flag::NS => 0x0100,
+        }
+    }
+
+    /// Convert the TCP header flags to a flag list.
+    pub fn from_bits(bits: u16) -> BTreeSet<Flag> {
+        [
+            Flag::ACK,
+            Flag::CWR,
+            Flag::ECE,
+            Flag::FIN,
+            Flag::NS,
+            Flag::PSH,
+            Flag::RST,
+            Flag::SYN,
+            Flag::URG,
+        ]
+        .iter()
+        .filter(|flag| bits & flag.bit() != 0)
+        .cloned()
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::flag::Flag;
+
+    #[test]
+    fn it_can_convert_flags_to_bits_and_back() {
+        let flag_list = Flag::from_bits(0x0112);
+        assert_eq!(
+            flag_list.iter().cloned().collect::<Vec<_>>(),
+            vec![Flag::ACK, Flag::NS, Flag::SYN]
+        );
+        assert_eq!(flag_list.iter().fold(0, |bits, flag| bits | flag.bit()), 0x0112);
+    }
+}
@@ -1,10 +1,17 @@
-use std::cmp::Ordering::{Equal, Greater, Less};
+use std::cmp::Ordering::{self, Equal, Less};
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::net::{IpAddr, Ipv4Addr};
 use std::str::FromStr;
 
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
 use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use crate::decoder::{IPPROTO_ICMP, IPPROTO_ICMPV6, IPPROTO_SCTP, IPPROTO_TCP, IPPROTO_UDP};
+use crate::direction::Endpoint;
+use crate::error::{Error, Result};
 
 /// The flow unique identifier.
 /// A flow id is equal to
@@ -30,18 +37,144 @@ pub struct FlowId {
 
 impl FlowId {
     /// Create a Flow Id with IP addresses for src and dest provided as string.
+    /// Panics if an IP address can't be parsed, see `try_new`,
+    /// which also rejects IP addresses of different versions.
     pub fn new(transport_protocol: u8, src: &str, dst: &str, src_port: u16, dst_port: u16) -> Self {
-        let src_ip_addr = IpAddr::from_str(src).unwrap();
-        let dst_ip_addr = IpAddr::from_str(dst).unwrap();
-
         Self {
+            transport_protocol,
+            src: parse_ip_addr(src).unwrap(),
+            dst: parse_ip_addr(dst).unwrap(),
+            src_port,
+            dst_port,
+        }
+    }
+
+    /// Create a Flow Id with IP addresses for src and dest provided as string,
+    /// or returns an error if an IP address can't be parsed,
+    /// or if src and dest aren't of the same IP version.
+    pub fn try_new(transport_protocol: u8, src: &str, dst: &str, src_port: u16, dst_port: u16) -> Result<Self> {
+        let src_ip_addr = parse_ip_addr(src)?;
+        let dst_ip_addr = parse_ip_addr(dst)?;
+        if src_ip_addr.is_ipv4() != dst_ip_addr.is_ipv4() {
+            return Err(Error::Validation(format!(
+                "{} and {} aren't of the same IP version",
+                src_ip_addr, dst_ip_addr
+            )));
+        }
+
+        Ok(Self {
             transport_protocol,
             src: src_ip_addr,
             dst: dst_ip_addr,
             src_port,
             dst_port,
+        })
+    }
+
+    /// Returns the source IP address and port.
+    pub fn source(&self) -> Endpoint {
+        Endpoint {
+            ip: self.src,
+            port: self.src_port,
+        }
+    }
+
+    /// Returns the destination IP address and port.
+    pub fn destination(&self) -> Endpoint {
+        Endpoint {
+            ip: self.dst,
+            port: self.dst_port,
+        }
+    }
+
+    /// Returns `true` if the source is the lower endpoint, comparing the IP addresses then the ports.
+    /// It's the direction-independent ordering of the hash and the Community ID.
+    pub(crate) fn is_ordered(&self) -> bool {
+        matches!(
+            (self.src.cmp(&self.dst), self.src_port.cmp(&self.dst_port)),
+            (Less, _) | (Equal, Less) | (Equal, Equal)
+        )
+    }
+
+    /// Returns the flow id oriented from the lower endpoint to the higher one,
+    /// the same for both directions of a flow.
+    pub fn canonical(&self) -> FlowId {
+        if self.is_ordered() {
+            *self
+        } else {
+            FlowId {
+                src: self.dst,
+                src_port: self.dst_port,
+                dst: self.src,
+                dst_port: self.src_port,
+                transport_protocol: self.transport_protocol,
+            }
         }
     }
+
+    /// Returns the Community ID v1 of the flow, as computed by Zeek or Suricata with the same seed (0 by default).
+    /// For ICMP and ICMPv6, the ports are the type and the code,
+    /// and the code of a request or a reply is replaced by its counterpart type,
+    /// so that both belong to the same flow; the other types are one-way flows.
+    pub fn community_id(&self, seed: u16) -> String {
+        let mut flow_id = *self;
+        let mut one_way = false;
+        if let IPPROTO_ICMP | IPPROTO_ICMPV6 = self.transport_protocol {
+            match icmp_counterpart(self.transport_protocol, self.src_port) {
+                Some(counterpart) => flow_id.dst_port = counterpart,
+                None => one_way = true,
+            }
+        }
+        let (src, dst, src_port, dst_port) = if one_way || flow_id.is_ordered() {
+            (flow_id.src, flow_id.dst, flow_id.src_port, flow_id.dst_port)
+        } else {
+            (flow_id.dst, flow_id.src, flow_id.dst_port, flow_id.src_port)
+        };
+
+        let mut hasher = Sha1::new();
+        hasher.update(seed.to_be_bytes());
+        for ip_addr in &[src, dst] {
+            match ip_addr {
+                IpAddr::V4(ip_addr) => hasher.update(ip_addr.octets()),
+                IpAddr::V6(ip_addr) => hasher.update(ip_addr.octets()),
+            }
+        }
+        hasher.update([self.transport_protocol, 0]);
+        if let IPPROTO_ICMP | IPPROTO_TCP | IPPROTO_UDP | IPPROTO_ICMPV6 | IPPROTO_SCTP = self.transport_protocol {
+            hasher.update(src_port.to_be_bytes());
+            hasher.update(dst_port.to_be_bytes());
+        }
+        format!("1:{}", STANDARD.encode(hasher.finalize()))
+    }
+}
+
+/// Returns the type answering an ICMP or ICMPv6 type, such as the echo reply of the echo request.
+fn icmp_counterpart(transport_protocol: u8, icmp_type: u16) -> Option<u16> {
+    let pair_list: &[(u16, u16)] = if transport_protocol == IPPROTO_ICMP {
+        // echo, router, timestamp, information and address mask
+        &[(8, 0), (9, 10), (13, 14), (15, 16), (17, 18)]
+    } else {
+        // echo, multicast listener, router, neighbor, node information and home agent address discovery
+        &[(128, 129), (130, 131), (133, 134), (135, 136), (139, 140), (144, 145)]
+    };
+    pair_list.iter().find_map(|(request, reply)| match icmp_type {
+        icmp_type if icmp_type == *request => Some(*reply),
+        icmp_type if icmp_type == *reply => Some(*request),
+        _ => None,
+    })
+}
+
+fn parse_ip_addr(ip_addr: &str) -> Result<IpAddr> {
+    IpAddr::from_str(ip_addr).map_err(|error| Error::Parse(format!("{:?}: {}", ip_addr, error)))
+}
+
+fn parse_number<T: FromStr>(number: &str) -> Result<T>
+where
+    T::Err: fmt::Display,
+{
+    number
+        .parse()
+        .map_err(|error| Error::Parse(format!("{:?}: {}", number, error)))
 }
 
 impl Default for FlowId {
@@ -71,25 +204,49 @@ impl PartialEq for FlowId {
 
 impl Eq for FlowId {}
 
+impl Ord for FlowId {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // direction-independent, as the equality: the canonical flow ids are compared
+        let (flow_id, other) = (self.canonical(), other.canonical());
+        (
+            flow_id.src,
+            flow_id.src_port,
+            flow_id.dst,
+            flow_id.dst_port,
+            flow_id.transport_protocol,
+        )
+            .cmp(&(
+                other.src,
+                other.src_port,
+                other.dst,
+                other.dst_port,
+                other.transport_protocol,
+            ))
+    }
+}
+
+impl PartialOrd for FlowId {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl Hash for FlowId {
     fn hash<H: Hasher>(&self, state: &mut H) {
         // protocol hash
         self.transport_protocol.hash(state);
-        match (self.src.cmp(&self.dst), self.src_port.cmp(&self.dst_port)) {
-            (Less, _) | (Equal, Less) | (Equal, Equal) => {
-                // source then destination IP and port (backward) hash
-                self.src.hash(state);
-                self.src_port.hash(state);
-                self.dst.hash(state);
-                self.dst_port.hash(state);
-            }
-            (Greater, _) | (Equal, Greater) => {
-                // destination then source IP and port (forward) hash
-                self.dst.hash(state);
-                self.dst_port.hash(state);
-                self.src.hash(state);
-                self.src_port.hash(state);
-            }
+        if self.is_ordered() {
+            // source then destination IP and port (backward) hash
+            self.src.hash(state);
+            self.src_port.hash(state);
+            self.dst.hash(state);
+            self.dst_port.hash(state);
+        } else {
+            // destination then source IP and port (forward) hash
+            self.dst.hash(state);
+            self.dst_port.hash(state);
+            self.src.hash(state);
+            self.src_port.hash(state);
         }
     }
 }
@@ -104,12 +261,34 @@ impl fmt::Display for FlowId {
     }
 }
 
+impl FromStr for FlowId {
+    type Err = Error;
+
+    /// Parse a flow id displayed as `src-dst-src_port-dst_port-transport_protocol`.
+    fn from_str(text: &str) -> Result<Self> {
+        let field_list: Vec<&str> = text.split('-').collect();
+        if field_list.len() != 5 {
+            return Err(Error::Parse(format!("{:?}: expected 5 fields separated by '-'", text)));
+        }
+        Self::try_new(
+            parse_number(field_list[4])?,
+            field_list[0],
+            field_list[1],
+            parse_number(field_list[2])?,
+            parse_number(field_list[3])?,
+        )
+    }
+}
+
 #[cfg(test)]
+#[allow(clippy::needless_return)]
 mod tests {
+    use std::cmp::Ordering;
     use std::collections::hash_map::DefaultHasher;
     use std::hash::{Hash, Hasher};
     use std::net::{IpAddr, Ipv4Addr};
 
+    use crate::error::Error;
     use crate::flow_id::FlowId;
 
     fn remove_whitespace(s: &str) -> String {
@@ -194,27 +373,45 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
     fn test_bad_src_new() {
-        FlowId::new(
+        let result = FlowId::try_new(
             17, // UDP
             "bad",
             "192.168.0.1",
             8001,
             8002,
         );
+        match result {
+            Err(Error::Parse(message)) => assert_eq!(message, "\"bad\": invalid IP address syntax"),
+            _ => panic!("unexpected {:?}", result),
+        }
     }
 
     #[test]
-    #[should_panic]
     fn test_bad_dst_new() {
-        FlowId::new(
+        let result = FlowId::try_new(
             17, // UDP
             "127.0.0.1",
             "192.168.0.KK",
             8001,
             8002,
         );
+        assert!(matches!(result, Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_mixed_ip_version_new() {
+        let result = FlowId::try_new(
+            17, // UDP
+            "127.0.0.1",
+            "::1",
+            8001,
+            8002,
+        );
+        assert!(matches!(result, Err(Error::Validation(_))));
+        // the infallible constructor doesn't check the IP versions
+        let flow = FlowId::new(17, "127.0.0.1", "::1", 8001, 8002);
+        assert!(flow.src.is_ipv4() && flow.dst.is_ipv6());
     }
 
     #[test]
@@ -269,12 +466,47 @@ mod tests {
         assert_eq!(hasher1.finish(), hasher2.finish());
     }
 
+    #[test]
+    fn test_backward_cmp() {
+        let flow1 = build_local_flow_id();
+        // the reverse
+        let flow2 = FlowId::new(
+            17, // UDP
+            "192.168.0.1",
+            "127.0.0.1",
+            8002,
+            8001,
+        );
+        assert_eq!(flow1.cmp(&flow2), Ordering::Equal);
+        assert_eq!(flow2.canonical().to_string(), "127.0.0.1-192.168.0.1-8001-8002-17");
+        // a lower port
+        let flow3 = FlowId::new(17, "192.168.0.1", "127.0.0.1", 8002, 8000);
+        assert!(flow3 < flow2);
+    }
+
     #[test]
     fn test_display() {
         let flow = build_local_flow_id();
         assert_eq!(flow.to_string(), "127.0.0.1-192.168.0.1-8001-8002-17")
     }
 
+    #[test]
+    fn it_can_parse_a_displayed_flow_id() {
+        let flow = build_local_flow_id();
+        assert_eq!(flow.to_string().parse::<FlowId>().unwrap(), flow);
+        let flow_id: FlowId = "2001:db8::1-2001:db8::2-42254-443-6".parse().unwrap();
+        assert_eq!(flow_id.src_port, 42254);
+        assert_eq!(flow_id.transport_protocol, 6);
+        assert!(matches!(
+            "127.0.0.1-192.168.0.1-8001-8002".parse::<FlowId>(),
+            Err(Error::Parse(_))
+        ));
+        assert!(matches!(
+            "127.0.0.1-192.168.0.1-8001-70000-17".parse::<FlowId>(),
+            Err(Error::Parse(_))
+        ));
+    }
+
     #[test]
     fn it_can_deserialize_then_serialize_a_basic_flow_id() {
         let json = basic_flow_id();
@@ -284,23 +516,59 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
-    fn it_should_panic_when_deserializing_a_flow_id_with_string_src_port() {
+    fn it_cannot_deserialize_a_flow_id_with_string_src_port() {
         let json = bad_flow_id_with_string_src_port();
-        let _: FlowId = serde_json::from_str(json).unwrap();
+        let result = serde_json::from_str::<FlowId>(json).map_err(Error::from);
+        assert!(matches!(result, Err(Error::Serialization(_))));
     }
 
     #[test]
-    #[should_panic]
-    fn it_should_panic_when_deserializing_a_flow_id_with_u32_transport_protocol() {
+    fn it_cannot_deserialize_a_flow_id_with_u32_transport_protocol() {
         let json = bad_flow_id_with_u32_transport_protocol();
-        let _: FlowId = serde_json::from_str(json).unwrap();
+        let result = serde_json::from_str::<FlowId>(json).map_err(Error::from);
+        assert!(matches!(result, Err(Error::Serialization(_))));
     }
 
     #[test]
-    #[should_panic]
-    fn it_should_panic_when_deserializing_a_flow_id_without_dst() {
+    fn it_cannot_deserialize_a_flow_id_without_dst() {
         let json = bad_flow_id_without_dst();
-        let _: FlowId = serde_json::from_str(json).unwrap();
+        let result = serde_json::from_str::<FlowId>(json).map_err(Error::from);
+        assert!(matches!(result, Err(Error::Serialization(_))));
+    }
+
+    #[test]
+    fn it_can_compute_the_community_id_test_vectors() {
+        let flow_id = FlowId::new(6, "128.232.110.120", "66.35.250.204", 34855, 80);
+        assert_eq!(flow_id.community_id(0), "1:LQU9qZlK+B5F3KDmev6m5PMibrg=");
+        assert_eq!(flow_id.community_id(1), "1:3V71V58M3Ksw/yuFALMcW0LAHvc=");
+        let reverse_flow_id = FlowId::new(6, "66.35.250.204", "128.232.110.120", 80, 34855);
+        assert_eq!(reverse_flow_id.community_id(0), "1:LQU9qZlK+B5F3KDmev6m5PMibrg=");
+
+        let flow_id = FlowId::new(17, "192.168.1.52", "8.8.8.8", 54585, 53);
+        assert_eq!(flow_id.community_id(0), "1:d/FP5EW3wiY1vCndhwleRRKHowQ=");
+        let flow_id = FlowId::new(132, "192.168.170.8", "192.168.170.56", 7, 80);
+        assert_eq!(flow_id.community_id(0), "1:jQgCxbku+pNGw8WPbEc/TS/uTpQ=");
+        let flow_id = FlowId::new(47, "10.0.0.1", "10.0.0.2", 0, 0);
+        assert_eq!(flow_id.community_id(0), "1:+KlEHDT0vJgzs/eNmzHq0aSpRYw=");
+    }
+
+    #[test]
+    fn it_can_compute_the_community_id_of_icmp_flows() {
+        // echo request and reply
+        let request_flow_id = FlowId::new(1, "192.168.0.89", "192.168.0.1", 8, 0);
+        let reply_flow_id = FlowId::new(1, "192.168.0.1", "192.168.0.89", 0, 0);
+        assert_eq!(request_flow_id.community_id(0), "1:X0snYXpgwiv9TZtqg64sgzUn6Dk=");
+        assert_eq!(reply_flow_id.community_id(0), "1:X0snYXpgwiv9TZtqg64sgzUn6Dk=");
+        // neighbor solicitation
+        let flow_id = FlowId::new(58, "fe80::200:86ff:fe05:80da", "fe80::260:97ff:fe07:69ea", 135, 0);
+        assert_eq!(flow_id.community_id(0), "1:dGHyGvjMfljg6Bppwm3bg0LO8TY=");
+        // node information query and response
+        let query_flow_id = FlowId::new(58, "2001:db8::1", "2001:db8::2", 139, 0);
+        let response_flow_id = FlowId::new(58, "2001:db8::2", "2001:db8::1", 140, 0);
+        assert_eq!(query_flow_id.community_id(0), "1:Sk22M0RiSzqPc3Ud7RgLkieiFvo=");
+        assert_eq!(response_flow_id.community_id(0), "1:Sk22M0RiSzqPc3Ud7RgLkieiFvo=");
+        // one-way port unreachable
+        let flow_id = FlowId::new(1, "10.0.0.1", "10.0.0.2", 3, 1);
+        assert_eq!(flow_id.community_id(0), "1:gkoOC4ouXvyYq0Ek/WqwbLoqcxM=");
     }
 }
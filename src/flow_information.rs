@@ -1,18 +1,56 @@
 use serde::{Deserialize, Serialize};
 
+use crate::direction::{DirectionPolicy, Endpoint};
+use crate::fingerprint;
+use crate::flow_id::FlowId;
+use crate::flow_summary::FlowSummary;
 use crate::packet::Packet;
+use crate::rtt::{self, RttEstimation};
+use crate::stream::TcpStreamPair;
+use crate::tcp_analysis::{self, TcpAnalysis};
+use crate::tcp_state::{EndReason, TcpState};
+use crate::tls::{Hello, TlsError, TlsInspector};
 
 /// The flow information.
-/// It contains forward and backward packet's.
+/// It contains forward and backward packet's,
+/// the forward packets being sent by the client and the backward ones by the server.
 #[serde_with::skip_serializing_none]
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct FlowInformation {
     /// SNI field
     pub sni: Option<String>,
+    /// JA3 string of the ClientHello
+    pub ja3: Option<String>,
+    /// MD5 hash of the JA3 string
+    pub ja3_hash: Option<String>,
+    /// JA3S string of the ServerHello
+    pub ja3s: Option<String>,
+    /// MD5 hash of the JA3S string
+    pub ja3s_hash: Option<String>,
+    /// JA4 fingerprint of the ClientHello
+    pub ja4: Option<String>,
+    /// JA4S fingerprint of the ServerHello
+    pub ja4s: Option<String>,
+    /// client endpoint, source of the forward packets
+    pub client: Option<Endpoint>,
+    /// server endpoint, source of the backward packets
+    pub server: Option<Endpoint>,
     /// backward packet list
     pub backward_packet_list: Vec<Packet>,
     /// forward packet list
     pub forward_packet_list: Vec<Packet>,
+    /// TCP state when the flow ended
+    pub tcp_state: Option<TcpState>,
+    /// reason why the flow ended
+    pub end_reason: Option<EndReason>,
+    /// counters of the flow when its packets aren't known, such as a collected flow record
+    pub summary: Option<FlowSummary>,
+    /// reassembled client and server byte streams, when the TCP reassembly is enabled
+    #[serde(skip)]
+    pub tcp_stream: Option<TcpStreamPair>,
+    /// TLS parsing of the payloads
+    #[serde(skip)]
+    tls_inspector: TlsInspector,
 }
 
 impl FlowInformation {
@@ -20,25 +58,289 @@ impl FlowInformation {
     pub fn new() -> Self {
         Self { ..Default::default() }
     }
+
+    /// Returns `true` if a packet of the given flow id is sent by the client.
+    /// Without resolved client, the packets sent by the lower endpoint, as in the canonical flow id,
+    /// are the forward ones.
+    pub fn is_forward(&self, flow_id: &FlowId) -> bool {
+        match self.client {
+            Some(client) => client == flow_id.source(),
+            None => flow_id.is_ordered(),
+        }
+    }
+
+    /// Resolve the client and the server of a flow read without them, such as from a legacy JSON file,
+    /// from the flow id it is stored under: its forward packets were sent by the source.
+    /// A flow without packet nor summary is left unresolved, for the direction policy.
+    pub fn resolve_stored_client(&mut self, stored_flow_id: &FlowId) {
+        let stored =
+            !self.forward_packet_list.is_empty() || !self.backward_packet_list.is_empty() || self.summary.is_some();
+        if self.client.is_none() && stored {
+            self.client = Some(stored_flow_id.source());
+            self.server = Some(stored_flow_id.destination());
+        }
+    }
+
+    /// Returns the flow id from the client to the server, if resolved.
+    pub fn oriented_flow_id(&self, flow_id: &FlowId) -> FlowId {
+        match (self.client, self.server) {
+            (Some(client), Some(server)) => FlowId {
+                src: client.ip,
+                src_port: client.port,
+                dst: server.ip,
+                dst_port: server.port,
+                transport_protocol: flow_id.transport_protocol,
+            },
+            _ => *flow_id,
+        }
+    }
+
+    /// Returns the forward and backward packets merged by timestamp, with `true` for the forward ones.
+    pub fn packet_sequence(&self) -> Vec<(&Packet, bool)> {
+        let mut packet_list: Vec<(&Packet, bool)> = self
+            .forward_packet_list
+            .iter()
+            .map(|packet| (packet, true))
+            .chain(self.backward_packet_list.iter().map(|packet| (packet, false)))
+            .collect();
+        packet_list.sort_by_key(|(packet, _)| (packet.timestamp, packet.position));
+        packet_list
+    }
+
+    /// Returns `true` if the packet is sent by the client,
+    /// resolving the client and the server with the direction policy on the first packet.
+    pub fn resolve_forward(&mut self, flow_id: &FlowId, packet: &Packet, direction_policy: DirectionPolicy) -> bool {
+        if self.client.is_none() {
+            let (client, server) = direction_policy.resolve(flow_id, packet);
+            self.client = Some(client);
+            self.server = Some(server);
+        }
+        self.is_forward(flow_id)
+    }
+
+    /// Parse the TCP payload of a packet, forward or backward,
+    /// filling the SNI and the client fingerprints from the ClientHello of the first client payloads,
+    /// then the server fingerprints from the ServerHello of the first server payloads.
+    /// The payloads must be in order and given once: a hello split across TCP segments
+    /// needs a `TcpReassembler` to drop the retransmitted ones.
+    pub fn add_payload(&mut self, forward: bool, payload: &[u8]) -> Result<(), TlsError> {
+        match self.tls_inspector.inspect(forward, payload)? {
+            Some(Hello::Client(client_hello)) => {
+                if let Some(server_name) = client_hello.server_name()? {
+                    self.sni = Some(server_name);
+                }
+                let ja3 = fingerprint::ja3(&client_hello)?;
+                self.ja3_hash = Some(fingerprint::md5_hex(&ja3));
+                self.ja3 = Some(ja3);
+                self.ja4 = Some(fingerprint::ja4(&client_hello)?);
+            }
+            Some(Hello::Server(server_hello)) => {
+                let ja3s = fingerprint::ja3s(&server_hello);
+                self.ja3s_hash = Some(fingerprint::md5_hex(&ja3s));
+                self.ja3s = Some(ja3s);
+                self.ja4s = Some(fingerprint::ja4s(&server_hello)?);
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Returns the RTT of the flow, from the handshake and from the later TCP segments,
+    /// with their statistics by direction.
+    pub fn rtt_estimation(&self) -> RttEstimation {
+        rtt::estimate(self)
+    }
+
+    /// Returns the classes of the TCP segments of the flow, with their counts by direction.
+    pub fn tcp_analysis(&self) -> TcpAnalysis {
+        tcp_analysis::analyze(self)
+    }
+
+    /// Returns the summary of the flow, the received one or else the one counted from its packets.
+    pub fn summarize(&self) -> FlowSummary {
+        if let Some(summary) = &self.summary {
+            return summary.clone();
+        }
+        let mut summary = FlowSummary::new();
+        for packet in &self.forward_packet_list {
+            summary.add_packet(true, packet);
+        }
+        for packet in &self.backward_packet_list {
+            summary.add_packet(false, packet);
+        }
+        summary
+    }
+
+    /// Add a packet to the forward or the backward list, and returns `true` for a forward one.
+    /// On the first packet, the client and server are resolved by the direction policy.
+    pub fn push_packet(&mut self, flow_id: &FlowId, packet: Packet, direction_policy: DirectionPolicy) -> bool {
+        let forward = self.resolve_forward(flow_id, &packet, direction_policy);
+        if forward {
+            self.forward_packet_list.push(packet);
+        } else {
+            self.backward_packet_list.push(packet);
+        }
+        forward
+    }
+
+    /// Merge another flow information of the same flow: the packets, ordered by timestamp then position,
+    /// the summaries, added together, and the fields unknown to this flow information.
+    /// The packets of the other flow information are swapped when its client is the server of this one,
+    /// or, without resolved clients, when its flow id is the reverse one.
+    pub fn merge(&mut self, flow_id: &FlowId, other: FlowInformation, other_flow_id: &FlowId) {
+        let same_direction = match (self.client, other.client) {
+            (Some(client), Some(other_client)) => client == other_client,
+            _ => flow_id.source() == other_flow_id.source(),
+        };
+        let (forward_packet_list, backward_packet_list) = if same_direction {
+            (other.forward_packet_list, other.backward_packet_list)
+        } else {
+            (other.backward_packet_list, other.forward_packet_list)
+        };
+        for (packet_list, other_packet_list) in [
+            (&mut self.forward_packet_list, forward_packet_list),
+            (&mut self.backward_packet_list, backward_packet_list),
+        ] {
+            packet_list.extend(other_packet_list);
+            packet_list.sort_by_key(|packet| (packet.timestamp, packet.position));
+        }
+        self.summary = match (self.summary.take(), other.summary) {
+            (Some(mut summary), Some(other_summary)) => {
+                summary.merge(other_summary, same_direction);
+                Some(summary)
+            }
+            (summary, other_summary) => summary.or(other_summary),
+        };
+
+        self.sni = self.sni.take().or(other.sni);
+        self.ja3 = self.ja3.take().or(other.ja3);
+        self.ja3_hash = self.ja3_hash.take().or(other.ja3_hash);
+        self.ja3s = self.ja3s.take().or(other.ja3s);
+        self.ja3s_hash = self.ja3s_hash.take().or(other.ja3s_hash);
+        self.ja4 = self.ja4.take().or(other.ja4);
+        self.ja4s = self.ja4s.take().or(other.ja4s);
+        if self.client.is_none() {
+            self.client = other.client;
+            self.server = other.server;
+        }
+        self.tcp_state = self.tcp_state.or(other.tcp_state);
+        self.end_reason = self.end_reason.or(other.end_reason);
+        self.tcp_stream = self.tcp_stream.take().or(other.tcp_stream);
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
+    use crate::direction::DirectionPolicy;
+    use crate::flow_id::FlowId;
     use crate::flow_information::FlowInformation;
+    use crate::flow_summary::FlowSummary;
+    use crate::packet::Packet;
+    use crate::tcp_state::EndReason;
 
     #[test]
     fn test_default() {
         let default = FlowInformation::default();
         assert!(default.sni.is_none());
+        assert!(default.ja3.is_none());
+        assert!(default.ja3_hash.is_none());
+        assert!(default.ja3s.is_none());
+        assert!(default.ja3s_hash.is_none());
+        assert!(default.ja4.is_none());
+        assert!(default.ja4s.is_none());
+        assert!(default.client.is_none());
+        assert!(default.server.is_none());
         assert!(default.backward_packet_list.is_empty());
         assert!(default.forward_packet_list.is_empty());
+        assert!(default.tcp_state.is_none());
+        assert!(default.end_reason.is_none());
+        assert!(default.summary.is_none());
+        assert!(default.tcp_stream.is_none());
     }
 
     #[test]
     fn test_new() {
         let new = FlowInformation::new();
         assert!(new.sni.is_none());
+        assert!(new.ja3.is_none());
+        assert!(new.ja3_hash.is_none());
+        assert!(new.ja3s.is_none());
+        assert!(new.ja3s_hash.is_none());
+        assert!(new.ja4.is_none());
+        assert!(new.ja4s.is_none());
+        assert!(new.client.is_none());
+        assert!(new.server.is_none());
         assert!(new.backward_packet_list.is_empty());
         assert!(new.forward_packet_list.is_empty());
+        assert!(new.tcp_state.is_none());
+        assert!(new.end_reason.is_none());
+        assert!(new.summary.is_none());
+        assert!(new.tcp_stream.is_none());
+    }
+
+    #[test]
+    fn it_can_push_packets_in_the_direction_of_the_policy() {
+        let server_flow_id = FlowId::new(6, "10.0.0.2", "10.0.0.1", 443, 42254);
+        let client_flow_id = FlowId::new(6, "10.0.0.1", "10.0.0.2", 42254, 443);
+        let mut flow_information = FlowInformation::new();
+
+        assert!(!flow_information.push_packet(&server_flow_id, Packet::new(), DirectionPolicy::LowerPortServer));
+        assert_eq!(flow_information.client, Some(client_flow_id.source()));
+        assert_eq!(flow_information.server, Some(server_flow_id.source()));
+        // the policy only applies to the first packet
+        assert!(flow_information.push_packet(&client_flow_id, Packet::new(), DirectionPolicy::FirstPacket));
+        assert!(flow_information.is_forward(&client_flow_id));
+        assert_eq!(flow_information.forward_packet_list.len(), 1);
+        assert_eq!(flow_information.backward_packet_list.len(), 1);
+    }
+
+    #[test]
+    fn it_can_tell_the_direction_of_a_flow_without_client() {
+        let client_flow_id = FlowId::new(6, "10.0.0.1", "10.0.0.2", 42254, 443);
+        let server_flow_id = FlowId::new(6, "10.0.0.2", "10.0.0.1", 443, 42254);
+        let mut flow_information = FlowInformation::new();
+        // the lower endpoint sends the forward packets
+        assert!(flow_information.is_forward(&client_flow_id));
+        assert!(!flow_information.is_forward(&server_flow_id));
+
+        // an empty flow is left to the direction policy
+        flow_information.resolve_stored_client(&server_flow_id);
+        assert!(flow_information.client.is_none());
+        flow_information.forward_packet_list.push(Packet::new());
+        flow_information.resolve_stored_client(&server_flow_id);
+        assert_eq!(flow_information.client, Some(server_flow_id.source()));
+        assert!(flow_information.is_forward(&server_flow_id));
+    }
+
+    #[test]
+    fn it_can_merge_a_reversed_flow_information() {
+        let client_flow_id = FlowId::new(17, "10.0.0.1", "10.0.0.2", 5353, 53);
+        let server_flow_id = FlowId::new(17, "10.0.0.2", "10.0.0.1", 53, 5353);
+        let mut summary = FlowSummary::new();
+        summary.start = Duration::from_secs(2);
+        summary.end = Duration::from_secs(4);
+        summary.forward_packet_count = 3;
+        summary.forward_octet_count = 300;
+        let mut flow_information = FlowInformation::new();
+        flow_information.summary = Some(summary.clone());
+        let mut other = FlowInformation::new();
+        other.sni = Some("example.com".to_string());
+        other.end_reason = Some(EndReason::IdleTimeout);
+        summary.start = Duration::from_secs(1);
+        other.summary = Some(summary);
+
+        flow_information.merge(&client_flow_id, other, &server_flow_id);
+
+        assert_eq!(flow_information.sni.as_deref(), Some("example.com"));
+        assert_eq!(flow_information.end_reason, Some(EndReason::IdleTimeout));
+        let summary = flow_information.summary.unwrap();
+        assert_eq!(summary.start, Duration::from_secs(1));
+        assert_eq!(summary.end, Duration::from_secs(4));
+        assert_eq!(summary.forward_packet_count, 3);
+        // the forward packets of the reverse flow id are backward ones
+        assert_eq!(summary.backward_packet_count, 3);
+        assert_eq!(summary.backward_octet_count, 300);
     }
 }
@@ -0,0 +1,153 @@
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::flag::Flag;
+use crate::packet::Packet;
+
+/// The flow summary: counters of a flow without its packets,
+/// such as a flow record received from a router.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct FlowSummary {
+    /// timestamp of the first packet
+    pub start: Duration,
+    /// timestamp of the last packet
+    pub end: Duration,
+    /// number of forward packets
+    pub forward_packet_count: u64,
+    /// number of forward bytes, IP headers included
+    pub forward_octet_count: u64,
+    /// union of the forward TCP flags
+    pub forward_flag_list: BTreeSet<Flag>,
+    /// number of backward packets
+    pub backward_packet_count: u64,
+    /// number of backward bytes, IP headers included
+    pub backward_octet_count: u64,
+    /// union of the backward TCP flags
+    pub backward_flag_list: BTreeSet<Flag>,
+}
+
+impl FlowSummary {
+    /// Provide an empty summary for now.
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    /// Count a forward or backward packet.
+    pub fn add_packet(&mut self, forward: bool, packet: &Packet) {
+        if self.forward_packet_count + self.backward_packet_count == 0 {
+            self.start = packet.timestamp;
+            self.end = packet.timestamp;
+        } else {
+            self.start = self.start.min(packet.timestamp);
+            self.end = self.end.max(packet.timestamp);
+        }
+        let (packet_count, octet_count, flag_list) = if forward {
+            (
+                &mut self.forward_packet_count,
+                &mut self.forward_octet_count,
+                &mut self.forward_flag_list,
+            )
+        } else {
+            (
+                &mut self.backward_packet_count,
+                &mut self.backward_octet_count,
+                &mut self.backward_flag_list,
+            )
+        };
+        *packet_count += 1;
+        // the IP datagram length, or the captured length if unknown
+        *octet_count += match (packet.network_header_length, packet.network_payload_length) {
+            (Some(header_length), Some(payload_length)) => (header_length + payload_length) as u64,
+            _ => packet.length,
+        };
+        flag_list.extend(packet.flag_list.iter().cloned());
+    }
+
+    /// Add the counters of another summary of the same flow, swapped when its direction is the reverse one.
+    pub fn merge(&mut self, other: FlowSummary, same_direction: bool) {
+        if other.forward_packet_count + other.backward_packet_count == 0 {
+            return;
+        }
+        if self.forward_packet_count + self.backward_packet_count == 0 {
+            self.start = other.start;
+            self.end = other.end;
+        } else {
+            self.start = self.start.min(other.start);
+            self.end = self.end.max(other.end);
+        }
+        let (forward, backward) = if same_direction {
+            (
+                (
+                    other.forward_packet_count,
+                    other.forward_octet_count,
+                    other.forward_flag_list,
+                ),
+                (
+                    other.backward_packet_count,
+                    other.backward_octet_count,
+                    other.backward_flag_list,
+                ),
+            )
+        } else {
+            (
+                (
+                    other.backward_packet_count,
+                    other.backward_octet_count,
+                    other.backward_flag_list,
+                ),
+                (
+                    other.forward_packet_count,
+                    other.forward_octet_count,
+                    other.forward_flag_list,
+                ),
+            )
+        };
+        self.forward_packet_count += forward.0;
+        self.forward_octet_count += forward.1;
+        self.forward_flag_list.extend(forward.2);
+        self.backward_packet_count += backward.0;
+        self.backward_octet_count += backward.1;
+        self.backward_flag_list.extend(backward.2);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::flag::Flag;
+    use crate::flow_summary::FlowSummary;
+    use crate::packet::Packet;
+
+    #[test]
+    fn test_default() {
+        let default = FlowSummary::default();
+        assert_eq!(default.start, Duration::default());
+        assert_eq!(default.forward_packet_count, 0);
+        assert!(default.backward_flag_list.is_empty());
+    }
+
+    #[test]
+    fn it_can_count_packets() {
+        let mut summary = FlowSummary::new();
+        let mut packet = Packet::new();
+        packet.timestamp = Duration::from_secs(3);
+        packet.length = 74;
+        packet.flag_list.insert(Flag::SYN);
+        summary.add_packet(true, &packet);
+        packet.timestamp = Duration::from_secs(2);
+        packet.network_header_length = Some(20);
+        packet.network_payload_length = Some(40);
+        packet.flag_list.insert(Flag::ACK);
+        summary.add_packet(false, &packet);
+
+        assert_eq!(summary.start, Duration::from_secs(2));
+        assert_eq!(summary.end, Duration::from_secs(3));
+        assert_eq!(summary.forward_octet_count, 74);
+        assert_eq!(summary.backward_octet_count, 60);
+        assert_eq!(summary.forward_flag_list.len(), 1);
+        assert_eq!(summary.backward_flag_list.len(), 2);
+    }
+}
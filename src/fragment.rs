@@ -0,0 +1,508 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::ops::Range;
+use std::time::Duration;
+
+use log::debug;
+
+use crate::decoder::{self, Fragment, LINKTYPE_RAW};
+
+/// default time to receive all the fragments of a datagram
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+/// default maximum number of datagrams being reassembled
+const DEFAULT_MAXIMUM_DATAGRAM_COUNT: usize = 1024;
+/// maximum value of the IPv4 total length and IPv6 payload length fields
+const MAXIMUM_LENGTH: usize = 65535;
+const IPV6_HEADER_LENGTH: usize = 40;
+
+/// What to do when a fragment overlaps data already received for its datagram.
+/// An exact duplicate of received data isn't an overlap.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum OverlapPolicy {
+    /// the whole datagram is dropped, as RFC 5722 requires for IPv6
+    #[default]
+    Drop,
+    /// the data already received is kept
+    First,
+    /// the data of the new fragment replaces the received one
+    Last,
+}
+
+/// The fragments of a datagram are grouped by addresses, protocol and identification.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct DatagramKey {
+    src: IpAddr,
+    dst: IpAddr,
+    protocol: u8,
+    identification: u32,
+}
+
+/// A datagram being reassembled.
+#[derive(Debug, Default)]
+struct PendingDatagram {
+    /// timestamp of its first received fragment
+    timestamp: Duration,
+    /// protocol of the fragmentable part
+    protocol: u8,
+    /// headers of the first fragment, with the IPv6 next header position
+    header: Option<(Vec<u8>, Option<usize>)>,
+    /// payload length, known from the last fragment
+    payload_length: Option<usize>,
+    payload: Vec<u8>,
+    /// received ranges of the payload, sorted and merged
+    received_list: Vec<Range<usize>>,
+}
+
+impl PendingDatagram {
+    /// Add the data of a fragment, or returns `false` if the datagram must be dropped.
+    fn add(&mut self, fragment: &Fragment, overlap_policy: OverlapPolicy) -> bool {
+        let range = fragment.offset..fragment.offset + fragment.data.len();
+        let length_field = match fragment.next_header_position {
+            Some(_) => fragment.header.len() - IPV6_HEADER_LENGTH + range.end,
+            None => fragment.header.len() + range.end,
+        };
+        if length_field > MAXIMUM_LENGTH {
+            debug!("fragment beyond the maximum datagram length");
+            return false;
+        }
+        if !fragment.more_fragments {
+            if self.payload_length.is_some_and(|length| length != range.end) {
+                debug!("fragments with distinct datagram lengths");
+                return false;
+            }
+            self.payload_length = Some(range.end);
+        }
+        if self.payload_length.is_some_and(|length| range.end > length) {
+            debug!("fragment beyond the datagram length");
+            return false;
+        }
+
+        let overlap = self
+            .received_list
+            .iter()
+            .any(|received| received.start < range.end && range.start < received.end);
+        let duplicate = overlap && self.gap_list(&range).is_empty() && self.payload[range.clone()] == *fragment.data;
+        if self.payload.len() < range.end {
+            self.payload.resize(range.end, 0);
+        }
+        match (overlap && !duplicate, overlap_policy) {
+            (true, OverlapPolicy::Drop) => {
+                debug!("overlapping fragments");
+                return false;
+            }
+            (true, OverlapPolicy::First) => {
+                for gap in self.gap_list(&range) {
+                    self.payload[gap.clone()]
+                        .copy_from_slice(&fragment.data[gap.start - range.start..gap.end - range.start]);
+                }
+            }
+            _ => self.payload[range.clone()].copy_from_slice(fragment.data),
+        }
+        if fragment.offset == 0 {
+            self.header = Some((fragment.header.to_vec(), fragment.next_header_position));
+        }
+        self.insert_received(range);
+        true
+    }
+
+    /// Returns the parts of the range which aren't received yet.
+    fn gap_list(&self, range: &Range<usize>) -> Vec<Range<usize>> {
+        let mut gap_list = Vec::new();
+        let mut start = range.start;
+        for received in &self.received_list {
+            if received.end <= start {
+                continue;
+            }
+            if received.start >= range.end {
+                break;
+            }
+            if received.start > start {
+                gap_list.push(start..received.start);
+            }
+            start = received.end;
+        }
+        if start < range.end {
+            gap_list.push(start..range.end);
+        }
+        gap_list
+    }
+
+    fn insert_received(&mut self, range: Range<usize>) {
+        self.received_list.push(range);
+        self.received_list.sort_by_key(|received| received.start);
+        let mut merged_list: Vec<Range<usize>> = Vec::with_capacity(self.received_list.len());
+        for received in self.received_list.drain(..) {
+            match merged_list.last_mut() {
+                Some(last) if received.start <= last.end => last.end = last.end.max(received.end),
+                _ => merged_list.push(received),
+            }
+        }
+        self.received_list = merged_list;
+    }
+
+    /// Returns the reassembled datagram once all its fragments are received.
+    fn datagram(&self) -> Option<Vec<u8>> {
+        let payload_length = self.payload_length?;
+        let (header, next_header_position) = self.header.as_ref()?;
+        if self.received_list.first() != Some(&(0..payload_length)) {
+            return None;
+        }
+
+        let mut datagram = header.clone();
+        match next_header_position {
+            // IPv6: the fragment header is removed, the payload length covers the extension headers
+            Some(position) => {
+                let length = (header.len() - IPV6_HEADER_LENGTH + payload_length) as u16;
+                datagram[4..6].copy_from_slice(&length.to_be_bytes());
+                datagram[*position] = self.protocol;
+            }
+            // IPv4: the flags keep "don't fragment", then the checksum is updated
+            None => {
+                let length = (header.len() + payload_length) as u16;
+                datagram[2..4].copy_from_slice(&length.to_be_bytes());
+                datagram[6] &= 0x40;
+                datagram[7] = 0;
+                datagram[10..12].copy_from_slice(&[0, 0]);
+                let checksum = ipv4_checksum(&datagram);
+                datagram[10..12].copy_from_slice(&checksum.to_be_bytes());
+            }
+        }
+        datagram.extend_from_slice(&self.payload);
+        Some(datagram)
+    }
+}
+
+/// A reassembler of the IPv4 and IPv6 fragments, in front of the flow assignment:
+/// the later fragments carry no port, so they are attributed once their datagram is reassembled.
+/// The datagrams are bounded in number and in time, the oldest ones being dropped.
+#[derive(Debug)]
+pub struct FragmentReassembler {
+    /// time to receive all the fragments of a datagram, from its first received one
+    pub timeout: Duration,
+    /// maximum number of datagrams being reassembled
+    pub maximum_datagram_count: usize,
+    /// what to do with overlapping fragments
+    pub overlap_policy: OverlapPolicy,
+    datagram_map: HashMap<DatagramKey, PendingDatagram>,
+}
+
+impl Default for FragmentReassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FragmentReassembler {
+    /// Provide a reassembler without pending datagram for now.
+    pub fn new() -> Self {
+        Self {
+            timeout: DEFAULT_TIMEOUT,
+            maximum_datagram_count: DEFAULT_MAXIMUM_DATAGRAM_COUNT,
+            overlap_policy: OverlapPolicy::default(),
+            datagram_map: HashMap::new(),
+        }
+    }
+
+    /// Returns the number of datagrams being reassembled.
+    pub fn len(&self) -> usize {
+        self.datagram_map.len()
+    }
+
+    /// Returns `true` if no datagram is being reassembled.
+    pub fn is_empty(&self) -> bool {
+        self.datagram_map.is_empty()
+    }
+
+    /// Add a captured frame, and returns the frame to decode with its link type:
+    /// the frame itself if it isn't a fragment, or can't be decoded up to its fragment,
+    /// the reassembled raw IP datagram on its last missing fragment,
+    /// or `None` while fragments are missing.
+    pub fn push<'a>(&mut self, link_type: u32, data: &'a [u8], timestamp: Duration) -> Option<(u32, Cow<'a, [u8]>)> {
+        self.expire(timestamp);
+        let fragment = match decoder::decode_fragment(link_type, data) {
+            Ok(Some(fragment)) => fragment,
+            Ok(None) | Err(_) => return Some((link_type, Cow::Borrowed(data))),
+        };
+
+        let key = DatagramKey {
+            src: fragment.src,
+            dst: fragment.dst,
+            protocol: fragment.protocol,
+            identification: fragment.identification,
+        };
+        if !self.datagram_map.contains_key(&key) && self.datagram_map.len() >= self.maximum_datagram_count {
+            self.evict_oldest();
+        }
+        let datagram = self.datagram_map.entry(key).or_insert_with(|| PendingDatagram {
+            timestamp,
+            protocol: key.protocol,
+            ..Default::default()
+        });
+        if !datagram.add(&fragment, self.overlap_policy) {
+            debug!(
+                "datagram {} from {} to {} dropped",
+                key.identification, key.src, key.dst
+            );
+            self.datagram_map.remove(&key);
+            return None;
+        }
+        let datagram = datagram.datagram()?;
+        self.datagram_map.remove(&key);
+        Some((LINKTYPE_RAW, Cow::Owned(datagram)))
+    }
+
+    /// Drop the datagrams which can't be reassembled in time.
+    fn expire(&mut self, timestamp: Duration) {
+        let timeout = self.timeout;
+        self.datagram_map.retain(|key, datagram| {
+            let pending = timestamp.saturating_sub(datagram.timestamp) <= timeout;
+            if !pending {
+                debug!(
+                    "datagram {} from {} to {} expired",
+                    key.identification, key.src, key.dst
+                );
+            }
+            pending
+        });
+    }
+
+    fn evict_oldest(&mut self) {
+        let oldest_key = self
+            .datagram_map
+            .iter()
+            .min_by_key(|(_, datagram)| datagram.timestamp)
+            .map(|(key, _)| *key);
+        if let Some(key) = oldest_key {
+            debug!(
+                "datagram {} from {} to {} evicted",
+                key.identification, key.src, key.dst
+            );
+            self.datagram_map.remove(&key);
+        }
+    }
+}
+
+/// Returns the IPv4 header checksum, computed with a null checksum field.
+fn ipv4_checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header
+        .chunks(2)
+        .map(|word| u32::from(word[0]) << 8 | u32::from(*word.get(1).unwrap_or(&0)))
+        .sum();
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::time::Duration;
+
+    use crate::decoder::{decode_with_payload, LINKTYPE_ETHERNET, LINKTYPE_RAW};
+    use crate::fragment::{ipv4_checksum, FragmentReassembler, OverlapPolicy};
+    use crate::generator::Generator;
+    use crate::pcap::{add_frame, CapturedFrame};
+
+    fn udp_segment() -> Vec<u8> {
+        // UDP: source port 5353, destination port 53, length 48
+        let mut segment = vec![0x14, 0xE9, 0x00, 0x35, 0x00, 0x30, 0x00, 0x00];
+        segment.extend_from_slice(b"0123456789abcdefghijklmnopqrstuvwxyzABCD");
+        segment
+    }
+
+    fn ipv4_fragment(offset: usize, more_fragments: bool, data: &[u8]) -> Vec<u8> {
+        let flags_and_offset = (offset / 8) as u16 | if more_fragments { 0x2000 } else { 0 };
+        let mut fragment = vec![0x45, 0x00];
+        fragment.extend_from_slice(&((20 + data.len()) as u16).to_be_bytes());
+        // identification 0x1234
+        fragment.extend_from_slice(&[0x12, 0x34]);
+        fragment.extend_from_slice(&flags_and_offset.to_be_bytes());
+        // TTL 64, UDP, checksum, source 10.0.0.1, destination 10.0.0.2
+        fragment.extend_from_slice(&[0x40, 0x11, 0x00, 0x00, 10, 0, 0, 1, 10, 0, 0, 2]);
+        fragment.extend_from_slice(data);
+        fragment
+    }
+
+    fn ipv6_fragment(offset: usize, more_fragments: bool, data: &[u8]) -> Vec<u8> {
+        let mut fragment = vec![0x60, 0x00, 0x00, 0x00];
+        fragment.extend_from_slice(&((8 + data.len()) as u16).to_be_bytes());
+        // fragment header next, hop limit 64, source 2001:db8::1, destination 2001:db8::2
+        fragment.extend_from_slice(&[44, 0x40]);
+        fragment.extend_from_slice(&[0x20, 0x01, 0x0D, 0xB8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        fragment.extend_from_slice(&[0x20, 0x01, 0x0D, 0xB8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+        // fragment header: UDP, offset and more fragments flag, identification 0x12345678
+        let offset_and_flag = offset as u16 | if more_fragments { 1 } else { 0 };
+        fragment.extend_from_slice(&[17, 0]);
+        fragment.extend_from_slice(&offset_and_flag.to_be_bytes());
+        fragment.extend_from_slice(&[0x12, 0x34, 0x56, 0x78]);
+        fragment.extend_from_slice(data);
+        fragment
+    }
+
+    #[test]
+    fn test_default() {
+        let reassembler = FragmentReassembler::default();
+        assert_eq!(reassembler.timeout, Duration::from_secs(30));
+        assert_eq!(reassembler.overlap_policy, OverlapPolicy::Drop);
+        assert!(reassembler.is_empty());
+    }
+
+    #[test]
+    fn it_can_pass_a_frame_which_isnt_a_fragment() {
+        let mut reassembler = FragmentReassembler::new();
+        let mut datagram = ipv4_fragment(0, false, &udp_segment());
+        // don't fragment
+        datagram[6] = 0x40;
+        let (link_type, data) = reassembler.push(LINKTYPE_RAW, &datagram, Duration::default()).unwrap();
+        assert_eq!(link_type, LINKTYPE_RAW);
+        assert!(matches!(data, Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn it_can_reassemble_ipv4_fragments_out_of_order() {
+        let segment = udp_segment();
+        let first = ipv4_fragment(0, true, &segment[..24]);
+        let last = ipv4_fragment(24, false, &segment[24..]);
+        let mut reassembler = FragmentReassembler::new();
+
+        assert!(reassembler
+            .push(LINKTYPE_RAW, &last, Duration::from_millis(1))
+            .is_none());
+        assert_eq!(reassembler.len(), 1);
+        let (link_type, datagram) = reassembler
+            .push(LINKTYPE_RAW, &first, Duration::from_millis(2))
+            .unwrap();
+        assert!(reassembler.is_empty());
+        assert_eq!(ipv4_checksum(&datagram[..20]), 0);
+
+        let (flow_id, packet, payload) = decode_with_payload(link_type, &datagram).unwrap();
+        assert_eq!(flow_id.to_string(), "10.0.0.1-10.0.0.2-5353-53-17");
+        assert_eq!(packet.network_header_length, Some(20));
+        assert_eq!(packet.network_payload_length, Some(48));
+        assert_eq!(payload, &segment[8..]);
+    }
+
+    #[test]
+    fn it_can_reassemble_ipv6_fragments() {
+        let segment = udp_segment();
+        let mut reassembler = FragmentReassembler::new();
+        let first = ipv6_fragment(0, true, &segment[..16]);
+        let middle = ipv6_fragment(16, true, &segment[16..32]);
+        let last = ipv6_fragment(32, false, &segment[32..]);
+
+        assert!(reassembler.push(LINKTYPE_RAW, &first, Duration::default()).is_none());
+        assert!(reassembler.push(LINKTYPE_RAW, &last, Duration::default()).is_none());
+        let (link_type, datagram) = reassembler.push(LINKTYPE_RAW, &middle, Duration::default()).unwrap();
+
+        let (flow_id, packet, payload) = decode_with_payload(link_type, &datagram).unwrap();
+        assert_eq!(flow_id.to_string(), "2001:db8::1-2001:db8::2-5353-53-17");
+        assert_eq!(packet.network_header_length, Some(40));
+        assert_eq!(packet.network_payload_length, Some(48));
+        assert_eq!(payload, &segment[8..]);
+    }
+
+    /// an Ethernet frame of the datagram, captured whole
+    fn ethernet_frame(datagram: &[u8]) -> CapturedFrame {
+        // ethernet: dst, src, IPv4 ether type
+        let mut data = vec![
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0x08, 0x00,
+        ];
+        data.extend_from_slice(datagram);
+        CapturedFrame {
+            link_type: LINKTYPE_ETHERNET,
+            timestamp: Duration::default(),
+            original_length: data.len() as u32,
+            data,
+            interface_id: None,
+        }
+    }
+
+    #[test]
+    fn it_can_give_a_reassembled_datagram_the_length_of_a_whole_frame() {
+        let segment = udp_segment();
+        let mut whole = ipv4_fragment(0, false, &segment);
+        // don't fragment
+        whole[6] = 0x40;
+        let frame_list = [
+            vec![ethernet_frame(&whole)],
+            vec![
+                ethernet_frame(&ipv4_fragment(0, true, &segment[..24])),
+                ethernet_frame(&ipv4_fragment(24, false, &segment[24..])),
+            ],
+        ];
+
+        let length_list: Vec<u64> = frame_list
+            .iter()
+            .map(|frame_list| {
+                let mut generator = Generator::new();
+                let mut reassembler = FragmentReassembler::new();
+                for (index, frame) in frame_list.iter().enumerate() {
+                    add_frame(&mut generator, &mut reassembler, frame, index + 1);
+                }
+                let (_, flow_information) = generator.iter().next().unwrap();
+                flow_information.forward_packet_list[0].length
+            })
+            .collect();
+        // Ethernet, IPv4 and UDP headers, then the UDP payload
+        assert_eq!(length_list, vec![14 + 20 + 48, 14 + 20 + 48]);
+    }
+
+    #[test]
+    fn it_can_apply_the_overlap_policy() {
+        let segment = udp_segment();
+        let mut forged = segment.clone();
+        forged[20..32].copy_from_slice(b"XXXXXXXXXXXX");
+        let first = ipv4_fragment(0, true, &segment[..24]);
+        let overlapping = ipv4_fragment(16, true, &forged[16..32]);
+        let last = ipv4_fragment(32, false, &segment[32..]);
+
+        let mut reassembler = FragmentReassembler::new();
+        assert!(reassembler.push(LINKTYPE_RAW, &first, Duration::default()).is_none());
+        assert!(reassembler.push(LINKTYPE_RAW, &first, Duration::default()).is_none());
+        assert_eq!(reassembler.len(), 1);
+        assert!(reassembler
+            .push(LINKTYPE_RAW, &overlapping, Duration::default())
+            .is_none());
+        assert!(reassembler.is_empty());
+
+        for (overlap_policy, expected) in &[(OverlapPolicy::First, &segment[20..24]), (OverlapPolicy::Last, b"XXXX")] {
+            let mut reassembler = FragmentReassembler::new();
+            reassembler.overlap_policy = *overlap_policy;
+            reassembler.push(LINKTYPE_RAW, &first, Duration::default());
+            reassembler.push(LINKTYPE_RAW, &overlapping, Duration::default());
+            let (_, datagram) = reassembler.push(LINKTYPE_RAW, &last, Duration::default()).unwrap();
+            assert_eq!(&datagram[40..44], *expected);
+            assert_eq!(&datagram[44..52], &forged[24..32]);
+        }
+    }
+
+    #[test]
+    fn it_can_expire_and_evict_the_pending_datagrams() {
+        let segment = udp_segment();
+        let first = ipv4_fragment(0, true, &segment[..24]);
+        let last = ipv4_fragment(24, false, &segment[24..]);
+        let mut reassembler = FragmentReassembler::new();
+        reassembler.timeout = Duration::from_secs(5);
+
+        reassembler.push(LINKTYPE_RAW, &first, Duration::from_secs(1));
+        assert!(reassembler.push(LINKTYPE_RAW, &last, Duration::from_secs(7)).is_none());
+        assert_eq!(reassembler.len(), 1);
+
+        reassembler.maximum_datagram_count = 1;
+        let mut other_first = first.clone();
+        other_first[5] = 0x35;
+        reassembler.push(LINKTYPE_RAW, &other_first, Duration::from_secs(8));
+        assert_eq!(reassembler.len(), 1);
+        assert!(reassembler.push(LINKTYPE_RAW, &first, Duration::from_secs(9)).is_none());
+    }
+
+    #[test]
+    fn it_cannot_reassemble_a_datagram_beyond_the_maximum_length() {
+        let mut reassembler = FragmentReassembler::new();
+        let fragment = ipv4_fragment(65520, true, &[0; 16]);
+        assert!(reassembler.push(LINKTYPE_RAW, &fragment, Duration::default()).is_none());
+        assert!(reassembler.is_empty());
+    }
+}
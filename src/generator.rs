@@ -1,29 +1,62 @@
-use std::collections::hash_map::{Entry, IntoIter};
+use std::collections::hash_map::{Entry, IntoIter, Iter};
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
+use std::io::{BufReader, BufWriter, Write};
+use std::iter::FromIterator;
 use std::path::Path;
+use std::time::Duration;
 
-use serde::{Deserialize, Serialize};
-//use serde_with::json::JsonString;
-use serde_with::serde_as;
+use log::debug;
+use serde::de;
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+use crate::decoder::IPPROTO_TCP;
+use crate::direction::DirectionPolicy;
+use crate::error::{Error, Result};
+use crate::filter::Filter;
 use crate::flow_id::FlowId;
 use crate::flow_information::FlowInformation;
+use crate::packet::Packet;
+use crate::stream::TcpReassembler;
+
+/// The order of the flows of a written generator,
+/// so that the files written from the same flows are identical.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum FlowOrder {
+    /// by timestamp of the first packet, then by flow id
+    #[default]
+    Timestamp,
+    /// by flow id, whatever the direction
+    FlowId,
+}
+
+/// The policy applied to the flows of a read generator with the same flow id, in either direction.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DuplicatePolicy {
+    /// the read fails
+    Error,
+    /// the first flow is kept
+    KeepFirst,
+    /// the last flow is kept, as a map would do
+    #[default]
+    KeepLast,
+    /// the packets of the flows are merged, ordered by timestamp then position
+    Merge,
+}
 
-#[serde_as]
-#[derive(Serialize, Debug, Deserialize, Default)]
+#[derive(Debug, Default)]
 pub struct Generator {
     // no map into the json protocol, and a key must be a string.
-
-    // we could convert the FlowId to String
-    // with a duplication possible
-    //#[serde_as(as = "HashMap<JsonString, _>")]
-
-    // we convert to an array of array
-    // with a duplication possible
-    #[serde_as(as = "Vec<(_, _)>")]
+    // we convert to an array of array,
+    // the duplicated flow ids being handled by a duplicate policy when read
     flow_map: HashMap<FlowId, FlowInformation>,
+    /// policy choosing the client of the flows added packet by packet
+    pub direction_policy: DirectionPolicy,
+    /// reassembler of the TCP byte streams, the payloads being parsed in sequence order when set
+    pub tcp_reassembler: Option<TcpReassembler>,
+    /// order of the flows when the generator is written
+    pub flow_order: FlowOrder,
 }
 
 impl Generator {
@@ -37,6 +70,41 @@ impl Generator {
         self.flow_map.len()
     }
 
+    /// Returns `true` if the map contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.flow_map.is_empty()
+    }
+
+    /// An iterator visiting all flow id - flow information pairs in arbitrary order.
+    pub fn iter(&self) -> Iter<'_, FlowId, FlowInformation> {
+        self.flow_map.iter()
+    }
+
+    /// Returns the flow id - flow information pairs sorted by the flow order.
+    pub fn sorted(&self) -> Vec<(&FlowId, &FlowInformation)> {
+        let mut flow_list: Vec<(&FlowId, &FlowInformation)> = self.flow_map.iter().collect();
+        match self.flow_order {
+            FlowOrder::Timestamp => flow_list.sort_by(|(flow_id, flow_information), (other_flow_id, other)| {
+                first_timestamp(flow_information)
+                    .cmp(&first_timestamp(other))
+                    .then(flow_id.cmp(other_flow_id))
+            }),
+            FlowOrder::FlowId => flow_list.sort_by_key(|(flow_id, _)| *flow_id),
+        }
+        flow_list
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    ///
+    /// The key may be any borrowed form of the map's key type, but
+    /// [`Hash`] and [`Eq`] on the borrowed form *must* match those for
+    /// the key type.
+    ///
+    #[inline]
+    pub fn get(&self, k: &FlowId) -> Option<&FlowInformation> {
+        self.flow_map.get(k)
+    }
+
     /// Returns a mutable reference to the value corresponding to the key.
     ///
     /// The key may be any borrowed form of the map's key type, but
@@ -60,10 +128,121 @@ impl Generator {
         self.flow_map.entry(key)
     }
 
+    /// Retains only the flows matching the filter.
+    pub fn retain_matching(&mut self, filter: &Filter) {
+        self.flow_map
+            .retain(|flow_id, flow_information| filter.matches(flow_id, flow_information));
+    }
+
     /// used for IntoIterator trait
     pub fn add(&mut self, key: FlowId, value: FlowInformation) {
         self.flow_map.insert(key, value);
     }
+
+    /// Add a packet to the flow information of its flow id,
+    /// creating the flow information on the first packet.
+    /// The packet is a forward one when it is sent by the client chosen by the direction policy,
+    /// a backward one otherwise.
+    pub fn add_packet(&mut self, flow_id: FlowId, packet: Packet) {
+        self.add_packet_with_payload(flow_id, packet, &[]);
+    }
+
+    /// Add a packet as `add_packet` does, then parse its TCP payload to fill the SNI.
+    /// With a TCP reassembler, the payload is first put back in sequence order with the previous ones,
+    /// the reassembled streams staying in the reassembler.
+    /// Without it, the payloads are parsed as they come, and a retransmitted segment
+    /// of a hello split across segments prevents it from being parsed.
+    /// A payload which can't be parsed is skipped.
+    pub fn add_packet_with_payload(&mut self, flow_id: FlowId, packet: Packet, payload: &[u8]) {
+        let flow_information = match self.flow_map.entry(flow_id) {
+            Entry::Occupied(entry) => {
+                let stored_flow_id = *entry.key();
+                let flow_information = entry.into_mut();
+                flow_information.resolve_stored_client(&stored_flow_id);
+                flow_information
+            }
+            Entry::Vacant(entry) => entry.insert(FlowInformation::new()),
+        };
+        let forward = flow_information.resolve_forward(&flow_id, &packet, self.direction_policy);
+        if flow_id.transport_protocol == IPPROTO_TCP {
+            let mut add_payload = |payload: &[u8]| {
+                if let Err(error) = flow_information.add_payload(forward, payload) {
+                    debug!("flow {} payload skipped: {}", flow_id, error);
+                }
+            };
+            match &mut self.tcp_reassembler {
+                Some(tcp_reassembler) => tcp_reassembler.add_segment(&flow_id, forward, &packet, payload, add_payload),
+                None => add_payload(payload),
+            }
+        }
+        flow_information.push_packet(&flow_id, packet, self.direction_policy);
+    }
+}
+
+impl Serialize for Generator {
+    /// Serialize the flow map as an array of flow id - flow information pairs, sorted by the flow order.
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Generator", 1)?;
+        state.serialize_field("flow_map", &self.sorted())?;
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Generator {
+    /// Deserialize the array of flow id - flow information pairs, the last one of a duplicated flow id being kept.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        FlowList::deserialize(deserializer)?
+            .into_generator(DuplicatePolicy::default())
+            .map_err(de::Error::custom)
+    }
+}
+
+/// The flows of a written generator, a flow id being possibly duplicated.
+#[derive(Deserialize)]
+struct FlowList {
+    flow_map: Vec<(FlowId, FlowInformation)>,
+}
+
+impl FlowList {
+    /// Returns a generator of the flows, the duplicated flow ids being handled by the duplicate policy.
+    fn into_generator(self, duplicate_policy: DuplicatePolicy) -> Result<Generator> {
+        let mut generator = Generator::new();
+        for (flow_id, flow_information) in self.flow_map {
+            match generator.flow_map.entry(flow_id) {
+                Entry::Vacant(entry) => {
+                    entry.insert(flow_information);
+                }
+                Entry::Occupied(mut entry) => match duplicate_policy {
+                    DuplicatePolicy::Error => {
+                        return Err(Error::Validation(format!("flow {} is duplicated", flow_id)));
+                    }
+                    DuplicatePolicy::KeepFirst => debug!("flow {} duplicate skipped", flow_id),
+                    DuplicatePolicy::KeepLast => {
+                        // the key is replaced too, as it may be the reverse flow id
+                        entry.remove();
+                        generator.flow_map.insert(flow_id, flow_information);
+                    }
+                    DuplicatePolicy::Merge => {
+                        let first_flow_id = *entry.key();
+                        entry.get_mut().merge(&first_flow_id, flow_information, &flow_id);
+                    }
+                },
+            }
+        }
+        Ok(generator)
+    }
+}
+
+/// Returns the timestamp of the first packet of a flow,
+/// or the start of its summary when its packets aren't known.
+fn first_timestamp(flow_information: &FlowInformation) -> Option<Duration> {
+    flow_information
+        .forward_packet_list
+        .iter()
+        .chain(flow_information.backward_packet_list.iter())
+        .map(|packet| packet.timestamp)
+        .min()
+        .or_else(|| flow_information.summary.as_ref().map(|summary| summary.start))
 }
 
 impl IntoIterator for Generator {
@@ -76,36 +255,77 @@ impl IntoIterator for Generator {
     }
 }
 
+impl FromIterator<(FlowId, FlowInformation)> for Generator {
+    fn from_iter<I: IntoIterator<Item = (FlowId, FlowInformation)>>(iter: I) -> Self {
+        let mut generator = Generator::new();
+        for (flow_id, flow_information) in iter {
+            generator.add(flow_id, flow_information);
+        }
+        generator
+    }
+}
+
+/// Read a generator from a JSON file.
+/// Panics if the file can't be read or deserialized, see `try_read_from_file`.
 pub fn read_from_file<P: AsRef<Path>>(path: P) -> Generator {
+    try_read_from_file(path).unwrap()
+}
+
+/// Read a generator from a JSON file,
+/// or returns an error if the file can't be read or deserialized.
+/// The last flow of a duplicated flow id is kept, see `try_read_from_file_with_policy`.
+pub fn try_read_from_file<P: AsRef<Path>>(path: P) -> Result<Generator> {
+    try_read_from_file_with_policy(path, DuplicatePolicy::default())
+}
+
+/// Read a generator from a JSON file, the duplicated flow ids, in either direction, being handled by the policy,
+/// or returns an error if the file can't be read or deserialized.
+pub fn try_read_from_file_with_policy<P: AsRef<Path>>(path: P, duplicate_policy: DuplicatePolicy) -> Result<Generator> {
     // open the file in read-only mode with buffer.
-    let file = File::open(path).unwrap();
+    let file = File::open(path)?;
     let reader = BufReader::new(file);
 
     // read the JSON contents of the file
-    serde_json::from_reader(reader).unwrap()
+    let flow_list: FlowList = serde_json::from_reader(reader)?;
+    flow_list.into_generator(duplicate_policy)
 }
 
+/// Write a generator to a JSON file.
+/// Panics if the file can't be written, see `try_write_to_file`.
 pub fn write_to_file<P: AsRef<Path>>(generator: &Generator, path: P) {
+    try_write_to_file(generator, path).unwrap()
+}
+
+/// Write a generator to a JSON file,
+/// or returns an error if the file can't be written.
+pub fn try_write_to_file<P: AsRef<Path>>(generator: &Generator, path: P) -> Result<()> {
     // open the file with buffer.
-    let file = File::create(path).unwrap();
-    let writer = BufWriter::new(file);
+    let file = File::create(path)?;
+    let mut writer = BufWriter::new(file);
 
     // write the JSON contents to the file
-    serde_json::to_writer(writer, generator).unwrap();
+    serde_json::to_writer(&mut writer, generator)?;
+    writer.flush()?;
+    Ok(())
 }
 
 #[cfg(test)]
+#[allow(clippy::get_first, clippy::needless_return)]
 mod tests {
     use std::collections::BTreeSet;
-    use std::fs::File;
-    use std::io::{Read, Write};
+    use std::fs::{self, File};
+    use std::io::{ErrorKind, Read, Write};
     use std::panic;
     use std::time::Duration;
 
+    use crate::error::Error;
     use crate::flag::Flag;
     use crate::flow_id::FlowId;
     use crate::flow_information::FlowInformation;
-    use crate::generator::{read_from_file, write_to_file, Generator};
+    use crate::generator::{
+        read_from_file, try_read_from_file, try_read_from_file_with_policy, try_write_to_file, write_to_file,
+        DuplicatePolicy, FlowOrder, Generator,
+    };
     use crate::packet::Packet;
 
     fn remove_whitespace(s: &str) -> String {
@@ -134,7 +354,7 @@ mod tests {
 "#;
     }
 
-    fn complete_flow_map_version_recto() -> &'static str {
+    fn complete_flow_map() -> &'static str {
         return r#"
 {
   "flow_map": [
@@ -253,118 +473,36 @@ mod tests {
 "#;
     }
 
-    fn complete_flow_map_version_verso() -> &'static str {
+    fn duplicated_flow_map() -> &'static str {
         return r#"
 {
-  "flow_map": [    
+  "flow_map": [
     [
+      {"src": "127.0.0.1", "src_port": 8001, "dst": "192.168.0.1", "dst_port": 8002, "transport_protocol": 17},
       {
-        "src": "2a01:cb06:a02d:8571:4706:7df1:bd62:5169",
-        "src_port": 42254,
-        "dst": "2a00:1450:4007:810::2004",
-        "dst_port": 443,
-        "transport_protocol": 6
-      },
-      {
-        "sni": "mtalk.google.com",
-        "backward_packet_list": [
-          {
-            "length": 218,
-            "timestamp": {
-              "secs": 1595324883,
-              "nanos": 969259000
-            },
-            "flag_list": [
-              "ACK"
-            ],
-            "network_protocol": 34525,
-            "position": 182
-          },
-          {
-            "length": 882,
-            "window": 3222,
-            "timestamp": {
-              "secs": 1595324884,
-              "nanos": 248056000
-            },
-            "flag_list": [
-              "ACK"
-            ],
-            "network_protocol": 34525,
-            "position": 196
-          }
-        ],
+        "backward_packet_list": [],
         "forward_packet_list": [
-          {
-            "length": 558,
-            "window": 28,
-            "timestamp": {
-              "secs": 1595324883,
-              "nanos": 910142000
-            },
-            "flag_list": [
-              "ACK"
-            ],
-            "network_protocol": 34525,
-            "position": 178
-          },
-          {
-            "length": 64,
-            "window": 38,
-            "timestamp": {
-              "secs": 1595324884,
-              "nanos": 36342000
-            },
-            "flag_list": [
-              "ACK"
-            ],
-            "network_protocol": 34525,
-            "network_header_length": 5,
-            "network_payload_length": 104,
-            "position": 189
-          },
-          {
-            "length": 234,
-            "window": 22240,
-            "timestamp": {
-              "secs": 1595324884,
-              "nanos": 158344000
-            },
-            "flag_list": [
-              "ACK", "CWR","ECE"
-            ],
-            "network_protocol": 34525,
-            "position": 194
-          }
+          {"length": 10, "timestamp": {"secs": 1, "nanos": 0}, "flag_list": [], "network_protocol": 2048, "position": 1},
+          {"length": 30, "timestamp": {"secs": 3, "nanos": 0}, "flag_list": [], "network_protocol": 2048, "position": 3}
         ]
       }
     ],
     [
+      {"src": "127.0.0.1", "src_port": 8001, "dst": "192.168.0.1", "dst_port": 8002, "transport_protocol": 17},
       {
-        "src": "10.216.31.192",
-        "src_port": 0,
-        "dst": "10.15.194.132",
-        "dst_port": 0,
-        "transport_protocol": 132
-      },
+        "backward_packet_list": [],
+        "forward_packet_list": [
+          {"length": 40, "timestamp": {"secs": 4, "nanos": 0}, "flag_list": [], "network_protocol": 2048, "position": 4}
+        ]
+      }
+    ],
+    [
+      {"src": "192.168.0.1", "src_port": 8002, "dst": "127.0.0.1", "dst_port": 8001, "transport_protocol": 17},
       {
-        "sni": "www.google.com",
         "backward_packet_list": [],
         "forward_packet_list": [
-          {
-            "length": 0,
-            "window": 0,
-            "timestamp": {
-              "secs": 1595324876,
-              "nanos": 73920000
-            },
-            "flag_list": [],
-            "network_protocol": 2048,
-            "network_header_length": 5,
-            "network_payload_length": 0,
-            "position": 1
-          }
-        ]        
+          {"length": 20, "timestamp": {"secs": 2, "nanos": 0}, "flag_list": [], "network_protocol": 2048, "position": 2}
+        ]
       }
     ]
   ]
@@ -423,6 +561,12 @@ mod tests {
             network_payload_length: Some(0),
             network_header_length: Some(5),
             position: 1,
+            interface_id: None,
+            sequence_number: None,
+            acknowledgment_number: None,
+            urgent_pointer: None,
+            tcp_options: None,
+            tcp_payload_length: None,
         });
         generator.add(flow_id_1, flow_information_1);
 
@@ -447,6 +591,12 @@ mod tests {
             network_payload_length: None,
             network_header_length: None,
             position: 182,
+            interface_id: None,
+            sequence_number: None,
+            acknowledgment_number: None,
+            urgent_pointer: None,
+            tcp_options: None,
+            tcp_payload_length: None,
         });
         flow_information_2.backward_packet_list.push(Packet {
             length: 882,
@@ -458,6 +608,12 @@ mod tests {
             network_payload_length: None,
             network_header_length: None,
             position: 196,
+            interface_id: None,
+            sequence_number: None,
+            acknowledgment_number: None,
+            urgent_pointer: None,
+            tcp_options: None,
+            tcp_payload_length: None,
         });
         flow_information_2.forward_packet_list.push(Packet {
             length: 558,
@@ -469,6 +625,12 @@ mod tests {
             network_payload_length: None,
             network_header_length: None,
             position: 178,
+            interface_id: None,
+            sequence_number: None,
+            acknowledgment_number: None,
+            urgent_pointer: None,
+            tcp_options: None,
+            tcp_payload_length: None,
         });
         flow_information_2.forward_packet_list.push(Packet {
             length: 64,
@@ -480,6 +642,12 @@ mod tests {
             network_payload_length: Some(104),
             network_header_length: Some(5),
             position: 189,
+            interface_id: None,
+            sequence_number: None,
+            acknowledgment_number: None,
+            urgent_pointer: None,
+            tcp_options: None,
+            tcp_payload_length: None,
         });
         flag_list.insert(Flag::CWR);
         flag_list.insert(Flag::ECE);
@@ -493,6 +661,12 @@ mod tests {
             network_payload_length: None,
             network_header_length: None,
             position: 194,
+            interface_id: None,
+            sequence_number: None,
+            acknowledgment_number: None,
+            urgent_pointer: None,
+            tcp_options: None,
+            tcp_payload_length: None,
         });
         generator.add(flow_id_2, flow_information_2);
         generator
@@ -517,7 +691,7 @@ mod tests {
     #[test]
     fn it_can_read_a_complete_flow_map() {
         let file = "target/read_complete_flow.json";
-        create_flow_map_file(file, complete_flow_map_version_recto());
+        create_flow_map_file(file, complete_flow_map());
 
         let generator = read_from_file(file);
 
@@ -602,13 +776,47 @@ mod tests {
 
         write_to_file(&generator, file);
 
-        let result = panic::catch_unwind(|| {
-            assert_file_equal_to_flow_map(file, complete_flow_map_version_recto());
-        });
-        // a hashmap isn't ordered, so 2 elements can be serialized
-        // as "A then B" (recto) or B then A (verso)
-        if result.is_err() {
-            assert_file_equal_to_flow_map(file, complete_flow_map_version_verso());
+        assert_file_equal_to_flow_map(file, complete_flow_map());
+    }
+
+    fn create_generator_with_udp_flows(flow_order: FlowOrder, reversed: bool) -> Generator {
+        let mut flow_list: Vec<(FlowId, FlowInformation)> = (1..=3)
+            .map(|n| {
+                let flow_id = FlowId::new(17, "10.0.0.1", "10.0.0.2", 5000 + n, 53);
+                let mut flow_information = FlowInformation::new();
+                let mut packet = Packet::new();
+                // the greater the port, the earlier the flow
+                packet.timestamp = Duration::from_secs(10 - u64::from(n));
+                flow_information.forward_packet_list.push(packet);
+                (flow_id, flow_information)
+            })
+            .collect();
+        if reversed {
+            flow_list.reverse();
+        }
+        let mut generator: Generator = flow_list.into_iter().collect();
+        generator.flow_order = flow_order;
+        generator
+    }
+
+    #[test]
+    fn it_can_write_the_flows_in_a_stable_order() {
+        for (flow_order, expected_port_list) in [
+            (FlowOrder::Timestamp, [5003, 5002, 5001]),
+            (FlowOrder::FlowId, [5001, 5002, 5003]),
+        ] {
+            let port_list: Vec<u16> = create_generator_with_udp_flows(flow_order, false)
+                .sorted()
+                .into_iter()
+                .map(|(flow_id, _)| flow_id.src_port)
+                .collect();
+            assert_eq!(port_list, expected_port_list);
+
+            let file = "target/write_sorted_flow.json";
+            let other_file = "target/write_sorted_flow_reversed.json";
+            write_to_file(&create_generator_with_udp_flows(flow_order, false), file);
+            write_to_file(&create_generator_with_udp_flows(flow_order, true), other_file);
+            assert_eq!(fs::read(file).unwrap(), fs::read(other_file).unwrap());
         }
     }
 
@@ -623,11 +831,111 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
-    fn it_should_panic_when_read_a_flow_path_with_packet_list() {
+    fn it_cannot_read_a_flow_path_with_packet_list() {
         let file = "target/bad_packet_list_flow.json";
         create_flow_map_file(file, bad_flow_map_with_packet_list());
-        let mut _generator = read_from_file(file);
+        let result = try_read_from_file(file);
+        assert!(matches!(result, Err(Error::Serialization(_))));
+    }
+
+    #[test]
+    fn it_can_read_a_duplicated_flow_map() {
+        let file = "target/duplicated_flow.json";
+        create_flow_map_file(file, duplicated_flow_map());
+        for generator in [
+            read_from_file(file),
+            serde_json::from_str::<Generator>(duplicated_flow_map()).unwrap(),
+        ] {
+            assert_eq!(generator.len(), 1);
+            let (flow_id, flow_information) = generator.iter().next().unwrap();
+            assert_eq!(flow_id.src_port, 8002);
+            assert_eq!(flow_information.forward_packet_list.len(), 1);
+        }
+    }
+
+    #[test]
+    fn it_cannot_read_a_duplicated_flow_map_with_the_error_policy() {
+        let file = "target/duplicated_flow_with_error_policy.json";
+        create_flow_map_file(file, duplicated_flow_map());
+        let result = try_read_from_file_with_policy(file, DuplicatePolicy::Error);
+        assert!(matches!(result, Err(Error::Validation(_))));
+    }
+
+    #[test]
+    fn it_can_read_a_duplicated_flow_map_with_a_policy() {
+        let file = "target/duplicated_flow_with_policy.json";
+        create_flow_map_file(file, duplicated_flow_map());
+        for (duplicate_policy, expected_src_port, expected_forward_length_list, expected_backward_length_list) in [
+            (DuplicatePolicy::KeepFirst, 8001, vec![10, 30], vec![]),
+            (DuplicatePolicy::KeepLast, 8002, vec![20], vec![]),
+            (DuplicatePolicy::Merge, 8001, vec![10, 30, 40], vec![20]),
+        ] {
+            let generator = try_read_from_file_with_policy(file, duplicate_policy).unwrap();
+            assert_eq!(generator.len(), 1);
+            let (flow_id, flow_information) = generator.iter().next().unwrap();
+            assert_eq!(flow_id.src_port, expected_src_port);
+            let length_list =
+                |packet_list: &Vec<Packet>| -> Vec<u64> { packet_list.iter().map(|packet| packet.length).collect() };
+            assert_eq!(
+                length_list(&flow_information.forward_packet_list),
+                expected_forward_length_list
+            );
+            assert_eq!(
+                length_list(&flow_information.backward_packet_list),
+                expected_backward_length_list
+            );
+        }
+    }
+
+    #[test]
+    fn it_cannot_read_a_missing_file() {
+        let result = try_read_from_file("target/missing_flow.json");
+        match result {
+            Err(Error::Io(error)) => assert_eq!(error.kind(), ErrorKind::NotFound),
+            _ => panic!("unexpected {:?}", result),
+        }
+    }
+
+    #[test]
+    fn it_cannot_write_to_a_missing_directory() {
+        let result = try_write_to_file(&Generator::new(), "target/missing/flow.json");
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn it_can_add_forward_and_backward_packets() {
+        let mut generator = Generator::new();
+        assert!(generator.is_empty());
+
+        let forward = FlowId::new(17, "127.0.0.1", "192.168.0.1", 8001, 8002);
+        let backward = FlowId::new(17, "192.168.0.1", "127.0.0.1", 8002, 8001);
+        generator.add_packet(forward, Packet::new());
+        generator.add_packet(backward, Packet::new());
+        generator.add_packet(forward, Packet::new());
+
+        assert_eq!(generator.len(), 1);
+        let flow_information = generator.get_mut(&backward).unwrap();
+        assert_eq!(flow_information.forward_packet_list.len(), 2);
+        assert_eq!(flow_information.backward_packet_list.len(), 1);
+    }
+
+    #[test]
+    fn it_can_add_packets_to_a_flow_without_client() {
+        let mut generator = Generator::new();
+        let stored = FlowId::new(17, "192.168.0.1", "127.0.0.1", 8002, 8001);
+        let reverse = FlowId::new(17, "127.0.0.1", "192.168.0.1", 8001, 8002);
+        // a flow read from a legacy JSON file, its forward packets being sent by the source of its flow id
+        let mut flow_information = FlowInformation::new();
+        flow_information.forward_packet_list.push(Packet::new());
+        generator.add(stored, flow_information);
+
+        generator.add_packet(reverse, Packet::new());
+        generator.add_packet(stored, Packet::new());
+
+        let flow_information = generator.get(&stored).unwrap();
+        assert_eq!(flow_information.client, Some(stored.source()));
+        assert_eq!(flow_information.forward_packet_list.len(), 2);
+        assert_eq!(flow_information.backward_packet_list.len(), 1);
     }
 
     #[test]
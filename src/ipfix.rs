@@ -0,0 +1,512 @@
+use std::borrow::Borrow;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::net::{IpAddr, UdpSocket};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::Result;
+use crate::flag::Flag;
+use crate::flow_id::FlowId;
+use crate::flow_information::FlowInformation;
+use crate::generator::Generator;
+
+/// IPFIX version number
+pub const IPFIX_VERSION: u16 = 10;
+/// set id of a template set
+pub const TEMPLATE_SET_ID: u16 = 2;
+/// set id of an options template set
+pub const OPTIONS_TEMPLATE_SET_ID: u16 = 3;
+/// template id of the IPv4 flow records
+pub const IPV4_TEMPLATE_ID: u16 = 256;
+/// template id of the IPv6 flow records
+pub const IPV6_TEMPLATE_ID: u16 = 257;
+/// private enterprise number of the reverse information elements (RFC 5103)
+pub const REVERSE_ENTERPRISE_NUMBER: u32 = 29305;
+
+/// IANA information elements
+pub const OCTET_DELTA_COUNT: u16 = 1;
+pub const PACKET_DELTA_COUNT: u16 = 2;
+pub const PROTOCOL_IDENTIFIER: u16 = 4;
+pub const TCP_CONTROL_BITS: u16 = 6;
+pub const SOURCE_TRANSPORT_PORT: u16 = 7;
+pub const SOURCE_IPV4_ADDRESS: u16 = 8;
+pub const DESTINATION_TRANSPORT_PORT: u16 = 11;
+pub const DESTINATION_IPV4_ADDRESS: u16 = 12;
+pub const FLOW_END_SYS_UP_TIME: u16 = 21;
+pub const FLOW_START_SYS_UP_TIME: u16 = 22;
+pub const SOURCE_IPV6_ADDRESS: u16 = 27;
+pub const DESTINATION_IPV6_ADDRESS: u16 = 28;
+pub const OCTET_TOTAL_COUNT: u16 = 85;
+pub const PACKET_TOTAL_COUNT: u16 = 86;
+pub const FLOW_START_SECONDS: u16 = 150;
+pub const FLOW_END_SECONDS: u16 = 151;
+pub const FLOW_START_MILLISECONDS: u16 = 152;
+pub const FLOW_END_MILLISECONDS: u16 = 153;
+
+pub(crate) const MESSAGE_HEADER_LENGTH: usize = 16;
+pub(crate) const SET_HEADER_LENGTH: usize = 4;
+/// default maximum message length, fitting an Ethernet MTU once in UDP over IPv6
+const DEFAULT_MAXIMUM_MESSAGE_LENGTH: usize = 1400;
+/// default number of messages between two template sets
+const DEFAULT_TEMPLATE_INTERVAL: u32 = 16;
+
+/// A field of a template: information element, length and enterprise number (0 for IANA).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FieldSpecifier {
+    /// information element identifier
+    pub element_id: u16,
+    /// field length (number of bytes)
+    pub length: u16,
+    /// private enterprise number, 0 for an IANA information element
+    pub enterprise_number: u32,
+}
+
+impl FieldSpecifier {
+    const fn iana(element_id: u16, length: u16) -> Self {
+        Self {
+            element_id,
+            length,
+            enterprise_number: 0,
+        }
+    }
+
+    const fn reverse(element_id: u16, length: u16) -> Self {
+        Self {
+            element_id,
+            length,
+            enterprise_number: REVERSE_ENTERPRISE_NUMBER,
+        }
+    }
+}
+
+/// Returns the fields of the flow records, for IPv4 or IPv6 addresses.
+/// The source is the client, the reverse elements are about the server packets.
+pub fn template(ipv6: bool) -> Vec<FieldSpecifier> {
+    let (source, destination, address_length) = if ipv6 {
+        (SOURCE_IPV6_ADDRESS, DESTINATION_IPV6_ADDRESS, 16)
+    } else {
+        (SOURCE_IPV4_ADDRESS, DESTINATION_IPV4_ADDRESS, 4)
+    };
+    vec![
+        FieldSpecifier::iana(source, address_length),
+        FieldSpecifier::iana(destination, address_length),
+        FieldSpecifier::iana(SOURCE_TRANSPORT_PORT, 2),
+        FieldSpecifier::iana(DESTINATION_TRANSPORT_PORT, 2),
+        FieldSpecifier::iana(PROTOCOL_IDENTIFIER, 1),
+        FieldSpecifier::iana(FLOW_START_MILLISECONDS, 8),
+        FieldSpecifier::iana(FLOW_END_MILLISECONDS, 8),
+        FieldSpecifier::iana(PACKET_DELTA_COUNT, 8),
+        FieldSpecifier::iana(OCTET_DELTA_COUNT, 8),
+        FieldSpecifier::iana(TCP_CONTROL_BITS, 2),
+        FieldSpecifier::reverse(PACKET_DELTA_COUNT, 8),
+        FieldSpecifier::reverse(OCTET_DELTA_COUNT, 8),
+        FieldSpecifier::reverse(TCP_CONTROL_BITS, 2),
+    ]
+}
+
+/// An IPFIX exporter, encoding flows into messages with their template sets.
+/// A message holds as many data records as its maximum length allows,
+/// and the template sets are repeated every template interval messages, for the UDP collectors.
+#[derive(Debug)]
+pub struct IpfixExporter {
+    /// observation domain id of the messages
+    pub observation_domain_id: u32,
+    /// maximum length of a message (number of bytes)
+    pub maximum_message_length: usize,
+    /// number of messages between two template sets, 0 to send them in the first message only
+    pub template_interval: u32,
+    sequence_number: u32,
+    message_count: u32,
+}
+
+impl Default for IpfixExporter {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl IpfixExporter {
+    /// Provide an exporter of the given observation domain, which didn't send any message yet.
+    pub fn new(observation_domain_id: u32) -> Self {
+        Self {
+            observation_domain_id,
+            maximum_message_length: DEFAULT_MAXIMUM_MESSAGE_LENGTH,
+            template_interval: DEFAULT_TEMPLATE_INTERVAL,
+            sequence_number: 0,
+            message_count: 0,
+        }
+    }
+
+    /// Returns the number of data records exported so far.
+    pub fn sequence_number(&self) -> u32 {
+        self.sequence_number
+    }
+
+    /// Encode the flows, owned or borrowed, into messages stamped with the given export time.
+    pub fn encode<I, K, V>(&mut self, flow_iter: I, export_time: Duration) -> Vec<Vec<u8>>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Borrow<FlowId>,
+        V: Borrow<FlowInformation>,
+    {
+        let mut message_list = Vec::new();
+        let mut message = self.start_message();
+        // the open data set: template id and start offset
+        let mut data_set: Option<(u16, usize)> = None;
+
+        for (flow_id, flow_information) in flow_iter {
+            let (template_id, record) = encode_record(flow_id.borrow(), flow_information.borrow());
+            let set_header_length = match data_set {
+                Some((id, _)) if id == template_id => 0,
+                _ => SET_HEADER_LENGTH,
+            };
+            if message.len() + set_header_length + record.len() > self.maximum_message_length
+                && message.len() > MESSAGE_HEADER_LENGTH
+            {
+                close_set(&mut message, data_set.take());
+                message_list.push(self.finish_message(message, export_time));
+                message = self.start_message();
+            }
+            match data_set {
+                Some((id, _)) if id == template_id => {}
+                _ => {
+                    close_set(&mut message, data_set.take());
+                    data_set = Some((template_id, message.len()));
+                    message.extend_from_slice(&template_id.to_be_bytes());
+                    message.extend_from_slice(&[0, 0]);
+                }
+            }
+            message.extend(record);
+            self.sequence_number = self.sequence_number.wrapping_add(1);
+        }
+        close_set(&mut message, data_set);
+        if message.len() > MESSAGE_HEADER_LENGTH {
+            message_list.push(self.finish_message(message, export_time));
+        }
+        message_list
+    }
+
+    /// Export the flows to a writer, such as a file, and returns the number of messages.
+    pub fn export_to_writer<W, I, K, V>(&mut self, writer: &mut W, flow_iter: I) -> Result<usize>
+    where
+        W: Write,
+        I: IntoIterator<Item = (K, V)>,
+        K: Borrow<FlowId>,
+        V: Borrow<FlowInformation>,
+    {
+        let message_list = self.encode(flow_iter, now());
+        for message in &message_list {
+            writer.write_all(message)?;
+        }
+        Ok(message_list.len())
+    }
+
+    /// Export the flows to the collector a UDP socket is connected to, and returns the number of messages.
+    pub fn export_to_socket<I, K, V>(&mut self, socket: &UdpSocket, flow_iter: I) -> Result<usize>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Borrow<FlowId>,
+        V: Borrow<FlowInformation>,
+    {
+        let message_list = self.encode(flow_iter, now());
+        for message in &message_list {
+            socket.send(message)?;
+        }
+        Ok(message_list.len())
+    }
+
+    /// Start a message with its header, then the template set if it is due.
+    /// The header length and export time are written once the message is finished.
+    #[allow(clippy::manual_is_multiple_of)]
+    fn start_message(&mut self) -> Vec<u8> {
+        let mut message = Vec::with_capacity(self.maximum_message_length);
+        message.extend_from_slice(&IPFIX_VERSION.to_be_bytes());
+        message.extend_from_slice(&[0; 6]);
+        message.extend_from_slice(&self.sequence_number.to_be_bytes());
+        message.extend_from_slice(&self.observation_domain_id.to_be_bytes());
+
+        let template_due = if self.template_interval == 0 {
+            self.message_count == 0
+        } else {
+            self.message_count % self.template_interval == 0
+        };
+        if template_due {
+            let start = message.len();
+            message.extend_from_slice(&TEMPLATE_SET_ID.to_be_bytes());
+            message.extend_from_slice(&[0, 0]);
+            for (template_id, ipv6) in &[(IPV4_TEMPLATE_ID, false), (IPV6_TEMPLATE_ID, true)] {
+                let field_list = template(*ipv6);
+                message.extend_from_slice(&template_id.to_be_bytes());
+                message.extend_from_slice(&(field_list.len() as u16).to_be_bytes());
+                for field in field_list {
+                    if field.enterprise_number == 0 {
+                        message.extend_from_slice(&field.element_id.to_be_bytes());
+                        message.extend_from_slice(&field.length.to_be_bytes());
+                    } else {
+                        message.extend_from_slice(&(field.element_id | 0x8000).to_be_bytes());
+                        message.extend_from_slice(&field.length.to_be_bytes());
+                        message.extend_from_slice(&field.enterprise_number.to_be_bytes());
+                    }
+                }
+            }
+            close_set(&mut message, Some((TEMPLATE_SET_ID, start)));
+        }
+        message
+    }
+
+    fn finish_message(&mut self, mut message: Vec<u8>, export_time: Duration) -> Vec<u8> {
+        let length = message.len() as u16;
+        message[2..4].copy_from_slice(&length.to_be_bytes());
+        message[4..8].copy_from_slice(&(export_time.as_secs() as u32).to_be_bytes());
+        self.message_count = self.message_count.wrapping_add(1);
+        message
+    }
+}
+
+/// Export the flows of a generator to an IPFIX file.
+/// Panics if the file can't be written, see `try_write_to_ipfix_file`.
+pub fn write_to_ipfix_file<P: AsRef<Path>>(generator: &Generator, path: P) {
+    try_write_to_ipfix_file(generator, path).unwrap()
+}
+
+/// Export the flows of a generator to an IPFIX file,
+/// or returns an error if the file can't be written.
+pub fn try_write_to_ipfix_file<P: AsRef<Path>>(generator: &Generator, path: P) -> Result<()> {
+    // open the file with buffer.
+    let file = File::create(path)?;
+    let mut writer = BufWriter::new(file);
+
+    IpfixExporter::new(0).export_to_writer(&mut writer, generator.sorted())?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write the length of the set starting at the given offset.
+fn close_set(message: &mut [u8], set: Option<(u16, usize)>) {
+    if let Some((_, start)) = set {
+        let length = (message.len() - start) as u16;
+        message[start + 2..start + 4].copy_from_slice(&length.to_be_bytes());
+    }
+}
+
+fn now() -> Duration {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+/// Encode a flow into a data record, from the client to the server, and returns its template id.
+fn encode_record(flow_id: &FlowId, flow_information: &FlowInformation) -> (u16, Vec<u8>) {
+    let (client, server) = match (flow_information.client, flow_information.server) {
+        (Some(client), Some(server)) => (client, server),
+        _ => (flow_id.source(), flow_id.destination()),
+    };
+    let summary = flow_information.summarize();
+
+    let mut record = Vec::new();
+    let template_id = match (client.ip, server.ip) {
+        (IpAddr::V4(client_ip), IpAddr::V4(server_ip)) => {
+            record.extend_from_slice(&client_ip.octets());
+            record.extend_from_slice(&server_ip.octets());
+            IPV4_TEMPLATE_ID
+        }
+        (client_ip, server_ip) => {
+            record.extend_from_slice(&ipv6_octets(client_ip));
+            record.extend_from_slice(&ipv6_octets(server_ip));
+            IPV6_TEMPLATE_ID
+        }
+    };
+    record.extend_from_slice(&client.port.to_be_bytes());
+    record.extend_from_slice(&server.port.to_be_bytes());
+    record.push(flow_id.transport_protocol);
+    record.extend_from_slice(&(summary.start.as_millis() as u64).to_be_bytes());
+    record.extend_from_slice(&(summary.end.as_millis() as u64).to_be_bytes());
+    record.extend_from_slice(&summary.forward_packet_count.to_be_bytes());
+    record.extend_from_slice(&summary.forward_octet_count.to_be_bytes());
+    record.extend_from_slice(&tcp_control_bits(&summary.forward_flag_list).to_be_bytes());
+    record.extend_from_slice(&summary.backward_packet_count.to_be_bytes());
+    record.extend_from_slice(&summary.backward_octet_count.to_be_bytes());
+    record.extend_from_slice(&tcp_control_bits(&summary.backward_flag_list).to_be_bytes());
+    (template_id, record)
+}
+
+fn ipv6_octets(ip: IpAddr) -> [u8; 16] {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped().octets(),
+        IpAddr::V6(ip) => ip.octets(),
+    }
+}
+
+/// Returns the union of the TCP flags, as the tcpControlBits element.
+fn tcp_control_bits(flag_list: &BTreeSet<Flag>) -> u16 {
+    flag_list.iter().fold(0, |bits, flag| bits | flag.bit())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    use crate::flag::Flag;
+    use crate::flow_id::FlowId;
+    use crate::flow_information::FlowInformation;
+    use crate::generator::Generator;
+    use crate::ipfix::{
+        try_write_to_ipfix_file, write_to_ipfix_file, IpfixExporter, IPV4_TEMPLATE_ID, IPV6_TEMPLATE_ID,
+    };
+    use crate::packet::Packet;
+
+    fn read_u16(data: &[u8], offset: usize) -> u16 {
+        u16::from_be_bytes([data[offset], data[offset + 1]])
+    }
+
+    fn packet(millis: u64, flags: &[Flag]) -> Packet {
+        let mut packet = Packet::new();
+        packet.timestamp = Duration::from_millis(millis);
+        packet.length = 74;
+        packet.network_header_length = Some(20);
+        packet.network_payload_length = Some(40);
+        packet.flag_list = flags.iter().cloned().collect();
+        packet
+    }
+
+    fn tcp_flow() -> (FlowId, FlowInformation) {
+        let mut flow_information = FlowInformation::new();
+        flow_information.forward_packet_list.push(packet(1_000, &[Flag::SYN]));
+        flow_information
+            .backward_packet_list
+            .push(packet(1_010, &[Flag::SYN, Flag::ACK]));
+        flow_information.forward_packet_list.push(packet(1_020, &[Flag::ACK]));
+        (FlowId::new(6, "10.0.0.1", "10.0.0.2", 42254, 443), flow_information)
+    }
+
+    fn ipv6_flow() -> (FlowId, FlowInformation) {
+        let mut flow_information = FlowInformation::new();
+        flow_information.forward_packet_list.push(packet(2_000, &[]));
+        (
+            FlowId::new(17, "2001:db8::1", "2001:db8::2", 5353, 53),
+            flow_information,
+        )
+    }
+
+    /// Returns the (set id, set length) list of a message.
+    fn set_list(message: &[u8]) -> Vec<(u16, usize)> {
+        let mut set_list = Vec::new();
+        let mut offset = 16;
+        while offset < message.len() {
+            let length = read_u16(message, offset + 2) as usize;
+            set_list.push((read_u16(message, offset), length));
+            offset += length;
+        }
+        set_list
+    }
+
+    #[test]
+    fn test_default() {
+        let exporter = IpfixExporter::default();
+        assert_eq!(exporter.observation_domain_id, 0);
+        assert_eq!(exporter.sequence_number(), 0);
+    }
+
+    #[test]
+    fn it_can_encode_a_template_set_then_data_sets() {
+        let mut exporter = IpfixExporter::new(7);
+        let flow_list = vec![tcp_flow(), ipv6_flow()];
+        let message_list = exporter.encode(flow_list, Duration::from_secs(1_600_000_000));
+        assert_eq!(message_list.len(), 1);
+
+        let message = &message_list[0];
+        assert_eq!(read_u16(message, 0), 10);
+        assert_eq!(read_u16(message, 2) as usize, message.len());
+        assert_eq!(&message[4..8], &1_600_000_000u32.to_be_bytes());
+        assert_eq!(&message[8..12], &[0, 0, 0, 0]);
+        assert_eq!(&message[12..16], &[0, 0, 0, 7]);
+        let set_list = set_list(message);
+        assert_eq!(set_list.len(), 3);
+        assert_eq!(set_list[0].0, 2);
+        // 4 + 13 fields of 4 bytes + 3 enterprise numbers, for each template
+        assert_eq!(set_list[0].1, 4 + 2 * (4 + 13 * 4 + 3 * 4));
+        assert_eq!(set_list[1], (IPV4_TEMPLATE_ID, 4 + 65));
+        assert_eq!(set_list[2], (IPV6_TEMPLATE_ID, 4 + 89));
+
+        let record = &message[16 + set_list[0].1 + 4..];
+        assert_eq!(&record[0..8], &[10, 0, 0, 1, 10, 0, 0, 2]);
+        assert_eq!(read_u16(record, 8), 42254);
+        assert_eq!(read_u16(record, 10), 443);
+        assert_eq!(record[12], 6);
+        assert_eq!(&record[13..21], &1_000u64.to_be_bytes());
+        assert_eq!(&record[21..29], &1_020u64.to_be_bytes());
+        assert_eq!(&record[29..37], &2u64.to_be_bytes());
+        assert_eq!(&record[37..45], &120u64.to_be_bytes());
+        assert_eq!(read_u16(record, 45), 0x12);
+        assert_eq!(&record[47..55], &1u64.to_be_bytes());
+        assert_eq!(read_u16(record, 63), 0x12);
+        assert_eq!(exporter.sequence_number(), 2);
+    }
+
+    #[test]
+    fn it_can_split_the_records_into_several_messages() {
+        let mut exporter = IpfixExporter::new(0);
+        exporter.maximum_message_length = 300;
+        exporter.template_interval = 2;
+        let flow_list: Vec<(FlowId, FlowInformation)> = (0..8).map(|_| tcp_flow()).collect();
+        let message_list = exporter.encode(flow_list.iter().map(|(k, v)| (k, v)), Duration::default());
+
+        assert_eq!(message_list.len(), 3);
+        assert!(message_list.iter().all(|message| message.len() <= 300));
+        assert_eq!(set_list(&message_list[0])[0].0, 2);
+        assert_eq!(set_list(&message_list[1]), vec![(IPV4_TEMPLATE_ID, 4 + 4 * 65)]);
+        assert_eq!(&message_list[1][8..12], &2u32.to_be_bytes());
+        assert_eq!(set_list(&message_list[2])[0].0, 2);
+        assert_eq!(&message_list[2][8..12], &6u32.to_be_bytes());
+        assert_eq!(exporter.sequence_number(), 8);
+    }
+
+    #[test]
+    fn it_can_export_to_a_file() {
+        let mut generator = Generator::new();
+        let (flow_id, flow_information) = ipv6_flow();
+        generator.add(flow_id, flow_information);
+        let (flow_id, flow_information) = tcp_flow();
+        generator.add(flow_id, flow_information);
+        let path = "target/write_flows.ipfix";
+        write_to_ipfix_file(&generator, path);
+
+        let content = fs::read(path).unwrap();
+        assert_eq!(read_u16(&content, 0), 10);
+        assert_eq!(read_u16(&content, 2) as usize, content.len());
+        // the flows are exported in flow order
+        let set_id_list: Vec<u16> = set_list(&content).iter().map(|(set_id, _)| *set_id).collect();
+        assert_eq!(set_id_list, vec![2, IPV4_TEMPLATE_ID, IPV6_TEMPLATE_ID]);
+    }
+
+    #[test]
+    fn it_cannot_export_to_a_file_in_a_missing_directory() {
+        let generator = Generator::new();
+        assert!(try_write_to_ipfix_file(&generator, "target/missing/write_flows.ipfix").is_err());
+    }
+
+    #[test]
+    fn it_can_export_to_a_udp_collector() {
+        let collector = UdpSocket::bind("127.0.0.1:0").unwrap();
+        collector.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.connect(collector.local_addr().unwrap()).unwrap();
+
+        let mut exporter = IpfixExporter::new(1);
+        assert_eq!(
+            exporter
+                .export_to_socket(&socket, vec![tcp_flow(), ipv6_flow()])
+                .unwrap(),
+            1
+        );
+
+        let mut buffer = [0u8; 2048];
+        let (length, source) = collector.recv_from(&mut buffer).unwrap();
+        assert_eq!(source, socket.local_addr().unwrap());
+        assert_eq!(read_u16(&buffer, 0), 10);
+        assert_eq!(read_u16(&buffer, 2) as usize, length);
+        assert_eq!(set_list(&buffer[..length]).len(), 3);
+    }
+}
@@ -0,0 +1,194 @@
+use std::borrow::Borrow;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::error::Result;
+use crate::flow_id::FlowId;
+use crate::flow_information::FlowInformation;
+
+/// A streaming reader of the JSON Lines format:
+/// one `[flow id, flow information]` record per line.
+/// The flows are read one by one, and the empty lines are skipped.
+pub struct JsonLinesReader<R: BufRead> {
+    reader: R,
+    line: String,
+}
+
+impl<R: BufRead> JsonLinesReader<R> {
+    /// Provide a reader positioned on the first line.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: String::new(),
+        }
+    }
+
+    /// Read the next flow, returns `None` at the end of the reader.
+    pub fn next_flow(&mut self) -> Result<Option<(FlowId, FlowInformation)>> {
+        loop {
+            self.line.clear();
+            if self.reader.read_line(&mut self.line)? == 0 {
+                return Ok(None);
+            }
+            if !self.line.trim().is_empty() {
+                return Ok(Some(serde_json::from_str(&self.line)?));
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for JsonLinesReader<R> {
+    type Item = Result<(FlowId, FlowInformation)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_flow().transpose()
+    }
+}
+
+/// A writer of the JSON Lines format, one `[flow id, flow information]` record per line.
+/// Each record is written as soon as it is added.
+pub struct JsonLinesWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JsonLinesWriter<W> {
+    /// Provide a writer appending the records to the given one.
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Write a flow on its own line.
+    pub fn write_flow(&mut self, flow_id: &FlowId, flow_information: &FlowInformation) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, &(flow_id, flow_information))?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Write the flows, owned or borrowed, such as the finished flows drained from a tracker,
+    /// and returns their number.
+    pub fn write_flows<I, K, V>(&mut self, flow_iter: I) -> Result<usize>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Borrow<FlowId>,
+        V: Borrow<FlowInformation>,
+    {
+        let mut count = 0;
+        for (flow_id, flow_information) in flow_iter {
+            self.write_flow(flow_id.borrow(), flow_information.borrow())?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Flush the written records.
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Open a JSON Lines file, and provide a reader of its flows.
+pub fn read_from_json_lines_file<P: AsRef<Path>>(path: P) -> Result<JsonLinesReader<BufReader<File>>> {
+    // open the file in read-only mode with buffer.
+    let file = File::open(path)?;
+    Ok(JsonLinesReader::new(BufReader::new(file)))
+}
+
+/// Open a JSON Lines file, created if missing, and provide a writer appending flows to it.
+/// The writer must be flushed once the flows are written.
+pub fn append_to_json_lines_file<P: AsRef<Path>>(path: P) -> Result<JsonLinesWriter<BufWriter<File>>> {
+    // open the file in append mode with buffer.
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    Ok(JsonLinesWriter::new(BufWriter::new(file)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+
+    use crate::error::{Error, Result};
+    use crate::flow_id::FlowId;
+    use crate::generator::Generator;
+    use crate::json_lines::{append_to_json_lines_file, read_from_json_lines_file, JsonLinesReader, JsonLinesWriter};
+    use crate::packet::Packet;
+    use crate::tracker::FlowTracker;
+
+    fn packet_at(seconds: u64) -> Packet {
+        let mut packet = Packet::new();
+        packet.timestamp = Duration::from_secs(seconds);
+        packet
+    }
+
+    #[test]
+    fn it_can_write_then_read_a_flow_per_line() {
+        let mut generator = Generator::new();
+        generator.add_packet(FlowId::new(17, "10.0.0.1", "10.0.0.2", 5353, 53), packet_at(1));
+        generator.add_packet(FlowId::new(17, "10.0.0.2", "10.0.0.1", 53, 5353), packet_at(2));
+        generator.add_packet(FlowId::new(6, "10.0.0.1", "10.0.0.3", 42254, 443), packet_at(3));
+
+        let mut writer = JsonLinesWriter::new(Vec::new());
+        assert_eq!(writer.write_flows(generator.sorted()).unwrap(), 2);
+        let content = writer.into_inner();
+        let line_list: Vec<&str> = std::str::from_utf8(&content).unwrap().lines().collect();
+        assert_eq!(
+            line_list,
+            vec![
+                r#"[{"src":"10.0.0.1","src_port":5353,"dst":"10.0.0.2","dst_port":53,"transport_protocol":17},{"client":{"ip":"10.0.0.1","port":5353},"server":{"ip":"10.0.0.2","port":53},"backward_packet_list":[{"length":0,"timestamp":{"secs":2,"nanos":0},"flag_list":[],"network_protocol":0,"position":0}],"forward_packet_list":[{"length":0,"timestamp":{"secs":1,"nanos":0},"flag_list":[],"network_protocol":0,"position":0}]}]"#,
+                r#"[{"src":"10.0.0.1","src_port":42254,"dst":"10.0.0.3","dst_port":443,"transport_protocol":6},{"client":{"ip":"10.0.0.1","port":42254},"server":{"ip":"10.0.0.3","port":443},"backward_packet_list":[],"forward_packet_list":[{"length":0,"timestamp":{"secs":3,"nanos":0},"flag_list":[],"network_protocol":0,"position":0}]}]"#,
+            ]
+        );
+
+        let flow_list: Vec<_> = JsonLinesReader::new(content.as_slice()).map(Result::unwrap).collect();
+        assert_eq!(flow_list.len(), 2);
+        let (flow_id, flow_information) = &flow_list[0];
+        assert_eq!(*flow_id, FlowId::new(17, "10.0.0.1", "10.0.0.2", 5353, 53));
+        assert_eq!(flow_information.forward_packet_list.len(), 1);
+        assert_eq!(flow_information.backward_packet_list.len(), 1);
+        assert_eq!(
+            flow_information.backward_packet_list[0].timestamp,
+            Duration::from_secs(2)
+        );
+        let (flow_id, flow_information) = &flow_list[1];
+        assert_eq!(flow_id.src_port, 42254);
+        assert_eq!(flow_id.transport_protocol, 6);
+        assert_eq!(flow_information.forward_packet_list.len(), 1);
+        assert!(flow_information.backward_packet_list.is_empty());
+    }
+
+    #[test]
+    fn it_can_append_the_flows_expired_by_a_tracker() {
+        let path = "target/append_tracker_flows.jsonl";
+        let _ = fs::remove_file(path);
+        let mut tracker = FlowTracker::new(Duration::from_secs(10), Duration::from_secs(100));
+
+        tracker.add_packet(FlowId::new(17, "10.0.0.1", "10.0.0.2", 5353, 53), packet_at(1));
+        tracker.add_packet(FlowId::new(17, "10.0.0.1", "10.0.0.3", 5353, 53), packet_at(20));
+        let mut writer = append_to_json_lines_file(path).unwrap();
+        assert_eq!(writer.write_flows(tracker.drain_finished()).unwrap(), 1);
+        writer.flush().unwrap();
+
+        tracker.flush();
+        let mut writer = append_to_json_lines_file(path).unwrap();
+        assert_eq!(writer.write_flows(tracker.drain_finished()).unwrap(), 1);
+        writer.flush().unwrap();
+
+        let flow_list: Vec<_> = read_from_json_lines_file(path).unwrap().map(Result::unwrap).collect();
+        assert_eq!(flow_list.len(), 2);
+        assert_eq!(flow_list[0].0.dst.to_string(), "10.0.0.2");
+        assert_eq!(flow_list[1].0.dst.to_string(), "10.0.0.3");
+    }
+
+    #[test]
+    fn it_cannot_read_a_malformed_line() {
+        let content = "\n[{\"src\":\"10.0.0.1\"}, {}]\n";
+        let mut reader = JsonLinesReader::new(content.as_bytes());
+        assert!(matches!(reader.next(), Some(Err(Error::Serialization(_)))));
+        assert!(reader.next().is_none());
+    }
+}
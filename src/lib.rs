@@ -1,5 +1,26 @@
+pub mod anonymizer;
+pub mod collector;
+pub mod decoder;
+pub mod direction;
+pub mod error;
+pub mod feature;
+pub mod filter;
+pub mod fingerprint;
 pub mod flag;
 pub mod flow_id;
 pub mod flow_information;
+pub mod flow_summary;
+pub mod fragment;
 pub mod generator;
+pub mod ipfix;
+pub mod json_lines;
 pub mod packet;
+pub mod pcap;
+pub mod pcapng;
+pub mod rtt;
+pub mod sequence;
+pub mod stream;
+pub mod tcp_analysis;
+pub mod tcp_state;
+pub mod tls;
+pub mod tracker;
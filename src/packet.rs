@@ -24,6 +24,18 @@ pub struct Packet {
     pub network_payload_length: Option<usize>,
     // position into the set considered
     pub position: usize,
+    /// identifier of the capture interface (pcapng), if any
+    pub interface_id: Option<u32>,
+    /// TCP sequence number
+    pub sequence_number: Option<u32>,
+    /// TCP acknowledgment number, when the ACK flag is set
+    pub acknowledgment_number: Option<u32>,
+    /// TCP urgent pointer, when the URG flag is set
+    pub urgent_pointer: Option<u16>,
+    /// TCP options, when the header has some
+    pub tcp_options: Option<TcpOptions>,
+    /// TCP payload size (number of bytes)
+    pub tcp_payload_length: Option<usize>,
 }
 
 impl Packet {
@@ -33,12 +45,41 @@ impl Packet {
     }
 }
 
+/// The parsed options of a TCP header.
+#[serde_with::skip_serializing_none]
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct TcpOptions {
+    /// maximum segment size
+    pub mss: Option<u16>,
+    /// window scale shift count
+    pub window_scale: Option<u8>,
+    /// SACK permitted
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sack_permitted: bool,
+    /// SACK blocks, as left and right edges
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sack_block_list: Vec<(u32, u32)>,
+    /// timestamp value
+    pub timestamp_value: Option<u32>,
+    /// timestamp echo reply
+    pub timestamp_echo_reply: Option<u32>,
+}
+
+impl TcpOptions {
+    /// Provide options without any value for now.
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+}
+
 #[cfg(test)]
+#[allow(clippy::needless_return)]
 mod tests {
     use std::collections::BTreeSet;
     use std::time::Duration;
 
-    use crate::packet::Packet;
+    use crate::error::Error;
+    use crate::packet::{Packet, TcpOptions};
 
     fn remove_whitespace(s: &str) -> String {
         s.split_whitespace().collect()
@@ -74,7 +115,18 @@ mod tests {
   "network_protocol": 17,
   "network_header_length": 5,
   "network_payload_length": 105,
-  "position": 1234
+  "position": 1234,
+  "sequence_number": 4000000000,
+  "acknowledgment_number": 42,
+  "urgent_pointer": 7,
+  "tcp_options": {
+    "mss": 1460,
+    "window_scale": 7,
+    "sack_permitted": true,
+    "sack_block_list": [[100, 200], [300, 400]],
+    "timestamp_value": 123456,
+    "timestamp_echo_reply": 654321
+  }
 }
 "#;
     }
@@ -143,6 +195,12 @@ mod tests {
         assert_eq!(default.network_payload_length, None);
         assert_eq!(default.network_header_length, None);
         assert_eq!(default.position, 0);
+        assert_eq!(default.interface_id, None);
+        assert_eq!(default.sequence_number, None);
+        assert_eq!(default.acknowledgment_number, None);
+        assert_eq!(default.urgent_pointer, None);
+        assert_eq!(default.tcp_options, None);
+        assert_eq!(default.tcp_payload_length, None);
     }
 
     #[test]
@@ -156,6 +214,24 @@ mod tests {
         assert_eq!(new.network_payload_length, None);
         assert_eq!(new.network_header_length, None);
         assert_eq!(new.position, 0);
+        assert_eq!(new.interface_id, None);
+        assert_eq!(new.sequence_number, None);
+        assert_eq!(new.acknowledgment_number, None);
+        assert_eq!(new.urgent_pointer, None);
+        assert_eq!(new.tcp_options, None);
+        assert_eq!(new.tcp_payload_length, None);
+    }
+
+    #[test]
+    fn test_new_tcp_options() {
+        let new = TcpOptions::new();
+        assert_eq!(new.mss, None);
+        assert_eq!(new.window_scale, None);
+        assert!(!new.sack_permitted);
+        assert!(new.sack_block_list.is_empty());
+        assert_eq!(new.timestamp_value, None);
+        assert_eq!(new.timestamp_echo_reply, None);
+        assert_eq!(serde_json::to_string(&new).unwrap(), "{}");
     }
 
     #[test]
@@ -171,27 +247,29 @@ mod tests {
         let json = complete_packet();
         let packet: Packet = serde_json::from_str(json).unwrap();
         assert_eq!(packet.timestamp.as_nanos(), 1595325118502092010);
+        let tcp_options = packet.tcp_options.as_ref().unwrap();
+        assert_eq!(tcp_options.sack_block_list, vec![(100, 200), (300, 400)]);
         assert_eq!(serde_json::to_string(&packet).unwrap(), remove_whitespace(json));
     }
 
     #[test]
-    #[should_panic]
-    fn it_should_panic_when_deserializing_a_packet_with_u32_position() {
+    fn it_cannot_deserialize_a_packet_with_u32_position() {
         let json = bad_packet_with_u32_position();
-        let _: Packet = serde_json::from_str(json).unwrap();
+        let result = serde_json::from_str::<Packet>(json).map_err(Error::from);
+        assert!(matches!(result, Err(Error::Serialization(_))));
     }
 
     #[test]
-    #[should_panic]
-    fn it_should_panic_when_deserializing_a_packet_with_string_network() {
+    fn it_cannot_deserialize_a_packet_with_string_network() {
         let json = bad_packet_with_string_network_protocol();
-        let _: Packet = serde_json::from_str(json).unwrap();
+        let result = serde_json::from_str::<Packet>(json).map_err(Error::from);
+        assert!(matches!(result, Err(Error::Serialization(_))));
     }
 
     #[test]
-    #[should_panic]
-    fn it_should_panic_when_deserializing_a_packet_without_flag_list() {
+    fn it_cannot_deserialize_a_packet_without_flag_list() {
         let json = bad_packet_without_flag_list();
-        let _: Packet = serde_json::from_str(json).unwrap();
+        let result = serde_json::from_str::<Packet>(json).map_err(Error::from);
+        assert!(matches!(result, Err(Error::Serialization(_))));
     }
 }
@@ -0,0 +1,373 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufReader, ErrorKind, Read};
+use std::path::Path;
+use std::time::Duration;
+
+use log::debug;
+
+use crate::decoder;
+use crate::error::Result;
+use crate::fragment::FragmentReassembler;
+use crate::generator::Generator;
+
+/// magic number of a pcap file with microsecond timestamps
+const MAGIC_MICROSECONDS: u32 = 0xA1B2_C3D4;
+/// magic number of a pcap file with nanosecond timestamps
+const MAGIC_NANOSECONDS: u32 = 0xA1B2_3C4D;
+
+const FILE_HEADER_LENGTH: usize = 24;
+const RECORD_HEADER_LENGTH: usize = 16;
+/// upper bound of a captured length, protects against a corrupted record header
+const MAXIMUM_CAPTURED_LENGTH: u32 = 0x0100_0000;
+
+/// A frame read from a capture file.
+#[derive(Debug, Default)]
+pub struct CapturedFrame {
+    /// link layer type of the data (see the `decoder::LINKTYPE_*` constants)
+    pub link_type: u32,
+    /// capture timestamp since the epoch
+    pub timestamp: Duration,
+    /// length of the frame on the wire (number of bytes)
+    pub original_length: u32,
+    /// captured bytes, potentially truncated to the snapshot length
+    pub data: Vec<u8>,
+    /// identifier of the capture interface, only known from a pcapng file
+    pub interface_id: Option<u32>,
+}
+
+/// A reader of the classic libpcap file format.
+/// Microsecond and nanosecond timestamp resolutions are supported,
+/// in both byte orders.
+pub struct PcapReader<R: Read> {
+    reader: R,
+    big_endian: bool,
+    nanosecond: bool,
+    link_type: u32,
+}
+
+impl<R: Read> PcapReader<R> {
+    /// Read the file header, and provide a reader positioned on the first record.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0u8; FILE_HEADER_LENGTH];
+        reader.read_exact(&mut header)?;
+
+        let (big_endian, nanosecond) = match u32::from_le_bytes([header[0], header[1], header[2], header[3]]) {
+            MAGIC_MICROSECONDS => (false, false),
+            MAGIC_NANOSECONDS => (false, true),
+            magic if magic.swap_bytes() == MAGIC_MICROSECONDS => (true, false),
+            magic if magic.swap_bytes() == MAGIC_NANOSECONDS => (true, true),
+            magic => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unknown pcap magic number {:#010x}", magic),
+                ))
+            }
+        };
+
+        let mut pcap_reader = Self {
+            reader,
+            big_endian,
+            nanosecond,
+            link_type: 0,
+        };
+        // the upper bits of the link type field may carry FCS information
+        pcap_reader.link_type = pcap_reader.read_u32(&header, 20) & 0x0FFF_FFFF;
+        Ok(pcap_reader)
+    }
+
+    /// Returns the link layer type of every frame of the file.
+    pub fn link_type(&self) -> u32 {
+        self.link_type
+    }
+
+    /// Read the next frame, `None` at the end of the file.
+    pub fn next_frame(&mut self) -> io::Result<Option<CapturedFrame>> {
+        let mut header = [0u8; RECORD_HEADER_LENGTH];
+        if !read_exact_or_eof(&mut self.reader, &mut header)? {
+            return Ok(None);
+        }
+
+        let seconds = self.read_u32(&header, 0);
+        let fraction = self.read_u32(&header, 4);
+        let captured_length = self.read_u32(&header, 8);
+        let original_length = self.read_u32(&header, 12);
+        if captured_length > MAXIMUM_CAPTURED_LENGTH {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("pcap record captured length {} is too large", captured_length),
+            ));
+        }
+
+        let mut data = vec![0u8; captured_length as usize];
+        self.reader.read_exact(&mut data)?;
+
+        let timestamp = if self.nanosecond {
+            Duration::new(seconds as u64, 0) + Duration::from_nanos(fraction as u64)
+        } else {
+            Duration::new(seconds as u64, 0) + Duration::from_micros(fraction as u64)
+        };
+
+        Ok(Some(CapturedFrame {
+            link_type: self.link_type,
+            timestamp,
+            original_length,
+            data,
+            interface_id: None,
+        }))
+    }
+
+    fn read_u32(&self, buffer: &[u8], offset: usize) -> u32 {
+        let bytes = [
+            buffer[offset],
+            buffer[offset + 1],
+            buffer[offset + 2],
+            buffer[offset + 3],
+        ];
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+}
+
+impl<R: Read> Iterator for PcapReader<R> {
+    type Item = io::Result<CapturedFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
+
+/// Read a pcap file, and group its frames into the flows of a generator.
+/// The frames which can't be decoded are skipped.
+/// Panics if the file can't be read, see `try_read_from_pcap_file`.
+pub fn read_from_pcap_file<P: AsRef<Path>>(path: P) -> Generator {
+    try_read_from_pcap_file(path).unwrap()
+}
+
+/// Read a pcap file into a generator,
+/// or returns an error if the file can't be read.
+/// The frames which can't be decoded are skipped.
+pub fn try_read_from_pcap_file<P: AsRef<Path>>(path: P) -> Result<Generator> {
+    // open the file in read-only mode with buffer.
+    let file = File::open(path)?;
+    let reader = PcapReader::new(BufReader::new(file))?;
+
+    let mut generator = Generator::new();
+    let mut reassembler = FragmentReassembler::new();
+    for (index, frame) in reader.enumerate() {
+        // positions start at 1, as the frame numbers of the capture tools
+        add_frame(&mut generator, &mut reassembler, &frame?, index + 1);
+    }
+    Ok(generator)
+}
+
+/// Reassemble then decode a captured frame, and add the resulting packet to its flow.
+/// A reassembled datagram is decoded on its last fragment,
+/// its length being the one of a frame carrying it whole: the link layer header of its last fragment and the datagram.
+pub(crate) fn add_frame(
+    generator: &mut Generator,
+    reassembler: &mut FragmentReassembler,
+    frame: &CapturedFrame,
+    position: usize,
+) {
+    let (link_type, data) = match reassembler.push(frame.link_type, &frame.data, frame.timestamp) {
+        Some(frame_data) => frame_data,
+        None => return,
+    };
+    match decoder::decode_with_payload(link_type, &data) {
+        Ok((flow_id, mut packet, payload)) => {
+            packet.timestamp = frame.timestamp;
+            packet.length = match data {
+                Cow::Borrowed(_) => frame.original_length as u64,
+                Cow::Owned(ref datagram) => {
+                    let link_header_length =
+                        decoder::link_header_length(frame.link_type, &frame.data).unwrap_or_default();
+                    (link_header_length + datagram.len()) as u64
+                }
+            };
+            packet.position = position;
+            packet.interface_id = frame.interface_id;
+            generator.add_packet_with_payload(flow_id, packet, payload);
+        }
+        Err(error) => debug!("frame {} skipped: {}", position, error),
+    }
+}
+
+/// Fill the buffer, returns `false` if the end of the reader is reached before any byte.
+pub(crate) fn read_exact_or_eof<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(read) => filled += read,
+            Err(error) if error.kind() == ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{ErrorKind, Write};
+    use std::time::Duration;
+
+    use crate::decoder::{ETHERTYPE_IPV4, LINKTYPE_ETHERNET};
+    use crate::error::Error;
+    use crate::flow_id::FlowId;
+    use crate::pcap::{read_from_pcap_file, try_read_from_pcap_file, PcapReader};
+
+    fn udp_frame(src: [u8; 4], dst: [u8; 4], src_port: u16, dst_port: u16) -> Vec<u8> {
+        let mut frame = vec![
+            // ethernet: dst, src, IPv4 ether type
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0x08, 0x00,
+            // IPv4: version 4, IHL 5, total length 28, TTL 64, UDP
+            0x45, 0x00, 0x00, 0x1C, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00,
+        ];
+        frame.extend_from_slice(&src);
+        frame.extend_from_slice(&dst);
+        frame.extend_from_slice(&src_port.to_be_bytes());
+        frame.extend_from_slice(&dst_port.to_be_bytes());
+        frame.extend_from_slice(&[0x00, 0x08, 0x00, 0x00]);
+        frame
+    }
+
+    /// Build a pcap file content, the frames are (seconds, fraction, data).
+    fn pcap_file(magic: u32, big_endian: bool, frames: &[(u32, u32, Vec<u8>)]) -> Vec<u8> {
+        let u16_bytes = |value: u16| {
+            if big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            }
+        };
+        let u32_bytes = |value: u32| {
+            if big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            }
+        };
+
+        let mut file = Vec::new();
+        file.extend_from_slice(&u32_bytes(magic));
+        file.extend_from_slice(&u16_bytes(2));
+        file.extend_from_slice(&u16_bytes(4));
+        file.extend_from_slice(&u32_bytes(0));
+        file.extend_from_slice(&u32_bytes(0));
+        file.extend_from_slice(&u32_bytes(65535));
+        file.extend_from_slice(&u32_bytes(LINKTYPE_ETHERNET));
+        for (seconds, fraction, data) in frames {
+            file.extend_from_slice(&u32_bytes(*seconds));
+            file.extend_from_slice(&u32_bytes(*fraction));
+            file.extend_from_slice(&u32_bytes(data.len() as u32));
+            // pretend the frame was 4 bytes longer on the wire
+            file.extend_from_slice(&u32_bytes(data.len() as u32 + 4));
+            file.extend_from_slice(data);
+        }
+        file
+    }
+
+    fn conversation() -> Vec<(u32, u32, Vec<u8>)> {
+        vec![
+            (1595324876, 73920, udp_frame([10, 0, 0, 1], [10, 0, 0, 2], 5353, 53)),
+            (1595324877, 1, udp_frame([10, 0, 0, 2], [10, 0, 0, 1], 53, 5353)),
+            (1595324878, 2, udp_frame([10, 0, 0, 1], [10, 0, 0, 3], 5353, 53)),
+        ]
+    }
+
+    #[test]
+    fn it_can_read_a_little_endian_microsecond_pcap() {
+        let file = pcap_file(0xA1B2C3D4, false, &conversation());
+        let mut reader = PcapReader::new(file.as_slice()).unwrap();
+        assert_eq!(reader.link_type(), LINKTYPE_ETHERNET);
+
+        let frame = reader.next_frame().unwrap().unwrap();
+        assert_eq!(frame.link_type, LINKTYPE_ETHERNET);
+        assert_eq!(frame.timestamp, Duration::new(1595324876, 73920000));
+        assert_eq!(frame.original_length, 46);
+        assert_eq!(frame.data.len(), 42);
+        assert_eq!(reader.count(), 2);
+    }
+
+    #[test]
+    fn it_can_read_a_big_endian_nanosecond_pcap() {
+        let file = pcap_file(0xA1B23C4D, true, &conversation());
+        let mut reader = PcapReader::new(file.as_slice()).unwrap();
+        assert_eq!(reader.link_type(), LINKTYPE_ETHERNET);
+
+        let frame = reader.next_frame().unwrap().unwrap();
+        assert_eq!(frame.timestamp, Duration::new(1595324876, 73920));
+        assert_eq!(frame.original_length, 46);
+        assert_eq!(reader.count(), 2);
+    }
+
+    #[test]
+    fn it_can_read_a_big_endian_microsecond_pcap() {
+        let file = pcap_file(0xA1B2C3D4, true, &conversation());
+        let frames: Vec<_> = PcapReader::new(file.as_slice()).unwrap().map(Result::unwrap).collect();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[1].timestamp, Duration::new(1595324877, 1000));
+    }
+
+    #[test]
+    fn it_cannot_read_an_unknown_magic_number() {
+        let file = pcap_file(0x0A0D0D0A, false, &conversation());
+        let error = PcapReader::new(file.as_slice()).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn it_cannot_read_a_truncated_record() {
+        let file = pcap_file(0xA1B2C3D4, false, &conversation());
+        let mut reader = PcapReader::new(&file[..file.len() - 1]).unwrap();
+        assert!(reader.next_frame().unwrap().is_some());
+        assert!(reader.next_frame().unwrap().is_some());
+        assert_eq!(reader.next_frame().err().unwrap().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn it_cannot_read_a_truncated_pcap_file_into_a_generator() {
+        let path = "target/read_truncated_conversation.pcap";
+        let content = pcap_file(0xA1B2C3D4, false, &conversation());
+        let mut file = File::create(path).unwrap();
+        file.write_all(&content[..content.len() - 1]).unwrap();
+
+        match try_read_from_pcap_file(path) {
+            Err(Error::Io(error)) => assert_eq!(error.kind(), ErrorKind::UnexpectedEof),
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+
+    #[test]
+    fn it_can_read_a_pcap_file_into_a_generator() {
+        let path = "target/read_conversation.pcap";
+        let mut file = File::create(path).unwrap();
+        file.write_all(&pcap_file(0xA1B2C3D4, false, &conversation())).unwrap();
+
+        let mut generator = read_from_pcap_file(path);
+
+        assert_eq!(generator.len(), 2);
+        let flow_information = generator
+            .get_mut(&FlowId::new(17, "10.0.0.1", "10.0.0.2", 5353, 53))
+            .unwrap();
+        assert_eq!(flow_information.forward_packet_list.len(), 1);
+        assert_eq!(flow_information.backward_packet_list.len(), 1);
+        let packet = &flow_information.backward_packet_list[0];
+        assert_eq!(packet.timestamp, Duration::new(1595324877, 1000));
+        assert_eq!(packet.length, 46);
+        assert_eq!(packet.network_protocol, ETHERTYPE_IPV4);
+        assert_eq!(packet.position, 2);
+
+        let flow_information = generator
+            .get_mut(&FlowId::new(17, "10.0.0.3", "10.0.0.1", 53, 5353))
+            .unwrap();
+        assert_eq!(flow_information.forward_packet_list.len(), 1);
+        assert_eq!(flow_information.forward_packet_list[0].position, 3);
+    }
+}
@@ -0,0 +1,602 @@
+use std::fs::File;
+use std::io::{self, BufReader, ErrorKind, Read};
+use std::path::Path;
+use std::time::Duration;
+
+use log::debug;
+
+use crate::error::Result;
+use crate::fragment::FragmentReassembler;
+use crate::generator::Generator;
+use crate::pcap::{add_frame, read_exact_or_eof, CapturedFrame};
+
+/// Section Header Block type, also the pcapng magic number
+const SECTION_HEADER_BLOCK: u32 = 0x0A0D_0D0A;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 0x0000_0001;
+/// obsolete Packet Block, still written by some old tools
+const PACKET_BLOCK: u32 = 0x0000_0002;
+const SIMPLE_PACKET_BLOCK: u32 = 0x0000_0003;
+const ENHANCED_PACKET_BLOCK: u32 = 0x0000_0006;
+
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const OPTION_END_OF_OPTIONS: u16 = 0;
+const OPTION_IF_TSRESOL: u16 = 9;
+const OPTION_IF_TSOFFSET: u16 = 14;
+
+/// upper bound of a block length, protects against a corrupted block header
+const MAXIMUM_BLOCK_LENGTH: u32 = 0x0100_0000;
+
+/// An interface described into a section of a pcapng file.
+#[derive(Clone, Debug)]
+pub struct Interface {
+    /// link layer type of the interface frames
+    pub link_type: u32,
+    /// maximum number of bytes captured from each frame, 0 if unlimited
+    pub snap_length: u32,
+    /// timestamp resolution (if_tsresol option): the most significant bit
+    /// selects a power of 2 instead of a power of 10, the other bits the negative exponent
+    pub timestamp_resolution: u8,
+    /// offset in seconds added to each timestamp (if_tsoffset option)
+    pub timestamp_offset: i64,
+}
+
+impl Default for Interface {
+    /// Create an interface with the microsecond default resolution.
+    fn default() -> Self {
+        Self {
+            link_type: 0,
+            snap_length: 0,
+            timestamp_resolution: 6,
+            timestamp_offset: 0,
+        }
+    }
+}
+
+impl Interface {
+    /// Convert a timestamp expressed in the interface resolution.
+    pub fn to_duration(&self, timestamp: u64) -> Duration {
+        let exponent = (self.timestamp_resolution & 0x7F) as u32;
+        let units_per_second: u128 = if self.timestamp_resolution & 0x80 == 0 {
+            10u128.saturating_pow(exponent)
+        } else {
+            2u128.saturating_pow(exponent)
+        };
+        let seconds = timestamp as u128 / units_per_second;
+        let nanos = (timestamp as u128 % units_per_second) * 1_000_000_000 / units_per_second;
+        let seconds = (seconds as i128 + self.timestamp_offset as i128).max(0);
+        Duration::new(seconds as u64, nanos as u32)
+    }
+}
+
+/// A reader of the pcapng file format.
+/// A file may contain several sections, of any byte order,
+/// and each section several interfaces, of any link type and timestamp resolution.
+pub struct PcapNgReader<R: Read> {
+    reader: R,
+    big_endian: bool,
+    interface_list: Vec<Interface>,
+}
+
+impl<R: Read> PcapNgReader<R> {
+    /// Read the first Section Header Block, and provide a reader positioned on the next block.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut block_type = [0u8; 4];
+        reader.read_exact(&mut block_type)?;
+        if u32::from_le_bytes(block_type) != SECTION_HEADER_BLOCK {
+            return Err(io::Error::new(ErrorKind::InvalidData, "not a pcapng file"));
+        }
+
+        let mut pcapng_reader = Self {
+            reader,
+            big_endian: false,
+            interface_list: Vec::new(),
+        };
+        pcapng_reader.read_section_header()?;
+        Ok(pcapng_reader)
+    }
+
+    /// Returns the interfaces of the current section, indexed by their identifier.
+    pub fn interface_list(&self) -> &[Interface] {
+        &self.interface_list
+    }
+
+    /// Read the next frame, `None` at the end of the file.
+    /// The blocks which don't carry a frame are consumed on the way.
+    pub fn next_frame(&mut self) -> io::Result<Option<CapturedFrame>> {
+        loop {
+            let mut block_type = [0u8; 4];
+            if !read_exact_or_eof(&mut self.reader, &mut block_type)? {
+                return Ok(None);
+            }
+            let block_type = self.to_u32(&block_type, 0);
+            if block_type == SECTION_HEADER_BLOCK {
+                self.read_section_header()?;
+                continue;
+            }
+
+            let body = self.read_block_body()?;
+            let frame = match block_type {
+                INTERFACE_DESCRIPTION_BLOCK => {
+                    self.read_interface_description(&body)?;
+                    None
+                }
+                ENHANCED_PACKET_BLOCK => Some(self.read_enhanced_packet(&body)?),
+                SIMPLE_PACKET_BLOCK => Some(self.read_simple_packet(&body)?),
+                PACKET_BLOCK => Some(self.read_packet(&body)?),
+                _ => {
+                    debug!("pcapng block {:#010x} skipped", block_type);
+                    None
+                }
+            };
+            if frame.is_some() {
+                return Ok(frame);
+            }
+        }
+    }
+
+    /// Read a Section Header Block following its type, the byte order of the section is set,
+    /// and the interfaces of the previous section are forgotten.
+    #[allow(clippy::manual_is_multiple_of)]
+    fn read_section_header(&mut self) -> io::Result<()> {
+        let mut header = [0u8; 8];
+        self.reader.read_exact(&mut header)?;
+        self.big_endian = match u32::from_le_bytes([header[4], header[5], header[6], header[7]]) {
+            BYTE_ORDER_MAGIC => false,
+            magic if magic.swap_bytes() == BYTE_ORDER_MAGIC => true,
+            magic => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unknown pcapng byte order magic {:#010x}", magic),
+                ))
+            }
+        };
+        let block_length = self.to_u32(&header, 0);
+        if block_length < 28 || block_length % 4 != 0 || block_length > MAXIMUM_BLOCK_LENGTH {
+            return Err(invalid_block_length(block_length));
+        }
+        // skip the version, the section length, the options and the trailing block length
+        let mut remaining = vec![0u8; block_length as usize - 12];
+        self.reader.read_exact(&mut remaining)?;
+        self.interface_list.clear();
+        Ok(())
+    }
+
+    /// Read the length of the block, then its body without the trailing length.
+    #[allow(clippy::manual_is_multiple_of)]
+    fn read_block_body(&mut self) -> io::Result<Vec<u8>> {
+        let mut length = [0u8; 4];
+        self.reader.read_exact(&mut length)?;
+        let block_length = self.to_u32(&length, 0);
+        if block_length < 12 || block_length % 4 != 0 || block_length > MAXIMUM_BLOCK_LENGTH {
+            return Err(invalid_block_length(block_length));
+        }
+        let mut body = vec![0u8; block_length as usize - 8];
+        self.reader.read_exact(&mut body)?;
+        body.truncate(block_length as usize - 12);
+        Ok(body)
+    }
+
+    fn read_interface_description(&mut self, body: &[u8]) -> io::Result<()> {
+        if body.len() < 8 {
+            return Err(truncated_block("Interface Description"));
+        }
+        let mut interface = Interface {
+            link_type: self.to_u16(body, 0) as u32,
+            snap_length: self.to_u32(body, 4),
+            ..Default::default()
+        };
+
+        let mut offset = 8;
+        while offset + 4 <= body.len() {
+            let code = self.to_u16(body, offset);
+            let length = self.to_u16(body, offset + 2) as usize;
+            let value = body
+                .get(offset + 4..offset + 4 + length)
+                .ok_or_else(|| truncated_block("Interface Description option"))?;
+            match code {
+                OPTION_END_OF_OPTIONS => break,
+                OPTION_IF_TSRESOL if length == 1 => interface.timestamp_resolution = value[0],
+                OPTION_IF_TSOFFSET if length == 8 => {
+                    interface.timestamp_offset = self.to_u64(value, 0) as i64;
+                }
+                _ => {}
+            }
+            // option values are padded to 32 bits
+            offset += 4 + length.div_ceil(4) * 4;
+        }
+
+        self.interface_list.push(interface);
+        Ok(())
+    }
+
+    fn read_enhanced_packet(&self, body: &[u8]) -> io::Result<CapturedFrame> {
+        if body.len() < 20 {
+            return Err(truncated_block("Enhanced Packet"));
+        }
+        let interface_id = self.to_u32(body, 0);
+        let timestamp = (self.to_u32(body, 4) as u64) << 32 | self.to_u32(body, 8) as u64;
+        let captured_length = self.to_u32(body, 12) as usize;
+        let original_length = self.to_u32(body, 16);
+        let data = body
+            .get(20..20 + captured_length)
+            .ok_or_else(|| truncated_block("Enhanced Packet"))?;
+        self.to_frame(interface_id, timestamp, original_length, data)
+    }
+
+    fn read_simple_packet(&self, body: &[u8]) -> io::Result<CapturedFrame> {
+        if body.len() < 4 {
+            return Err(truncated_block("Simple Packet"));
+        }
+        let original_length = self.to_u32(body, 0);
+        // the captured length is the original one, bounded by the snapshot length and the padding
+        let mut captured_length = (original_length as usize).min(body.len() - 4);
+        match self.interface_list.first() {
+            Some(interface) if interface.snap_length != 0 => {
+                captured_length = captured_length.min(interface.snap_length as usize)
+            }
+            _ => {}
+        }
+        // no timestamp into a simple packet block
+        self.to_frame(0, 0, original_length, &body[4..4 + captured_length])
+    }
+
+    fn read_packet(&self, body: &[u8]) -> io::Result<CapturedFrame> {
+        if body.len() < 20 {
+            return Err(truncated_block("Packet"));
+        }
+        let interface_id = self.to_u16(body, 0) as u32;
+        let timestamp = (self.to_u32(body, 4) as u64) << 32 | self.to_u32(body, 8) as u64;
+        let captured_length = self.to_u32(body, 12) as usize;
+        let original_length = self.to_u32(body, 16);
+        let data = body
+            .get(20..20 + captured_length)
+            .ok_or_else(|| truncated_block("Packet"))?;
+        self.to_frame(interface_id, timestamp, original_length, data)
+    }
+
+    fn to_frame(
+        &self,
+        interface_id: u32,
+        timestamp: u64,
+        original_length: u32,
+        data: &[u8],
+    ) -> io::Result<CapturedFrame> {
+        let interface = self.interface_list.get(interface_id as usize).ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("pcapng frame of the undescribed interface {}", interface_id),
+            )
+        })?;
+        Ok(CapturedFrame {
+            link_type: interface.link_type,
+            timestamp: interface.to_duration(timestamp),
+            original_length,
+            data: data.to_vec(),
+            interface_id: Some(interface_id),
+        })
+    }
+
+    fn to_u16(&self, buffer: &[u8], offset: usize) -> u16 {
+        let bytes = [buffer[offset], buffer[offset + 1]];
+        if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+
+    fn to_u32(&self, buffer: &[u8], offset: usize) -> u32 {
+        let bytes = [
+            buffer[offset],
+            buffer[offset + 1],
+            buffer[offset + 2],
+            buffer[offset + 3],
+        ];
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+
+    fn to_u64(&self, buffer: &[u8], offset: usize) -> u64 {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&buffer[offset..offset + 8]);
+        if self.big_endian {
+            u64::from_be_bytes(bytes)
+        } else {
+            u64::from_le_bytes(bytes)
+        }
+    }
+}
+
+impl<R: Read> Iterator for PcapNgReader<R> {
+    type Item = io::Result<CapturedFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
+
+fn invalid_block_length(block_length: u32) -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidData,
+        format!("invalid pcapng block length {}", block_length),
+    )
+}
+
+fn truncated_block(block_name: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("truncated pcapng {} block", block_name))
+}
+
+/// Read a pcapng file, and group its frames into the flows of a generator.
+/// Each packet keeps the identifier of the interface which captured it.
+/// The frames which can't be decoded are skipped.
+/// Panics if the file can't be read, see `try_read_from_pcapng_file`.
+pub fn read_from_pcapng_file<P: AsRef<Path>>(path: P) -> Generator {
+    try_read_from_pcapng_file(path).unwrap()
+}
+
+/// Read a pcapng file into a generator,
+/// or returns an error if the file can't be read.
+/// The frames which can't be decoded are skipped.
+pub fn try_read_from_pcapng_file<P: AsRef<Path>>(path: P) -> Result<Generator> {
+    // open the file in read-only mode with buffer.
+    let file = File::open(path)?;
+    let reader = PcapNgReader::new(BufReader::new(file))?;
+
+    let mut generator = Generator::new();
+    let mut reassembler = FragmentReassembler::new();
+    for (index, frame) in reader.enumerate() {
+        // positions start at 1, as the frame numbers of the capture tools
+        add_frame(&mut generator, &mut reassembler, &frame?, index + 1);
+    }
+    Ok(generator)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{ErrorKind, Write};
+    use std::time::Duration;
+
+    use crate::decoder::{LINKTYPE_ETHERNET, LINKTYPE_RAW};
+    use crate::flow_id::FlowId;
+    use crate::pcapng::{read_from_pcapng_file, Interface, PcapNgReader};
+
+    fn ipv4_udp_datagram(src: [u8; 4], dst: [u8; 4], src_port: u16, dst_port: u16) -> Vec<u8> {
+        let mut datagram = vec![
+            // IPv4: version 4, IHL 5, total length 28, TTL 64, UDP
+            0x45, 0x00, 0x00, 0x1C, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00,
+        ];
+        datagram.extend_from_slice(&src);
+        datagram.extend_from_slice(&dst);
+        datagram.extend_from_slice(&src_port.to_be_bytes());
+        datagram.extend_from_slice(&dst_port.to_be_bytes());
+        datagram.extend_from_slice(&[0x00, 0x08, 0x00, 0x00]);
+        datagram
+    }
+
+    fn ethernet_frame(datagram: Vec<u8>) -> Vec<u8> {
+        let mut frame = vec![
+            // ethernet: dst, src, IPv4 ether type
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0x08, 0x00,
+        ];
+        frame.extend(datagram);
+        frame
+    }
+
+    /// A pcapng file content builder, in a given byte order.
+    struct Builder {
+        big_endian: bool,
+        content: Vec<u8>,
+    }
+
+    impl Builder {
+        fn new(big_endian: bool) -> Self {
+            let mut builder = Self {
+                big_endian,
+                content: Vec::new(),
+            };
+            builder.section_header();
+            builder
+        }
+
+        fn u16_bytes(&self, value: u16) -> [u8; 2] {
+            if self.big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            }
+        }
+
+        fn u32_bytes(&self, value: u32) -> [u8; 4] {
+            if self.big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            }
+        }
+
+        #[allow(clippy::manual_is_multiple_of)]
+        fn block(&mut self, block_type: u32, mut body: Vec<u8>) -> &mut Self {
+            while body.len() % 4 != 0 {
+                body.push(0);
+            }
+            let length = self.u32_bytes(body.len() as u32 + 12);
+            let block_type = self.u32_bytes(block_type);
+            self.content.extend_from_slice(&block_type);
+            self.content.extend_from_slice(&length);
+            self.content.extend(body);
+            self.content.extend_from_slice(&length);
+            self
+        }
+
+        fn section_header(&mut self) -> &mut Self {
+            let mut body = Vec::new();
+            body.extend_from_slice(&self.u32_bytes(0x1A2B3C4D));
+            body.extend_from_slice(&self.u16_bytes(1));
+            body.extend_from_slice(&self.u16_bytes(0));
+            // unknown section length
+            body.extend_from_slice(&[0xFF; 8]);
+            self.block(0x0A0D0D0A, body)
+        }
+
+        fn interface_description(&mut self, link_type: u16, timestamp_resolution: Option<u8>) -> &mut Self {
+            let mut body = Vec::new();
+            body.extend_from_slice(&self.u16_bytes(link_type));
+            body.extend_from_slice(&self.u16_bytes(0));
+            body.extend_from_slice(&self.u32_bytes(0));
+            if let Some(timestamp_resolution) = timestamp_resolution {
+                body.extend_from_slice(&self.u16_bytes(9));
+                body.extend_from_slice(&self.u16_bytes(1));
+                body.extend_from_slice(&[timestamp_resolution, 0, 0, 0]);
+                body.extend_from_slice(&[0, 0, 0, 0]);
+            }
+            self.block(1, body)
+        }
+
+        fn enhanced_packet(&mut self, interface_id: u32, timestamp: u64, data: Vec<u8>) -> &mut Self {
+            let mut body = Vec::new();
+            body.extend_from_slice(&self.u32_bytes(interface_id));
+            body.extend_from_slice(&self.u32_bytes((timestamp >> 32) as u32));
+            body.extend_from_slice(&self.u32_bytes(timestamp as u32));
+            body.extend_from_slice(&self.u32_bytes(data.len() as u32));
+            body.extend_from_slice(&self.u32_bytes(data.len() as u32));
+            body.extend(data);
+            self.block(6, body)
+        }
+
+        fn simple_packet(&mut self, data: Vec<u8>) -> &mut Self {
+            let mut body = Vec::new();
+            body.extend_from_slice(&self.u32_bytes(data.len() as u32));
+            body.extend(data);
+            self.block(3, body)
+        }
+    }
+
+    fn two_interface_capture(big_endian: bool) -> Vec<u8> {
+        let mut builder = Builder::new(big_endian);
+        builder
+            .interface_description(LINKTYPE_ETHERNET as u16, None)
+            .interface_description(LINKTYPE_RAW as u16, Some(9))
+            // a name resolution block, to skip
+            .block(4, vec![0, 0, 0, 0])
+            .enhanced_packet(
+                0,
+                1_595_324_876_073_920,
+                ethernet_frame(ipv4_udp_datagram([10, 0, 0, 1], [10, 0, 0, 2], 5353, 53)),
+            )
+            .enhanced_packet(
+                1,
+                1_595_324_877_000_000_001,
+                ipv4_udp_datagram([10, 0, 0, 2], [10, 0, 0, 1], 53, 5353),
+            );
+        builder.content
+    }
+
+    #[test]
+    fn test_interface_to_duration() {
+        let microsecond = Interface::default();
+        assert_eq!(microsecond.to_duration(1_500_001), Duration::new(1, 500_001_000));
+
+        let nanosecond = Interface {
+            timestamp_resolution: 9,
+            ..Default::default()
+        };
+        assert_eq!(nanosecond.to_duration(1_500_000_001), Duration::new(1, 500_000_001));
+
+        let binary = Interface {
+            timestamp_resolution: 0x80 | 10,
+            timestamp_offset: 100,
+            ..Default::default()
+        };
+        assert_eq!(binary.to_duration(1024 + 512), Duration::new(101, 500_000_000));
+    }
+
+    #[test]
+    fn it_can_read_a_little_endian_pcapng_with_two_interfaces() {
+        let file = two_interface_capture(false);
+        let mut reader = PcapNgReader::new(file.as_slice()).unwrap();
+
+        let frame = reader.next_frame().unwrap().unwrap();
+        assert_eq!(reader.interface_list().len(), 2);
+        assert_eq!(frame.interface_id, Some(0));
+        assert_eq!(frame.link_type, LINKTYPE_ETHERNET);
+        assert_eq!(frame.timestamp, Duration::new(1595324876, 73920000));
+        assert_eq!(frame.original_length, 42);
+
+        let frame = reader.next_frame().unwrap().unwrap();
+        assert_eq!(frame.interface_id, Some(1));
+        assert_eq!(frame.link_type, LINKTYPE_RAW);
+        assert_eq!(frame.timestamp, Duration::new(1595324877, 1));
+        assert_eq!(frame.data.len(), 28);
+
+        assert!(reader.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn it_can_read_a_big_endian_pcapng_with_two_interfaces() {
+        let file = two_interface_capture(true);
+        let frames: Vec<_> = PcapNgReader::new(file.as_slice())
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].timestamp, Duration::new(1595324876, 73920000));
+        assert_eq!(frames[1].timestamp, Duration::new(1595324877, 1));
+    }
+
+    #[test]
+    fn it_can_read_several_sections_of_different_byte_orders() {
+        let mut file = two_interface_capture(false);
+        let mut builder = Builder::new(true);
+        builder
+            .interface_description(LINKTYPE_RAW as u16, None)
+            .simple_packet(ipv4_udp_datagram([10, 0, 0, 3], [10, 0, 0, 4], 1, 2));
+        file.extend(builder.content);
+
+        let mut reader = PcapNgReader::new(file.as_slice()).unwrap();
+        assert_eq!(reader.by_ref().take(2).count(), 2);
+        let frame = reader.next_frame().unwrap().unwrap();
+        assert_eq!(reader.interface_list().len(), 1);
+        assert_eq!(frame.interface_id, Some(0));
+        assert_eq!(frame.link_type, LINKTYPE_RAW);
+        assert_eq!(frame.timestamp, Duration::default());
+        assert_eq!(frame.data.len(), 28);
+    }
+
+    #[test]
+    fn it_cannot_read_a_frame_of_an_undescribed_interface() {
+        let mut builder = Builder::new(false);
+        builder.enhanced_packet(0, 0, vec![0x45]);
+        let mut reader = PcapNgReader::new(builder.content.as_slice()).unwrap();
+        assert_eq!(reader.next_frame().err().unwrap().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn it_cannot_read_a_pcap_file() {
+        let file = [0xD4, 0xC3, 0xB2, 0xA1, 0x02, 0x00, 0x04, 0x00];
+        let error = PcapNgReader::new(&file[..]).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn it_can_read_a_pcapng_file_into_a_generator() {
+        let path = "target/read_two_interface_capture.pcapng";
+        let mut file = File::create(path).unwrap();
+        file.write_all(&two_interface_capture(false)).unwrap();
+
+        let mut generator = read_from_pcapng_file(path);
+
+        assert_eq!(generator.len(), 1);
+        let flow_information = generator
+            .get_mut(&FlowId::new(17, "10.0.0.1", "10.0.0.2", 5353, 53))
+            .unwrap();
+        assert_eq!(flow_information.forward_packet_list[0].interface_id, Some(0));
+        assert_eq!(flow_information.forward_packet_list[0].position, 1);
+        assert_eq!(flow_information.backward_packet_list[0].interface_id, Some(1));
+        assert_eq!(flow_information.backward_packet_list[0].position, 2);
+    }
+}
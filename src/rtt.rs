@@ -0,0 +1,346 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::flag::Flag;
+use crate::flow_information::FlowInformation;
+use crate::packet::Packet;
+use crate::tcp_analysis::{is_before, segment_length};
+
+/// The statistics of RTT samples.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RttStatistics {
+    /// number of samples
+    pub count: usize,
+    /// minimum RTT
+    pub min: Duration,
+    /// average RTT
+    pub average: Duration,
+    /// maximum RTT
+    pub max: Duration,
+    /// median RTT
+    pub p50: Duration,
+    /// 90th percentile of the RTT
+    pub p90: Duration,
+    /// 95th percentile of the RTT
+    pub p95: Duration,
+    /// 99th percentile of the RTT
+    pub p99: Duration,
+}
+
+impl RttStatistics {
+    /// Compute the statistics of the samples, if any.
+    pub fn new(sample_list: &[Duration]) -> Option<Self> {
+        if sample_list.is_empty() {
+            return None;
+        }
+        let mut sorted_list = sample_list.to_vec();
+        sorted_list.sort();
+        let total: Duration = sorted_list.iter().sum();
+        Some(Self {
+            count: sorted_list.len(),
+            min: sorted_list[0],
+            average: total / sorted_list.len() as u32,
+            max: sorted_list[sorted_list.len() - 1],
+            p50: percentile(&sorted_list, 50.0),
+            p90: percentile(&sorted_list, 90.0),
+            p95: percentile(&sorted_list, 95.0),
+            p99: percentile(&sorted_list, 99.0),
+        })
+    }
+}
+
+/// Returns the percentile of sorted samples, by the nearest-rank method.
+pub fn percentile(sorted_list: &[Duration], percent: f64) -> Duration {
+    if sorted_list.is_empty() {
+        return Duration::default();
+    }
+    let rank = (percent / 100.0 * sorted_list.len() as f64).ceil() as usize;
+    sorted_list[rank.clamp(1, sorted_list.len()) - 1]
+}
+
+/// The RTT of a TCP flow, from its handshake and from its later segments.
+/// The RTT are seen from the observer,
+/// so the client side RTT is the one between the observer and the server,
+/// and the server side RTT the one between the observer and the client.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RttEstimation {
+    /// delay from the SYN to the SYN-ACK
+    pub client_side_rtt: Option<Duration>,
+    /// delay from the SYN-ACK to the ACK
+    pub server_side_rtt: Option<Duration>,
+    /// RTT samples of the forward segments, up to the backward ACK
+    pub forward_sample_list: Vec<Duration>,
+    /// RTT samples of the backward segments, up to the forward ACK
+    pub backward_sample_list: Vec<Duration>,
+}
+
+impl RttEstimation {
+    /// Provide an estimation without RTT for now.
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    /// Returns the delay from the SYN to the ACK of the handshake.
+    pub fn handshake_rtt(&self) -> Option<Duration> {
+        Some(self.client_side_rtt? + self.server_side_rtt?)
+    }
+
+    /// Returns the statistics of the forward RTT samples.
+    pub fn forward_statistics(&self) -> Option<RttStatistics> {
+        RttStatistics::new(&self.forward_sample_list)
+    }
+
+    /// Returns the statistics of the backward RTT samples.
+    pub fn backward_statistics(&self) -> Option<RttStatistics> {
+        RttStatistics::new(&self.backward_sample_list)
+    }
+}
+
+/// What is known of the segments of a direction not acknowledged yet.
+#[derive(Debug, Default)]
+struct SenderState {
+    /// sequence number following the highest one sent
+    next_sequence_number: Option<u32>,
+    /// end sequence numbers and timestamps of the segments sent once
+    pending_list: VecDeque<(u32, Duration)>,
+    /// TCP timestamp values and timestamps of the segments, in order
+    timestamp_value_list: VecDeque<(u32, Duration)>,
+}
+
+impl SenderState {
+    /// Remember a segment sent, forgetting the ones it sends again (Karn's algorithm).
+    fn send(&mut self, packet: &Packet, sequence_number: u32) {
+        let end = sequence_number.wrapping_add(segment_length(packet));
+        match self.next_sequence_number {
+            Some(next_sequence_number) if is_before(sequence_number, next_sequence_number) => {
+                self.pending_list
+                    .retain(|(pending_end, _)| !is_before(sequence_number, *pending_end));
+                if is_before(next_sequence_number, end) {
+                    self.next_sequence_number = Some(end);
+                }
+            }
+            _ => {
+                self.pending_list.push_back((end, packet.timestamp));
+                self.next_sequence_number = Some(end);
+            }
+        }
+        if let Some(timestamp_value) = packet.tcp_options.as_ref().and_then(|options| options.timestamp_value) {
+            if self.timestamp_value_list.back().map(|(value, _)| *value) != Some(timestamp_value) {
+                self.timestamp_value_list.push_back((timestamp_value, packet.timestamp));
+            }
+        }
+    }
+
+    /// Forget the segments acknowledged by a packet of the other direction, and returns the RTT sample it gives:
+    /// the delay since the segment of the echoed TCP timestamp value,
+    /// or else since the segment ending at the acknowledgment number.
+    fn acknowledge(&mut self, packet: &Packet, acknowledgment_number: u32) -> Option<Duration> {
+        let mut sample = None;
+        if let Some(echo_reply) = packet
+            .tcp_options
+            .as_ref()
+            .and_then(|options| options.timestamp_echo_reply)
+        {
+            if let Some(index) = self
+                .timestamp_value_list
+                .iter()
+                .position(|(value, _)| *value == echo_reply)
+            {
+                let (_, timestamp) = self.timestamp_value_list[index];
+                self.timestamp_value_list.drain(..=index);
+                sample = Some(packet.timestamp.saturating_sub(timestamp));
+            }
+        }
+
+        let mut last_acknowledged = None;
+        while let Some(&(end, timestamp)) = self.pending_list.front() {
+            if is_before(acknowledgment_number, end) {
+                break;
+            }
+            last_acknowledged = Some((end, timestamp));
+            self.pending_list.pop_front();
+        }
+        match last_acknowledged {
+            Some((end, timestamp)) if sample.is_none() && end == acknowledgment_number => {
+                Some(packet.timestamp.saturating_sub(timestamp))
+            }
+            _ => sample,
+        }
+    }
+}
+
+/// Estimate the RTT of a TCP flow from the timestamps of its packets, in both directions and by timestamp.
+/// The handshake gives the client and server side RTT,
+/// then each ACK of new data gives a sample of the RTT of the other direction,
+/// from the echoed TCP timestamp value or else from the acknowledged segment.
+/// The segments sent again don't give samples.
+pub fn estimate(flow_information: &FlowInformation) -> RttEstimation {
+    let mut rtt_estimation = RttEstimation::new();
+    let mut forward_state = SenderState::default();
+    let mut backward_state = SenderState::default();
+    // timestamp and direction of the SYN, then timestamp of the SYN-ACK
+    let mut syn: Option<(Duration, bool)> = None;
+    let mut syn_ack: Option<Duration> = None;
+
+    for (packet, forward) in flow_information.packet_sequence() {
+        let sequence_number = match packet.sequence_number {
+            Some(sequence_number) => sequence_number,
+            None => continue,
+        };
+
+        let flag_list = &packet.flag_list;
+        let is_ack = flag_list.contains(&Flag::ACK);
+        if flag_list.contains(&Flag::SYN) && !is_ack && rtt_estimation.client_side_rtt.is_none() {
+            syn = Some((packet.timestamp, forward));
+        } else if flag_list.contains(&Flag::SYN) && is_ack {
+            if let (Some((syn_timestamp, syn_forward)), None) = (syn, syn_ack) {
+                if syn_forward != forward {
+                    rtt_estimation.client_side_rtt = Some(packet.timestamp.saturating_sub(syn_timestamp));
+                    syn_ack = Some(packet.timestamp);
+                }
+            }
+        } else if is_ack && rtt_estimation.server_side_rtt.is_none() {
+            if let (Some((_, syn_forward)), Some(syn_ack_timestamp)) = (syn, syn_ack) {
+                if syn_forward == forward {
+                    rtt_estimation.server_side_rtt = Some(packet.timestamp.saturating_sub(syn_ack_timestamp));
+                }
+            }
+        }
+
+        let (sender, receiver, sample_list) = if forward {
+            (
+                &mut forward_state,
+                &mut backward_state,
+                &mut rtt_estimation.backward_sample_list,
+            )
+        } else {
+            (
+                &mut backward_state,
+                &mut forward_state,
+                &mut rtt_estimation.forward_sample_list,
+            )
+        };
+        if is_ack {
+            if let Some(acknowledgment_number) = packet.acknowledgment_number {
+                sample_list.extend(receiver.acknowledge(packet, acknowledgment_number));
+            }
+        }
+        if segment_length(packet) > 0 {
+            sender.send(packet, sequence_number);
+        }
+    }
+    rtt_estimation
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::flag::Flag;
+    use crate::flow_information::FlowInformation;
+    use crate::packet::{Packet, TcpOptions};
+    use crate::rtt::{estimate, percentile, RttStatistics};
+
+    fn segment(millis: u64, flags: &[Flag], sequence_number: u32, payload_length: usize, ack: Option<u32>) -> Packet {
+        let mut packet = Packet::new();
+        packet.timestamp = Duration::from_millis(millis);
+        packet.flag_list = flags.iter().cloned().collect();
+        packet.sequence_number = Some(sequence_number);
+        packet.tcp_payload_length = Some(payload_length);
+        packet.acknowledgment_number = ack;
+        packet
+    }
+
+    fn millis_list(list: &[u64]) -> Vec<Duration> {
+        list.iter().map(|millis| Duration::from_millis(*millis)).collect()
+    }
+
+    fn handshake_flow_information() -> FlowInformation {
+        let mut flow_information = FlowInformation::new();
+        let forward_packet_list = &mut flow_information.forward_packet_list;
+        forward_packet_list.push(segment(0, &[Flag::SYN], 100, 0, None));
+        forward_packet_list.push(segment(50, &[Flag::ACK], 101, 0, Some(501)));
+        forward_packet_list.push(segment(60, &[Flag::ACK, Flag::PSH], 101, 200, Some(501)));
+        let backward_packet_list = &mut flow_information.backward_packet_list;
+        backward_packet_list.push(segment(40, &[Flag::SYN, Flag::ACK], 500, 0, Some(101)));
+        backward_packet_list.push(segment(100, &[Flag::ACK], 501, 1000, Some(301)));
+        flow_information
+            .forward_packet_list
+            .push(segment(110, &[Flag::ACK], 301, 0, Some(1501)));
+        flow_information
+    }
+
+    #[test]
+    fn it_can_compute_percentiles() {
+        let sample_list = millis_list(&[5, 1, 4, 2, 3]);
+        let statistics = RttStatistics::new(&sample_list).unwrap();
+        assert_eq!(statistics.count, 5);
+        assert_eq!(statistics.min, Duration::from_millis(1));
+        assert_eq!(statistics.average, Duration::from_millis(3));
+        assert_eq!(statistics.max, Duration::from_millis(5));
+        assert_eq!(statistics.p50, Duration::from_millis(3));
+        assert_eq!(statistics.p90, Duration::from_millis(5));
+        assert_eq!(percentile(&millis_list(&[1, 2, 3, 4]), 25.0), Duration::from_millis(1));
+        assert!(RttStatistics::new(&[]).is_none());
+    }
+
+    #[test]
+    fn it_can_estimate_the_handshake_rtt() {
+        let rtt_estimation = estimate(&handshake_flow_information());
+        assert_eq!(rtt_estimation.client_side_rtt, Some(Duration::from_millis(40)));
+        assert_eq!(rtt_estimation.server_side_rtt, Some(Duration::from_millis(10)));
+        assert_eq!(rtt_estimation.handshake_rtt(), Some(Duration::from_millis(50)));
+    }
+
+    #[test]
+    fn it_can_sample_the_rtt_from_data_and_ack_pairs() {
+        let rtt_estimation = estimate(&handshake_flow_information());
+        // the SYN acknowledged by the SYN-ACK, then the data acknowledged by the backward data
+        assert_eq!(rtt_estimation.forward_sample_list, millis_list(&[40, 40]));
+        // the SYN-ACK then the backward data
+        assert_eq!(rtt_estimation.backward_sample_list, millis_list(&[10, 10]));
+        let statistics = rtt_estimation.forward_statistics().unwrap();
+        assert_eq!(statistics.count, 2);
+        assert_eq!(statistics.max, Duration::from_millis(40));
+    }
+
+    #[test]
+    fn it_cannot_sample_the_rtt_of_a_retransmitted_segment() {
+        let mut flow_information = FlowInformation::new();
+        let forward_packet_list = &mut flow_information.forward_packet_list;
+        forward_packet_list.push(segment(0, &[Flag::ACK], 1, 100, Some(1)));
+        forward_packet_list.push(segment(300, &[Flag::ACK], 1, 100, Some(1)));
+        forward_packet_list.push(segment(310, &[Flag::ACK], 101, 100, Some(1)));
+        let backward_packet_list = &mut flow_information.backward_packet_list;
+        backward_packet_list.push(segment(320, &[Flag::ACK], 1, 0, Some(101)));
+        backward_packet_list.push(segment(340, &[Flag::ACK], 1, 0, Some(201)));
+
+        let rtt_estimation = estimate(&flow_information);
+        assert_eq!(rtt_estimation.forward_sample_list, millis_list(&[30]));
+        assert!(rtt_estimation.client_side_rtt.is_none());
+    }
+
+    #[test]
+    fn it_can_sample_the_rtt_from_timestamp_echoes() {
+        let with_timestamps = |mut packet: Packet, value: u32, echo_reply: u32| {
+            let mut tcp_options = TcpOptions::new();
+            tcp_options.timestamp_value = Some(value);
+            tcp_options.timestamp_echo_reply = Some(echo_reply);
+            packet.tcp_options = Some(tcp_options);
+            packet
+        };
+        let mut flow_information = FlowInformation::new();
+        let forward_packet_list = &mut flow_information.forward_packet_list;
+        forward_packet_list.push(with_timestamps(segment(0, &[Flag::ACK], 1, 100, Some(1)), 7, 0));
+        forward_packet_list.push(with_timestamps(segment(5, &[Flag::ACK], 101, 100, Some(1)), 8, 0));
+        // a delayed ACK of both segments, echoing the timestamp value of the first one
+        flow_information
+            .backward_packet_list
+            .push(with_timestamps(segment(45, &[Flag::ACK], 1, 0, Some(201)), 90, 7));
+
+        let rtt_estimation = estimate(&flow_information);
+        assert_eq!(rtt_estimation.forward_sample_list, millis_list(&[45]));
+    }
+}
@@ -0,0 +1,244 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::error::Result;
+use crate::flow_id::FlowId;
+use crate::flow_information::FlowInformation;
+use crate::generator::Generator;
+
+/// number of values of a packet: direction, signed length, inter-arrival time and TCP flags
+pub const VALUE_COUNT: usize = 4;
+/// names of the values of a packet, suffixed by the packet index in the CSV header
+pub const VALUE_NAME_LIST: [&str; VALUE_COUNT] = ["direction", "length", "iat", "flags"];
+
+/// The sequence of the first packets of a flow, in both directions and by timestamp,
+/// padded with zeros or truncated to the sequence length.
+/// A packet is a row of values:
+/// the direction (1 for forward, -1 for backward, 0 for padding),
+/// the length, positive for forward and negative for backward,
+/// the inter-arrival time since the previous packet in seconds (0 for the first one),
+/// and the TCP flags bits.
+pub fn packet_sequence(flow_information: &FlowInformation, sequence_length: usize) -> Vec<[f32; VALUE_COUNT]> {
+    let mut sequence = Vec::with_capacity(sequence_length);
+    let mut previous_timestamp = None;
+    for (packet, forward) in flow_information.packet_sequence().into_iter().take(sequence_length) {
+        let direction: f32 = if forward { 1.0 } else { -1.0 };
+        let iat = previous_timestamp
+            .map(|previous| packet.timestamp.saturating_sub(previous).as_secs_f32())
+            .unwrap_or_default();
+        let flags = packet.flag_list.iter().fold(0, |bits, flag| bits | flag.bit());
+        sequence.push([direction, direction * packet.length as f32, iat, flags as f32]);
+        previous_timestamp = Some(packet.timestamp);
+    }
+    sequence.resize(sequence_length, [0.0; VALUE_COUNT]);
+    sequence
+}
+
+/// Write the packet sequences of the flows as a NumPy `.npy` array of 32 bits floats,
+/// of shape (flow count, sequence length, 4), and returns the flow ids of the rows, from the client to the server.
+pub fn write_npy<'a, W, I>(writer: &mut W, flow_iter: I, sequence_length: usize) -> io::Result<Vec<FlowId>>
+where
+    W: Write,
+    I: IntoIterator<Item = (&'a FlowId, &'a FlowInformation)>,
+{
+    let (flow_id_list, sequence_list): (Vec<_>, Vec<_>) = flow_iter
+        .into_iter()
+        .map(|(flow_id, flow_information)| {
+            (
+                flow_information.oriented_flow_id(flow_id),
+                packet_sequence(flow_information, sequence_length),
+            )
+        })
+        .unzip();
+
+    let mut header = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}, {}), }}",
+        sequence_list.len(),
+        sequence_length,
+        VALUE_COUNT
+    );
+    // the magic string, version and header length, then the header, end with a newline on a 64 bytes boundary
+    let preamble_length = 10;
+    while (preamble_length + header.len() + 1) % 64 != 0 {
+        header.push(' ');
+    }
+    header.push('\n');
+    writer.write_all(b"\x93NUMPY\x01\x00")?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    for value in sequence_list.iter().flatten().flatten() {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(flow_id_list)
+}
+
+/// Write the packet sequences of the flows as CSV, one flow per row:
+/// the flow id then the values of each packet.
+pub fn write_csv<'a, W, I>(writer: &mut W, flow_iter: I, sequence_length: usize) -> io::Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = (&'a FlowId, &'a FlowInformation)>,
+{
+    let mut column_list = vec!["Flow ID".to_string()];
+    for index in 0..sequence_length {
+        column_list.extend(VALUE_NAME_LIST.iter().map(|name| format!("{}_{}", name, index)));
+    }
+    writeln!(writer, "{}", column_list.join(","))?;
+
+    for (flow_id, flow_information) in flow_iter {
+        let mut record = vec![flow_information.oriented_flow_id(flow_id).to_string()];
+        for values in packet_sequence(flow_information, sequence_length) {
+            record.extend(values.iter().map(|value| value.to_string()));
+        }
+        writeln!(writer, "{}", record.join(","))?;
+    }
+    Ok(())
+}
+
+/// Write the packet sequences of the flows of a generator to a NumPy `.npy` file,
+/// in the order of the generator's flow order, and returns the flow ids of the rows.
+/// Panics if the file can't be written, see `try_write_to_npy_file`.
+pub fn write_to_npy_file<P: AsRef<Path>>(generator: &Generator, path: P, sequence_length: usize) -> Vec<FlowId> {
+    try_write_to_npy_file(generator, path, sequence_length).unwrap()
+}
+
+/// Write the packet sequences of the flows of a generator to a NumPy `.npy` file,
+/// and returns the flow ids of the rows, or an error if the file can't be written.
+pub fn try_write_to_npy_file<P: AsRef<Path>>(
+    generator: &Generator,
+    path: P,
+    sequence_length: usize,
+) -> Result<Vec<FlowId>> {
+    // open the file with buffer.
+    let file = File::create(path)?;
+    let mut writer = BufWriter::new(file);
+
+    let flow_id_list = write_npy(&mut writer, generator.sorted(), sequence_length)?;
+    writer.flush()?;
+    Ok(flow_id_list)
+}
+
+/// Write the packet sequences of the flows of a generator to a CSV file.
+/// Panics if the file can't be written, see `try_write_to_sequence_csv_file`.
+pub fn write_to_sequence_csv_file<P: AsRef<Path>>(generator: &Generator, path: P, sequence_length: usize) {
+    try_write_to_sequence_csv_file(generator, path, sequence_length).unwrap()
+}
+
+/// Write the packet sequences of the flows of a generator to a CSV file,
+/// or returns an error if the file can't be written.
+pub fn try_write_to_sequence_csv_file<P: AsRef<Path>>(
+    generator: &Generator,
+    path: P,
+    sequence_length: usize,
+) -> Result<()> {
+    // open the file with buffer.
+    let file = File::create(path)?;
+    let mut writer = BufWriter::new(file);
+
+    write_csv(&mut writer, generator.sorted(), sequence_length)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+
+    use crate::flag::Flag;
+    use crate::flow_id::FlowId;
+    use crate::flow_information::FlowInformation;
+    use crate::generator::Generator;
+    use crate::packet::Packet;
+    use crate::sequence::{packet_sequence, write_csv, write_npy, write_to_npy_file};
+
+    fn packet(millis: u64, length: u64, flags: &[Flag]) -> Packet {
+        let mut packet = Packet::new();
+        packet.timestamp = Duration::from_millis(millis);
+        packet.length = length;
+        packet.flag_list = flags.iter().cloned().collect();
+        packet
+    }
+
+    fn flow_information() -> FlowInformation {
+        let mut flow_information = FlowInformation::new();
+        flow_information
+            .forward_packet_list
+            .push(packet(1_000, 74, &[Flag::SYN]));
+        flow_information
+            .forward_packet_list
+            .push(packet(1_500, 66, &[Flag::ACK]));
+        flow_information
+            .backward_packet_list
+            .push(packet(1_250, 74, &[Flag::SYN, Flag::ACK]));
+        flow_information
+    }
+
+    #[test]
+    fn it_can_merge_pad_and_truncate_a_packet_sequence() {
+        let sequence = packet_sequence(&flow_information(), 4);
+        assert_eq!(
+            sequence,
+            vec![
+                [1.0, 74.0, 0.0, 2.0],
+                [-1.0, -74.0, 0.25, 18.0],
+                [1.0, 66.0, 0.25, 16.0],
+                [0.0, 0.0, 0.0, 0.0]
+            ]
+        );
+        assert_eq!(packet_sequence(&flow_information(), 2), sequence[..2].to_vec());
+    }
+
+    #[test]
+    fn it_can_write_a_npy_array() {
+        let flow_id = FlowId::new(6, "10.0.0.1", "10.0.0.2", 42254, 443);
+        let flow_information = flow_information();
+        let mut content = Vec::new();
+        assert_eq!(
+            write_npy(&mut content, vec![(&flow_id, &flow_information)], 4).unwrap(),
+            vec![flow_id]
+        );
+
+        assert_eq!(&content[..8], b"\x93NUMPY\x01\x00");
+        let header_length = u16::from_le_bytes([content[8], content[9]]) as usize;
+        assert_eq!((10 + header_length) % 64, 0);
+        let header = String::from_utf8_lossy(&content[10..10 + header_length]);
+        assert!(header.contains("'shape': (1, 4, 4)"));
+        let data = &content[10 + header_length..];
+        assert_eq!(data.len(), 4 * 4 * 4);
+        assert_eq!(f32::from_le_bytes([data[20], data[21], data[22], data[23]]), -74.0);
+    }
+
+    #[test]
+    fn it_can_write_a_npy_file() {
+        let mut generator = Generator::new();
+        let flow_id_list = vec![
+            FlowId::new(6, "10.0.0.1", "10.0.0.2", 42254, 443),
+            FlowId::new(6, "10.0.0.1", "10.0.0.2", 42255, 443),
+        ];
+        for flow_id in &flow_id_list {
+            generator.add(*flow_id, flow_information());
+        }
+        let path = "target/write_sequences.npy";
+        // the rows are sorted by first timestamp, then by flow id
+        assert_eq!(write_to_npy_file(&generator, path, 8), flow_id_list);
+        assert_eq!(fs::read(path).unwrap().len(), 128 + 2 * 8 * 4 * 4);
+    }
+
+    #[test]
+    fn it_can_write_a_csv() {
+        let flow_id = FlowId::new(6, "10.0.0.1", "10.0.0.2", 42254, 443);
+        let flow_information = flow_information();
+        let mut content = Vec::new();
+        write_csv(&mut content, vec![(&flow_id, &flow_information)], 2).unwrap();
+
+        let content = String::from_utf8(content).unwrap();
+        let line_list: Vec<&str> = content.lines().collect();
+        assert_eq!(
+            line_list[0],
+            "Flow ID,direction_0,length_0,iat_0,flags_0,direction_1,length_1,iat_1,flags_1"
+        );
+        assert_eq!(line_list[1], "10.0.0.1-10.0.0.2-42254-443-6,1,74,0,2,-1,-74,0.25,18");
+    }
+}
@@ -0,0 +1,366 @@
+use std::collections::{BTreeMap, HashMap};
+
+use log::debug;
+
+use crate::flag::Flag;
+use crate::flow_id::FlowId;
+use crate::packet::Packet;
+
+/// default maximum number of out-of-order bytes buffered by direction
+const DEFAULT_MAXIMUM_PENDING_LENGTH: usize = 1 << 20;
+/// default maximum number of reassembled bytes kept by direction
+const DEFAULT_MAXIMUM_DATA_LENGTH: usize = 1 << 20;
+
+/// The byte stream of a TCP connection in one direction.
+/// The segments are put back in sequence order,
+/// the bytes received more than once keep their first value,
+/// and the bytes never received are skipped once too many bytes follow them.
+#[derive(Debug, Default)]
+pub struct TcpStream {
+    /// reassembled bytes, in order, up to the maximum data length
+    pub data: Vec<u8>,
+    /// number of reassembled bytes, including the ones not kept
+    pub length: u64,
+    /// number of bytes received more than once
+    pub retransmitted_length: u64,
+    /// number of bytes skipped without having been received
+    pub missing_length: u64,
+    /// sequence number of the next in-order byte, once known
+    next_sequence_number: Option<u32>,
+    /// out-of-order segments, by stream offset
+    pending_map: BTreeMap<u64, Vec<u8>>,
+    pending_length: usize,
+}
+
+impl TcpStream {
+    /// Provide an empty stream, its first sequence number being unknown for now.
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    /// Returns the number of out-of-order bytes waiting for the missing ones.
+    pub fn pending_length(&self) -> usize {
+        self.pending_length
+    }
+
+    /// Add a segment, and pass the bytes it puts in order to the callback.
+    /// A SYN gives the initial sequence number, otherwise the first segment starts the stream.
+    pub fn add_segment<F: FnMut(&[u8])>(
+        &mut self,
+        sequence_number: u32,
+        syn: bool,
+        payload: &[u8],
+        reassembler: &TcpReassembler,
+        callback: &mut F,
+    ) {
+        let sequence_number = if syn {
+            sequence_number.wrapping_add(1)
+        } else {
+            sequence_number
+        };
+        let next_sequence_number = *self.next_sequence_number.get_or_insert(sequence_number);
+        if payload.is_empty() {
+            return;
+        }
+
+        // the offset of the segment in the stream, the sequence numbers wrapping around
+        let start = self.length as i64 + i64::from(sequence_number.wrapping_sub(next_sequence_number) as i32);
+        let overlap = (self.length as i64 - start).clamp(0, payload.len() as i64) as usize;
+        self.retransmitted_length += overlap as u64;
+        if overlap == payload.len() {
+            return;
+        }
+        self.insert_pending((start + overlap as i64) as u64, &payload[overlap..]);
+        self.deliver_pending(reassembler, callback);
+
+        // too many bytes wait for the missing ones, they are skipped
+        if self.pending_length > reassembler.maximum_pending_length {
+            if let Some(first_offset) = self.pending_map.keys().next().copied() {
+                debug!("{} missing bytes skipped", first_offset - self.length);
+                self.skip(first_offset - self.length);
+                self.deliver_pending(reassembler, callback);
+            }
+        }
+    }
+
+    /// Buffer the bytes of a segment which aren't buffered yet, the first received ones being kept.
+    fn insert_pending(&mut self, start: u64, bytes: &[u8]) {
+        let end = start + bytes.len() as u64;
+        let mut position = start;
+        let covered_list: Vec<(u64, u64)> = self
+            .pending_map
+            .range(..end)
+            .map(|(offset, segment)| (*offset, offset + segment.len() as u64))
+            .filter(|(_, covered_end)| *covered_end > start)
+            .collect();
+        for (covered_start, covered_end) in covered_list {
+            if covered_start > position {
+                self.insert_gap(
+                    position,
+                    &bytes[(position - start) as usize..(covered_start - start) as usize],
+                );
+            }
+            let next_position = covered_end.min(end);
+            self.retransmitted_length += next_position - covered_start.max(position);
+            position = next_position;
+        }
+        if position < end {
+            self.insert_gap(position, &bytes[(position - start) as usize..]);
+        }
+    }
+
+    fn insert_gap(&mut self, offset: u64, bytes: &[u8]) {
+        self.pending_length += bytes.len();
+        self.pending_map.insert(offset, bytes.to_vec());
+    }
+
+    /// Deliver the pending segments which are now in order.
+    fn deliver_pending<F: FnMut(&[u8])>(&mut self, reassembler: &TcpReassembler, callback: &mut F) {
+        while let Some(segment) = self.pending_map.remove(&self.length) {
+            self.pending_length -= segment.len();
+            self.deliver(&segment, reassembler, callback);
+        }
+    }
+
+    fn deliver<F: FnMut(&[u8])>(&mut self, bytes: &[u8], reassembler: &TcpReassembler, callback: &mut F) {
+        let kept_length = reassembler
+            .maximum_data_length
+            .saturating_sub(self.data.len())
+            .min(bytes.len());
+        self.data.extend_from_slice(&bytes[..kept_length]);
+        callback(bytes);
+        self.advance(bytes.len() as u64);
+    }
+
+    fn skip(&mut self, length: u64) {
+        self.missing_length += length;
+        self.advance(length);
+    }
+
+    fn advance(&mut self, length: u64) {
+        self.length += length;
+        self.next_sequence_number = self.next_sequence_number.map(|next| next.wrapping_add(length as u32));
+    }
+}
+
+/// The byte streams of a TCP connection, sent by the client and by the server.
+#[derive(Debug, Default)]
+pub struct TcpStreamPair {
+    /// bytes sent by the client (forward)
+    pub client: TcpStream,
+    /// bytes sent by the server (backward)
+    pub server: TcpStream,
+}
+
+impl TcpStreamPair {
+    /// Provide empty streams for now.
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+}
+
+/// A TCP reassembler, keeping the byte streams of each flow.
+/// The reassembled bytes are passed to a callback as soon as they are in order,
+/// and kept with the streams up to the maximum data length.
+#[derive(Debug)]
+pub struct TcpReassembler {
+    /// maximum number of out-of-order bytes buffered by direction, beyond which the missing bytes are skipped
+    pub maximum_pending_length: usize,
+    /// maximum number of reassembled bytes kept by direction, the next ones being only passed to the callback
+    pub maximum_data_length: usize,
+    stream_map: HashMap<FlowId, TcpStreamPair>,
+}
+
+impl Default for TcpReassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TcpReassembler {
+    /// Provide a reassembler without stream for now.
+    pub fn new() -> Self {
+        Self {
+            maximum_pending_length: DEFAULT_MAXIMUM_PENDING_LENGTH,
+            maximum_data_length: DEFAULT_MAXIMUM_DATA_LENGTH,
+            stream_map: HashMap::new(),
+        }
+    }
+
+    /// Returns the number of flows with streams.
+    pub fn len(&self) -> usize {
+        self.stream_map.len()
+    }
+
+    /// Returns `true` if no flow has streams.
+    pub fn is_empty(&self) -> bool {
+        self.stream_map.is_empty()
+    }
+
+    /// Returns the streams of a flow.
+    pub fn get(&self, flow_id: &FlowId) -> Option<&TcpStreamPair> {
+        self.stream_map.get(flow_id)
+    }
+
+    /// Removes the streams of a flow, such as a finished one, and returns them.
+    pub fn remove(&mut self, flow_id: &FlowId) -> Option<TcpStreamPair> {
+        self.stream_map.remove(flow_id)
+    }
+
+    /// Add the payload of a forward or backward TCP packet to the streams of its flow,
+    /// and pass the bytes it puts in order to the callback.
+    /// A packet without sequence number is skipped.
+    pub fn add_segment<F: FnMut(&[u8])>(
+        &mut self,
+        flow_id: &FlowId,
+        forward: bool,
+        packet: &Packet,
+        payload: &[u8],
+        mut callback: F,
+    ) {
+        let sequence_number = match packet.sequence_number {
+            Some(sequence_number) => sequence_number,
+            None => {
+                debug!("flow {} segment without sequence number skipped", flow_id);
+                return;
+            }
+        };
+        let mut stream_pair = self.stream_map.remove(flow_id).unwrap_or_default();
+        let stream = if forward {
+            &mut stream_pair.client
+        } else {
+            &mut stream_pair.server
+        };
+        let syn = packet.flag_list.contains(&Flag::SYN);
+        stream.add_segment(sequence_number, syn, payload, self, &mut callback);
+        self.stream_map.insert(*flow_id, stream_pair);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::flag::Flag;
+    use crate::flow_id::FlowId;
+    use crate::packet::Packet;
+    use crate::stream::{TcpReassembler, TcpStream};
+
+    fn segment(sequence_number: u32, flags: &[Flag]) -> Packet {
+        let mut packet = Packet::new();
+        packet.sequence_number = Some(sequence_number);
+        packet.flag_list = flags.iter().cloned().collect();
+        packet
+    }
+
+    fn add(stream: &mut TcpStream, reassembler: &TcpReassembler, sequence_number: u32, payload: &[u8]) -> Vec<u8> {
+        let mut delivered = Vec::new();
+        stream.add_segment(sequence_number, false, payload, reassembler, &mut |bytes: &[u8]| {
+            delivered.extend_from_slice(bytes)
+        });
+        delivered
+    }
+
+    #[test]
+    fn test_new() {
+        let reassembler = TcpReassembler::new();
+        assert!(reassembler.is_empty());
+        let stream = TcpStream::new();
+        assert!(stream.data.is_empty());
+        assert_eq!(stream.pending_length(), 0);
+    }
+
+    #[test]
+    fn it_can_reassemble_out_of_order_segments() {
+        let reassembler = TcpReassembler::new();
+        let mut stream = TcpStream::new();
+        stream.add_segment(1000, true, &[], &reassembler, &mut |_: &[u8]| {});
+
+        assert_eq!(add(&mut stream, &reassembler, 1001, b"GET "), b"GET ");
+        assert!(add(&mut stream, &reassembler, 1009, b"HTTP").is_empty());
+        assert_eq!(stream.pending_length(), 4);
+        assert_eq!(add(&mut stream, &reassembler, 1005, b"/ a "), b"/ a HTTP");
+        assert_eq!(stream.data, b"GET / a HTTP");
+        assert_eq!(stream.pending_length(), 0);
+        assert_eq!(stream.retransmitted_length, 0);
+    }
+
+    #[test]
+    fn it_can_handle_retransmissions_and_overlaps() {
+        let reassembler = TcpReassembler::new();
+        let mut stream = TcpStream::new();
+
+        assert_eq!(add(&mut stream, &reassembler, 10, b"abcd"), b"abcd");
+        assert!(add(&mut stream, &reassembler, 10, b"abcd").is_empty());
+        // the overlapping bytes keep their first value
+        assert_eq!(add(&mut stream, &reassembler, 12, b"XXef"), b"ef");
+        assert!(add(&mut stream, &reassembler, 18, b"ij").is_empty());
+        assert_eq!(add(&mut stream, &reassembler, 16, b"ghXX"), b"ghij");
+        assert_eq!(stream.data, b"abcdefghij");
+        assert_eq!(stream.retransmitted_length, 8);
+    }
+
+    #[test]
+    fn it_can_handle_a_segment_straddling_the_stream_start() {
+        let reassembler = TcpReassembler::new();
+        let mut stream = TcpStream::new();
+
+        // the capture starts mid-connection, then an earlier segment is retransmitted
+        assert_eq!(add(&mut stream, &reassembler, 1000, b"abcd"), b"abcd");
+        assert_eq!(add(&mut stream, &reassembler, 995, b"0123456789"), b"9");
+        assert_eq!(stream.data, b"abcd9");
+        assert_eq!(stream.retransmitted_length, 9);
+    }
+
+    #[test]
+    fn it_can_wrap_the_sequence_numbers() {
+        let reassembler = TcpReassembler::new();
+        let mut stream = TcpStream::new();
+
+        assert_eq!(add(&mut stream, &reassembler, u32::MAX - 1, b"abcd"), b"abcd");
+        assert_eq!(add(&mut stream, &reassembler, 2, b"ef"), b"ef");
+        assert_eq!(stream.length, 6);
+    }
+
+    #[test]
+    fn it_can_skip_missing_bytes_beyond_the_pending_bound() {
+        let mut reassembler = TcpReassembler::new();
+        reassembler.maximum_pending_length = 4;
+        reassembler.maximum_data_length = 6;
+        let mut stream = TcpStream::new();
+
+        assert_eq!(add(&mut stream, &reassembler, 0, b"ab"), b"ab");
+        assert!(add(&mut stream, &reassembler, 4, b"ef").is_empty());
+        assert_eq!(add(&mut stream, &reassembler, 6, b"ghi"), b"efghi");
+        assert_eq!(stream.missing_length, 2);
+        assert_eq!(stream.length, 9);
+        assert_eq!(stream.data, b"abefgh");
+    }
+
+    #[test]
+    fn it_can_keep_the_streams_of_each_flow() {
+        let client_flow_id = FlowId::new(6, "10.0.0.1", "10.0.0.2", 42254, 80);
+        let server_flow_id = FlowId::new(6, "10.0.0.2", "10.0.0.1", 80, 42254);
+        let mut reassembler = TcpReassembler::new();
+        let mut delivered = Vec::new();
+
+        reassembler.add_segment(&client_flow_id, true, &segment(100, &[Flag::SYN]), &[], |_| {});
+        reassembler.add_segment(
+            &server_flow_id,
+            false,
+            &segment(500, &[Flag::SYN, Flag::ACK]),
+            &[],
+            |_| {},
+        );
+        reassembler.add_segment(&server_flow_id, false, &segment(501, &[Flag::ACK]), b"hello", |bytes| {
+            delivered.extend_from_slice(bytes)
+        });
+        reassembler.add_segment(&client_flow_id, true, &segment(101, &[Flag::ACK]), b"hi", |_| {});
+        reassembler.add_segment(&client_flow_id, true, &Packet::new(), b"ignored", |_| {});
+
+        assert_eq!(delivered, b"hello");
+        assert_eq!(reassembler.len(), 1);
+        let stream_pair = reassembler.remove(&server_flow_id).unwrap();
+        assert_eq!(stream_pair.client.data, b"hi");
+        assert_eq!(stream_pair.server.data, b"hello");
+        assert!(reassembler.is_empty());
+    }
+}
@@ -0,0 +1,362 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::flag::Flag;
+use crate::flow_information::FlowInformation;
+use crate::packet::Packet;
+
+/// a segment sent again sooner than this after the highest one is deemed out of order, as Wireshark does
+const OUT_OF_ORDER_THRESHOLD: Duration = Duration::from_millis(3);
+/// number of duplicate ACKs which trigger a fast retransmission
+const FAST_RETRANSMISSION_DUPLICATE_ACK_COUNT: u64 = 2;
+
+/// The class of a TCP segment which isn't a plain in-order one.
+#[derive(Clone, Copy, Debug, Eq, Hash, Deserialize, PartialEq, Serialize)]
+pub enum SegmentClass {
+    /// data sent again after a timeout
+    Retransmission,
+    /// data sent again after duplicate ACKs of the receiver
+    FastRetransmission,
+    /// data older than the highest one sent, shortly after it
+    OutOfOrder,
+    /// ACK without data repeating the previous acknowledgment number and window
+    DuplicateAck,
+    /// one byte sent while the receiver advertises a zero window
+    ZeroWindowProbe,
+}
+
+/// The analysis of the TCP segments of one direction.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct DirectionAnalysis {
+    /// class of each packet of the direction, in the packet list order
+    pub class_list: Vec<Option<SegmentClass>>,
+    /// number of segments with data, SYN or FIN
+    pub data_segment_count: u64,
+    /// number of retransmissions
+    pub retransmission_count: u64,
+    /// number of fast retransmissions
+    pub fast_retransmission_count: u64,
+    /// number of out-of-order segments
+    pub out_of_order_count: u64,
+    /// number of duplicate ACKs
+    pub duplicate_ack_count: u64,
+    /// number of zero window probes
+    pub zero_window_probe_count: u64,
+    /// number of gaps in the sequence numbers, the segments before them not being captured
+    pub missing_segment_count: u64,
+}
+
+impl DirectionAnalysis {
+    /// Provide an analysis without segment for now.
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    /// Returns the estimated loss ratio: the share of the data segments which were sent again.
+    pub fn loss_ratio(&self) -> f64 {
+        if self.data_segment_count == 0 {
+            return 0.0;
+        }
+        (self.retransmission_count + self.fast_retransmission_count) as f64 / self.data_segment_count as f64
+    }
+
+    /// Count a classified segment, and annotate its packet.
+    fn add(&mut self, index: usize, class: SegmentClass) {
+        match class {
+            SegmentClass::Retransmission => self.retransmission_count += 1,
+            SegmentClass::FastRetransmission => self.fast_retransmission_count += 1,
+            SegmentClass::OutOfOrder => self.out_of_order_count += 1,
+            SegmentClass::DuplicateAck => self.duplicate_ack_count += 1,
+            SegmentClass::ZeroWindowProbe => self.zero_window_probe_count += 1,
+        }
+        self.class_list[index] = Some(class);
+    }
+}
+
+/// The analysis of the TCP segments of a flow, in both directions.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct TcpAnalysis {
+    /// analysis of the forward packets
+    pub forward: DirectionAnalysis,
+    /// analysis of the backward packets
+    pub backward: DirectionAnalysis,
+}
+
+impl TcpAnalysis {
+    /// Provide an analysis without segment for now.
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+}
+
+/// What is known of the sender of a direction.
+#[derive(Debug, Default)]
+struct SenderState {
+    /// sequence number following the highest one sent
+    next_sequence_number: Option<u32>,
+    /// timestamp of the last segment advancing the sequence numbers
+    last_advance: Duration,
+    /// last acknowledgment number sent
+    last_ack: Option<u32>,
+    /// last window advertised
+    last_window: Option<u16>,
+    /// number of duplicate ACKs of the last acknowledgment number
+    duplicate_ack_count: u64,
+}
+
+/// Classify the TCP segments of a flow, in both directions and by timestamp,
+/// and count them by class.
+/// A packet without sequence number, such as a non-TCP one, is left unclassified.
+pub fn analyze(flow_information: &FlowInformation) -> TcpAnalysis {
+    let mut tcp_analysis = TcpAnalysis::new();
+    tcp_analysis.forward.class_list = vec![None; flow_information.forward_packet_list.len()];
+    tcp_analysis.backward.class_list = vec![None; flow_information.backward_packet_list.len()];
+
+    // the packets with their direction and their index in their packet list
+    let mut packet_list: Vec<(&Packet, bool, usize)> = flow_information
+        .forward_packet_list
+        .iter()
+        .enumerate()
+        .map(|(index, packet)| (packet, true, index))
+        .chain(
+            flow_information
+                .backward_packet_list
+                .iter()
+                .enumerate()
+                .map(|(index, packet)| (packet, false, index)),
+        )
+        .collect();
+    packet_list.sort_by_key(|(packet, _, _)| (packet.timestamp, packet.position));
+
+    let mut forward_state = SenderState::default();
+    let mut backward_state = SenderState::default();
+    for (packet, forward, index) in packet_list {
+        let sequence_number = match packet.sequence_number {
+            Some(sequence_number) => sequence_number,
+            None => continue,
+        };
+        let (sender, receiver, direction_analysis) = if forward {
+            (&mut forward_state, &backward_state, &mut tcp_analysis.forward)
+        } else {
+            (&mut backward_state, &forward_state, &mut tcp_analysis.backward)
+        };
+        let (class, gap) = classify(packet, sequence_number, sender, receiver);
+        if segment_length(packet) > 0 {
+            direction_analysis.data_segment_count += 1;
+        }
+        if gap {
+            direction_analysis.missing_segment_count += 1;
+        }
+        if let Some(class) = class {
+            direction_analysis.add(index, class);
+        }
+    }
+    tcp_analysis
+}
+
+/// Returns the number of sequence numbers used by a segment: its data, SYN and FIN.
+pub(crate) fn segment_length(packet: &Packet) -> u32 {
+    let mut length = packet.tcp_payload_length.unwrap_or_default() as u32;
+    if packet.flag_list.contains(&Flag::SYN) {
+        length += 1;
+    }
+    if packet.flag_list.contains(&Flag::FIN) {
+        length += 1;
+    }
+    length
+}
+
+/// Returns `true` if the first sequence number comes before the second one, the sequence numbers wrapping around.
+pub(crate) fn is_before(first: u32, second: u32) -> bool {
+    (first.wrapping_sub(second) as i32) < 0
+}
+
+/// Classify a segment with what is known of its sender and receiver, then update its sender.
+/// Returns the class of the segment, if any, and `true` if segments are missing before it.
+fn classify(
+    packet: &Packet,
+    sequence_number: u32,
+    sender: &mut SenderState,
+    receiver: &SenderState,
+) -> (Option<SegmentClass>, bool) {
+    let segment_length = segment_length(packet);
+    let mut class = None;
+    let mut gap = false;
+
+    if segment_length > 0 {
+        let end = sequence_number.wrapping_add(segment_length);
+        match sender.next_sequence_number {
+            None => {
+                sender.next_sequence_number = Some(end);
+                sender.last_advance = packet.timestamp;
+            }
+            Some(next_sequence_number) => {
+                if segment_length == 1
+                    && packet.tcp_payload_length == Some(1)
+                    && sequence_number == next_sequence_number
+                    && receiver.last_window == Some(0)
+                {
+                    class = Some(SegmentClass::ZeroWindowProbe);
+                } else if is_before(sequence_number, next_sequence_number) {
+                    class = Some(
+                        if receiver.duplicate_ack_count >= FAST_RETRANSMISSION_DUPLICATE_ACK_COUNT
+                            && receiver.last_ack == Some(sequence_number)
+                        {
+                            SegmentClass::FastRetransmission
+                        } else if packet.timestamp.saturating_sub(sender.last_advance) < OUT_OF_ORDER_THRESHOLD {
+                            SegmentClass::OutOfOrder
+                        } else {
+                            SegmentClass::Retransmission
+                        },
+                    );
+                    if is_before(next_sequence_number, end) {
+                        sender.next_sequence_number = Some(end);
+                    }
+                } else {
+                    gap = is_before(next_sequence_number, sequence_number);
+                    sender.next_sequence_number = Some(end);
+                    sender.last_advance = packet.timestamp;
+                }
+            }
+        }
+    }
+
+    if packet.flag_list.contains(&Flag::ACK) && !packet.flag_list.contains(&Flag::RST) {
+        if let Some(acknowledgment_number) = packet.acknowledgment_number {
+            if sender.last_ack == Some(acknowledgment_number) {
+                if segment_length == 0 && sender.last_window == packet.window {
+                    sender.duplicate_ack_count += 1;
+                    class = class.or(Some(SegmentClass::DuplicateAck));
+                }
+            } else {
+                sender.duplicate_ack_count = 0;
+                sender.last_ack = Some(acknowledgment_number);
+            }
+        }
+    }
+    if packet.window.is_some() {
+        sender.last_window = packet.window;
+    }
+    (class, gap)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::flag::Flag;
+    use crate::flow_information::FlowInformation;
+    use crate::packet::Packet;
+    use crate::tcp_analysis::{analyze, DirectionAnalysis, SegmentClass};
+
+    fn segment(millis: u64, sequence_number: u32, payload_length: usize, acknowledgment_number: u32) -> Packet {
+        let mut packet = Packet::new();
+        packet.timestamp = Duration::from_millis(millis);
+        packet.sequence_number = Some(sequence_number);
+        packet.tcp_payload_length = Some(payload_length);
+        packet.acknowledgment_number = Some(acknowledgment_number);
+        packet.window = Some(1000);
+        packet.flag_list = vec![Flag::ACK].into_iter().collect();
+        packet
+    }
+
+    #[test]
+    fn test_new() {
+        let new = DirectionAnalysis::new();
+        assert!(new.class_list.is_empty());
+        assert_eq!(new.data_segment_count, 0);
+        assert_eq!(new.loss_ratio(), 0.0);
+    }
+
+    #[test]
+    fn it_can_classify_retransmissions_and_out_of_order_segments() {
+        let mut flow_information = FlowInformation::new();
+        let forward_packet_list = &mut flow_information.forward_packet_list;
+        forward_packet_list.push(segment(0, 1, 100, 1));
+        forward_packet_list.push(segment(100, 101, 100, 1));
+        // a segment older than the highest one, just after it
+        forward_packet_list.push(segment(101, 201, 100, 1));
+        forward_packet_list.push(segment(102, 101, 100, 1));
+        // a segment sent again much later
+        forward_packet_list.push(segment(500, 1, 100, 1));
+        // a gap: the segment at 301 is never seen
+        forward_packet_list.push(segment(600, 401, 100, 1));
+        flow_information.backward_packet_list.push(segment(700, 1, 0, 101));
+
+        let tcp_analysis = analyze(&flow_information);
+        assert_eq!(
+            tcp_analysis.forward.class_list,
+            vec![
+                None,
+                None,
+                None,
+                Some(SegmentClass::OutOfOrder),
+                Some(SegmentClass::Retransmission),
+                None
+            ]
+        );
+        assert_eq!(tcp_analysis.forward.data_segment_count, 6);
+        assert_eq!(tcp_analysis.forward.out_of_order_count, 1);
+        assert_eq!(tcp_analysis.forward.retransmission_count, 1);
+        assert_eq!(tcp_analysis.forward.missing_segment_count, 1);
+        assert!((tcp_analysis.forward.loss_ratio() - 1.0 / 6.0).abs() < 1e-9);
+        assert_eq!(tcp_analysis.backward.class_list, vec![None]);
+        assert_eq!(tcp_analysis.backward.data_segment_count, 0);
+    }
+
+    #[test]
+    fn it_can_classify_a_fast_retransmission_after_duplicate_acks() {
+        let mut flow_information = FlowInformation::new();
+        let forward_packet_list = &mut flow_information.forward_packet_list;
+        forward_packet_list.push(segment(0, 1, 100, 1));
+        forward_packet_list.push(segment(1, 101, 100, 1));
+        forward_packet_list.push(segment(2, 201, 100, 1));
+        forward_packet_list.push(segment(50, 101, 100, 1));
+        let backward_packet_list = &mut flow_information.backward_packet_list;
+        backward_packet_list.push(segment(20, 1, 0, 101));
+        backward_packet_list.push(segment(21, 1, 0, 101));
+        backward_packet_list.push(segment(22, 1, 0, 101));
+
+        let tcp_analysis = analyze(&flow_information);
+        assert_eq!(
+            tcp_analysis.backward.class_list,
+            vec![None, Some(SegmentClass::DuplicateAck), Some(SegmentClass::DuplicateAck)]
+        );
+        assert_eq!(tcp_analysis.backward.duplicate_ack_count, 2);
+        assert_eq!(
+            tcp_analysis.forward.class_list[3],
+            Some(SegmentClass::FastRetransmission)
+        );
+        assert_eq!(tcp_analysis.forward.fast_retransmission_count, 1);
+        assert_eq!(tcp_analysis.forward.loss_ratio(), 0.25);
+    }
+
+    #[test]
+    fn it_can_classify_a_zero_window_probe() {
+        let mut flow_information = FlowInformation::new();
+        flow_information.forward_packet_list.push(segment(0, 1, 100, 1));
+        let mut zero_window = segment(10, 1, 0, 101);
+        zero_window.window = Some(0);
+        flow_information.backward_packet_list.push(zero_window);
+        flow_information.forward_packet_list.push(segment(200, 101, 1, 1));
+
+        let tcp_analysis = flow_information.tcp_analysis();
+        assert_eq!(tcp_analysis, analyze(&flow_information));
+        assert_eq!(
+            tcp_analysis.forward.class_list,
+            vec![None, Some(SegmentClass::ZeroWindowProbe)]
+        );
+        assert_eq!(tcp_analysis.forward.zero_window_probe_count, 1);
+        assert_eq!(tcp_analysis.forward.loss_ratio(), 0.0);
+    }
+
+    #[test]
+    fn it_cannot_classify_a_packet_without_sequence_number() {
+        let mut flow_information = FlowInformation::new();
+        flow_information.forward_packet_list.push(Packet::new());
+        let tcp_analysis = analyze(&flow_information);
+        assert_eq!(tcp_analysis.forward.class_list, vec![None]);
+        assert_eq!(tcp_analysis.forward.data_segment_count, 0);
+    }
+}
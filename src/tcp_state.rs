@@ -0,0 +1,280 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::flag::Flag;
+
+/// The state of a TCP connection, as seen by an observer of both directions.
+/// The forward direction is the one of the packets sent by the client.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Deserialize, PartialEq, Serialize)]
+pub enum TcpState {
+    /// no packet seen yet
+    #[default]
+    New,
+    /// SYN sent by the initiator
+    SynSent,
+    /// SYN-ACK sent by the responder
+    SynReceived,
+    /// handshake completed, or connection picked up in the middle
+    Established,
+    /// FIN sent by one side only
+    FinWait,
+    /// FIN sent by both sides, the last acknowledgment is awaited
+    Closing,
+    /// FIN sent by both sides and acknowledged
+    Closed,
+    /// RST sent by one side
+    Reset,
+}
+
+impl TcpState {
+    /// Returns `true` if the connection can't carry more packets.
+    pub fn is_terminal(self) -> bool {
+        self == TcpState::Closed || self == TcpState::Reset
+    }
+
+    /// Returns `true` if the handshake hasn't completed.
+    pub fn is_half_open(self) -> bool {
+        self == TcpState::SynSent || self == TcpState::SynReceived
+    }
+}
+
+/// The reason why a flow ended.
+#[derive(Clone, Copy, Debug, Eq, Hash, Deserialize, PartialEq, Serialize)]
+pub enum EndReason {
+    /// TCP connection closed by FIN on both sides
+    Close,
+    /// TCP connection reset
+    Reset,
+    /// TCP connection timed out before the handshake completed
+    HalfOpen,
+    /// TCP 5-tuple reused by a new SYN
+    NewConnection,
+    /// no packet during the idle timeout
+    IdleTimeout,
+    /// flow longer than the active timeout
+    ActiveTimeout,
+    /// flow still in progress at the end of the capture
+    EndOfCapture,
+}
+
+/// A per flow TCP state machine, driven by the packets' flags.
+#[derive(Clone, Debug, Default)]
+pub struct TcpStateMachine {
+    state: TcpState,
+    forward_fin: bool,
+    backward_fin: bool,
+    /// direction of the first FIN, which acknowledges the last one to close the connection
+    first_fin_forward: bool,
+    /// initial sequence number of the client, from its SYN
+    initial_sequence_number: Option<u32>,
+}
+
+impl TcpStateMachine {
+    /// Provide a state machine in the `New` state.
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    /// Returns the current state.
+    pub fn state(&self) -> TcpState {
+        self.state
+    }
+
+    /// Returns `true` if the packet opens a new connection on a 5-tuple already in use:
+    /// a SYN without ACK with another sequence number than the initial one of the connection.
+    /// If either sequence number is unknown, a SYN is a retransmission only while the handshake is in progress.
+    pub fn is_new_connection(&self, flag_list: &BTreeSet<Flag>, sequence_number: Option<u32>) -> bool {
+        if !flag_list.contains(&Flag::SYN) || flag_list.contains(&Flag::ACK) || self.state == TcpState::New {
+            return false;
+        }
+        match (self.initial_sequence_number, sequence_number) {
+            (Some(initial_sequence_number), Some(sequence_number)) => initial_sequence_number != sequence_number,
+            _ => !self.state.is_half_open(),
+        }
+    }
+
+    /// Update the state with the flags of a packet, forward or backward, and returns the new state.
+    pub fn update(&mut self, flag_list: &BTreeSet<Flag>, forward: bool) -> TcpState {
+        self.update_with_sequence_number(flag_list, forward, None)
+    }
+
+    /// Update the state as `update` does, recording the sequence number of the first SYN
+    /// to tell a retransmitted SYN from a new connection.
+    pub fn update_with_sequence_number(
+        &mut self,
+        flag_list: &BTreeSet<Flag>,
+        forward: bool,
+        sequence_number: Option<u32>,
+    ) -> TcpState {
+        let syn = flag_list.contains(&Flag::SYN);
+        let ack = flag_list.contains(&Flag::ACK);
+        let fin = flag_list.contains(&Flag::FIN);
+
+        if syn && !ack && self.initial_sequence_number.is_none() {
+            self.initial_sequence_number = sequence_number;
+        }
+        if self.state.is_terminal() {
+            return self.state;
+        }
+        if flag_list.contains(&Flag::RST) {
+            self.state = TcpState::Reset;
+            return self.state;
+        }
+
+        self.state = match self.state {
+            TcpState::New if syn && !ack => TcpState::SynSent,
+            // the SYN wasn't captured
+            TcpState::New if syn => TcpState::SynReceived,
+            TcpState::SynSent if syn && ack && !forward => TcpState::SynReceived,
+            // the SYN-ACK wasn't captured
+            TcpState::SynSent if ack && !syn && forward => TcpState::Established,
+            TcpState::SynReceived if ack && !syn && forward => TcpState::Established,
+            TcpState::New => TcpState::Established,
+            state => state,
+        };
+
+        if fin && !self.state.is_half_open() {
+            if !self.forward_fin && !self.backward_fin {
+                self.first_fin_forward = forward;
+            }
+            if forward {
+                self.forward_fin = true;
+            } else {
+                self.backward_fin = true;
+            }
+            self.state = if self.forward_fin && self.backward_fin {
+                TcpState::Closing
+            } else {
+                TcpState::FinWait
+            };
+        } else if self.state == TcpState::Closing && ack && forward == self.first_fin_forward {
+            self.state = TcpState::Closed;
+        }
+        self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::flag::Flag;
+    use crate::tcp_state::{TcpState, TcpStateMachine};
+
+    fn flag_list(flags: &[Flag]) -> BTreeSet<Flag> {
+        flags.iter().cloned().collect()
+    }
+
+    #[test]
+    fn test_new() {
+        let state_machine = TcpStateMachine::new();
+        assert_eq!(state_machine.state(), TcpState::New);
+        assert!(!state_machine.state().is_terminal());
+    }
+
+    #[test]
+    fn it_can_follow_a_handshake_then_a_close() {
+        let mut state_machine = TcpStateMachine::new();
+        assert_eq!(state_machine.update(&flag_list(&[Flag::SYN]), true), TcpState::SynSent);
+        assert!(state_machine.state().is_half_open());
+        assert_eq!(
+            state_machine.update(&flag_list(&[Flag::SYN, Flag::ACK]), false),
+            TcpState::SynReceived
+        );
+        assert_eq!(
+            state_machine.update(&flag_list(&[Flag::ACK]), true),
+            TcpState::Established
+        );
+        assert_eq!(
+            state_machine.update(&flag_list(&[Flag::ACK, Flag::PSH]), false),
+            TcpState::Established
+        );
+        assert_eq!(
+            state_machine.update(&flag_list(&[Flag::FIN, Flag::ACK]), false),
+            TcpState::FinWait
+        );
+        assert_eq!(state_machine.update(&flag_list(&[Flag::ACK]), true), TcpState::FinWait);
+        assert_eq!(
+            state_machine.update(&flag_list(&[Flag::FIN, Flag::ACK]), true),
+            TcpState::Closing
+        );
+        // the last acknowledgment comes from the side of the first FIN
+        assert_eq!(state_machine.update(&flag_list(&[Flag::ACK]), false), TcpState::Closed);
+        assert!(state_machine.state().is_terminal());
+    }
+
+    #[test]
+    fn it_can_follow_a_reset() {
+        let mut state_machine = TcpStateMachine::new();
+        state_machine.update(&flag_list(&[Flag::SYN]), true);
+        assert_eq!(
+            state_machine.update(&flag_list(&[Flag::RST, Flag::ACK]), false),
+            TcpState::Reset
+        );
+        // nothing changes a terminal state
+        assert_eq!(state_machine.update(&flag_list(&[Flag::SYN]), true), TcpState::Reset);
+    }
+
+    #[test]
+    fn it_can_pick_up_a_connection_in_the_middle() {
+        let mut state_machine = TcpStateMachine::new();
+        assert_eq!(
+            state_machine.update(&flag_list(&[Flag::ACK]), true),
+            TcpState::Established
+        );
+        assert_eq!(state_machine.update(&flag_list(&[Flag::FIN]), true), TcpState::FinWait);
+    }
+
+    #[test]
+    fn it_can_detect_a_new_connection() {
+        let mut state_machine = TcpStateMachine::new();
+        let syn = flag_list(&[Flag::SYN]);
+        assert!(!state_machine.is_new_connection(&syn, Some(1000)));
+        state_machine.update_with_sequence_number(&syn, true, Some(1000));
+        // a retransmitted SYN
+        assert!(!state_machine.is_new_connection(&syn, Some(1000)));
+        state_machine.update(&flag_list(&[Flag::SYN, Flag::ACK]), false);
+        // a SYN retransmitted after the SYN-ACK, or of unknown sequence number
+        assert!(!state_machine.is_new_connection(&syn, Some(1000)));
+        assert!(!state_machine.is_new_connection(&syn, None));
+        assert!(!state_machine.is_new_connection(&flag_list(&[Flag::SYN, Flag::ACK]), Some(5000)));
+        // another initial sequence number
+        assert!(state_machine.is_new_connection(&syn, Some(5000)));
+    }
+
+    #[test]
+    fn it_can_detect_a_new_connection_of_unknown_sequence_number() {
+        let mut state_machine = TcpStateMachine::new();
+        let syn = flag_list(&[Flag::SYN]);
+        state_machine.update(&syn, true);
+        // a SYN retransmitted during the handshake
+        assert!(!state_machine.is_new_connection(&syn, None));
+        state_machine.update(&flag_list(&[Flag::SYN, Flag::ACK]), false);
+        assert!(!state_machine.is_new_connection(&syn, Some(1000)));
+        state_machine.update(&flag_list(&[Flag::ACK]), true);
+        // a SYN once the connection is established
+        assert!(state_machine.is_new_connection(&syn, None));
+        assert!(state_machine.is_new_connection(&syn, Some(1000)));
+    }
+
+    #[test]
+    fn it_can_detect_a_new_connection_picked_up_in_the_middle() {
+        let mut state_machine = TcpStateMachine::new();
+        let syn = flag_list(&[Flag::SYN]);
+        state_machine.update_with_sequence_number(&flag_list(&[Flag::ACK]), true, Some(1000));
+        assert_eq!(state_machine.state(), TcpState::Established);
+        assert!(state_machine.is_new_connection(&syn, Some(5000)));
+        state_machine.update(&flag_list(&[Flag::FIN]), true);
+        assert!(state_machine.is_new_connection(&syn, None));
+    }
+
+    #[test]
+    fn it_can_detect_a_new_connection_after_a_close() {
+        let mut state_machine = TcpStateMachine::new();
+        let syn = flag_list(&[Flag::SYN]);
+        state_machine.update(&syn, true);
+        state_machine.update(&flag_list(&[Flag::RST]), false);
+        assert!(state_machine.is_new_connection(&syn, None));
+    }
+}
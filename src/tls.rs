@@ -0,0 +1,846 @@
+use std::error;
+use std::fmt;
+
+/// TLS record content type of the handshake messages
+pub const CONTENT_TYPE_HANDSHAKE: u8 = 22;
+/// handshake message type of a ClientHello
+pub const HANDSHAKE_TYPE_CLIENT_HELLO: u8 = 1;
+/// handshake message type of a ServerHello
+pub const HANDSHAKE_TYPE_SERVER_HELLO: u8 = 2;
+/// extension type of the server name indication
+pub const EXTENSION_SERVER_NAME: u16 = 0;
+/// extension type of the supported groups (formerly elliptic curves)
+pub const EXTENSION_SUPPORTED_GROUPS: u16 = 10;
+/// extension type of the EC point formats
+pub const EXTENSION_EC_POINT_FORMATS: u16 = 11;
+/// extension type of the signature algorithms
+pub const EXTENSION_SIGNATURE_ALGORITHMS: u16 = 13;
+/// extension type of the application layer protocol negotiation
+pub const EXTENSION_ALPN: u16 = 16;
+/// extension type of the supported versions
+pub const EXTENSION_SUPPORTED_VERSIONS: u16 = 43;
+
+const RECORD_HEADER_LENGTH: usize = 5;
+const HANDSHAKE_HEADER_LENGTH: usize = 4;
+/// upper bound of a record length: 2^14 bytes of plaintext and 2048 bytes of expansion
+const MAXIMUM_RECORD_LENGTH: usize = 0x4800;
+/// upper bound of a handshake message length, protects the reassembly buffer
+const MAXIMUM_HANDSHAKE_LENGTH: usize = 0x10000;
+/// name type of a host name in the server name list
+const NAME_TYPE_HOST_NAME: u8 = 0;
+
+/// The reason why a TLS handshake can't be parsed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TlsError {
+    /// the payload doesn't start with a handshake record (content type)
+    NotHandshake(u8),
+    /// the first handshake message isn't the one expected (handshake type)
+    UnexpectedMessage(u8),
+    /// a length is greater than its upper bound
+    TooLong {
+        /// the field name
+        field: &'static str,
+        /// the length (number of bytes)
+        length: usize,
+    },
+    /// a field is shorter than its length
+    Truncated {
+        /// the field name
+        field: &'static str,
+        /// the expected length (number of bytes)
+        expected: usize,
+        /// the available length (number of bytes)
+        actual: usize,
+    },
+    /// a field, or an extension, has an inconsistent value
+    Malformed {
+        /// the field name
+        field: &'static str,
+        /// the inconsistency
+        reason: &'static str,
+    },
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsError::NotHandshake(content_type) => {
+                write!(f, "not a TLS handshake record: content type {}", content_type)
+            }
+            TlsError::UnexpectedMessage(handshake_type) => {
+                write!(f, "unexpected TLS handshake message type {}", handshake_type)
+            }
+            TlsError::TooLong { field, length } => write!(f, "too long TLS {}: {} bytes", field, length),
+            TlsError::Truncated {
+                field,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "truncated TLS {}: {} bytes expected, {} available",
+                field, expected, actual
+            ),
+            TlsError::Malformed { field, reason } => write!(f, "malformed TLS {}: {}", field, reason),
+        }
+    }
+}
+
+impl error::Error for TlsError {}
+
+/// A handshake message, reassembled from its records.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HandshakeMessage {
+    /// handshake type (e.g 1 for a ClientHello)
+    pub handshake_type: u8,
+    /// message body, without the handshake header
+    pub body: Vec<u8>,
+}
+
+/// A reader of the handshake messages of one direction of a TLS connection.
+/// The payloads are pushed in order, and a record or a handshake message
+/// may be split across several payloads.
+/// A payload pushed twice is read twice: a retransmitted one must be dropped first,
+/// as a `TcpReassembler` does.
+#[derive(Clone, Debug, Default)]
+pub struct HandshakeReader {
+    record_buffer: Vec<u8>,
+    handshake_buffer: Vec<u8>,
+    /// `true` once a handshake record has been seen
+    started: bool,
+}
+
+impl HandshakeReader {
+    /// Provide a reader waiting for the first record.
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    /// Add a payload, and returns the handshake messages it completes.
+    /// The records which aren't handshake ones are skipped once the handshake started.
+    pub fn push(&mut self, payload: &[u8]) -> Result<Vec<HandshakeMessage>, TlsError> {
+        self.record_buffer.extend_from_slice(payload);
+
+        let mut offset = 0;
+        while self.record_buffer.len() - offset >= RECORD_HEADER_LENGTH {
+            let record_header = &self.record_buffer[offset..offset + RECORD_HEADER_LENGTH];
+            let content_type = record_header[0];
+            let length = u16::from_be_bytes([record_header[3], record_header[4]]) as usize;
+            if content_type != CONTENT_TYPE_HANDSHAKE && !self.started {
+                return Err(TlsError::NotHandshake(content_type));
+            }
+            if length > MAXIMUM_RECORD_LENGTH {
+                return Err(TlsError::TooLong {
+                    field: "record",
+                    length,
+                });
+            }
+            let end = offset + RECORD_HEADER_LENGTH + length;
+            if self.record_buffer.len() < end {
+                break;
+            }
+            if content_type == CONTENT_TYPE_HANDSHAKE {
+                self.started = true;
+                self.handshake_buffer
+                    .extend_from_slice(&self.record_buffer[offset + RECORD_HEADER_LENGTH..end]);
+            }
+            offset = end;
+        }
+        self.record_buffer.drain(..offset);
+
+        let mut message_list = Vec::new();
+        while self.handshake_buffer.len() >= HANDSHAKE_HEADER_LENGTH {
+            let length = u32::from_be_bytes([
+                0,
+                self.handshake_buffer[1],
+                self.handshake_buffer[2],
+                self.handshake_buffer[3],
+            ]) as usize;
+            if length > MAXIMUM_HANDSHAKE_LENGTH {
+                return Err(TlsError::TooLong {
+                    field: "handshake message",
+                    length,
+                });
+            }
+            if self.handshake_buffer.len() < HANDSHAKE_HEADER_LENGTH + length {
+                break;
+            }
+            let mut message: Vec<u8> = self
+                .handshake_buffer
+                .drain(..HANDSHAKE_HEADER_LENGTH + length)
+                .collect();
+            message_list.push(HandshakeMessage {
+                handshake_type: message[0],
+                body: message.split_off(HANDSHAKE_HEADER_LENGTH),
+            });
+        }
+        Ok(message_list)
+    }
+}
+
+/// An extension of a hello message.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Extension {
+    /// extension type (e.g 0 for the server name indication)
+    pub extension_type: u16,
+    /// extension data
+    pub data: Vec<u8>,
+}
+
+/// The fields of a ClientHello message.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ClientHello {
+    /// legacy version (e.g 0x0303 for TLS 1.2)
+    pub version: u16,
+    /// cipher suite list, in the client order
+    pub cipher_suite_list: Vec<u16>,
+    /// compression method list
+    pub compression_method_list: Vec<u8>,
+    /// extension list, in the client order
+    pub extension_list: Vec<Extension>,
+}
+
+impl ClientHello {
+    /// Parse the body of a ClientHello handshake message.
+    pub fn parse(body: &[u8]) -> Result<Self, TlsError> {
+        let mut reader = ByteReader::new(body);
+        let version = reader.read_u16("version")?;
+        reader.read_bytes(32, "random")?;
+        reader.read_vector_u8("session id")?;
+        let cipher_suites = reader.read_vector_u16("cipher suites")?;
+        if cipher_suites.len() % 2 != 0 {
+            return Err(malformed("cipher suites", "odd length"));
+        }
+        let compression_method_list = reader.read_vector_u8("compression methods")?.to_vec();
+        let extension_list = if reader.is_empty() {
+            Vec::new()
+        } else {
+            parse_extension_list(reader.read_vector_u16("extensions")?)?
+        };
+
+        Ok(Self {
+            version,
+            cipher_suite_list: u16_list(cipher_suites),
+            compression_method_list,
+            extension_list,
+        })
+    }
+
+    /// Returns the data of the first extension of the given type.
+    pub fn extension(&self, extension_type: u16) -> Option<&[u8]> {
+        find_extension(&self.extension_list, extension_type)
+    }
+
+    /// Returns the supported groups (elliptic curves) extension list, empty without extension.
+    pub fn supported_group_list(&self) -> Result<Vec<u16>, TlsError> {
+        extension_u16_list(self.extension(EXTENSION_SUPPORTED_GROUPS), false, "supported groups")
+    }
+
+    /// Returns the EC point formats extension list, empty without extension.
+    pub fn ec_point_format_list(&self) -> Result<Vec<u8>, TlsError> {
+        match self.extension(EXTENSION_EC_POINT_FORMATS) {
+            Some(data) => {
+                let mut reader = ByteReader::new(data);
+                let list = reader.read_vector_u8("EC point formats")?.to_vec();
+                reader.finish("EC point formats")?;
+                Ok(list)
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Returns the signature algorithms extension list, empty without extension.
+    pub fn signature_algorithm_list(&self) -> Result<Vec<u16>, TlsError> {
+        extension_u16_list(
+            self.extension(EXTENSION_SIGNATURE_ALGORITHMS),
+            false,
+            "signature algorithms",
+        )
+    }
+
+    /// Returns the supported versions extension list, empty without extension.
+    pub fn supported_version_list(&self) -> Result<Vec<u16>, TlsError> {
+        extension_u16_list(self.extension(EXTENSION_SUPPORTED_VERSIONS), true, "supported versions")
+    }
+
+    /// Returns the ALPN extension protocol list, empty without extension.
+    pub fn alpn_list(&self) -> Result<Vec<Vec<u8>>, TlsError> {
+        alpn_list(self.extension(EXTENSION_ALPN))
+    }
+
+    /// Returns the host name of the server name indication extension, if any.
+    pub fn server_name(&self) -> Result<Option<String>, TlsError> {
+        let data = match self.extension(EXTENSION_SERVER_NAME) {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let mut list_reader = ByteReader::new(data);
+        let mut reader = ByteReader::new(list_reader.read_vector_u16("server name list")?);
+        list_reader.finish("server name list")?;
+        while !reader.is_empty() {
+            let name_type = reader.read_u8("server name type")?;
+            let name = reader.read_vector_u16("server name")?;
+            if name_type == NAME_TYPE_HOST_NAME {
+                if name.is_empty() {
+                    return Err(malformed("server name", "empty host name"));
+                }
+                return match std::str::from_utf8(name) {
+                    Ok(name) if name.is_ascii() => Ok(Some(name.to_string())),
+                    _ => Err(malformed("server name", "host name isn't ASCII")),
+                };
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// The fields of a ServerHello message.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ServerHello {
+    /// legacy version (e.g 0x0303 for TLS 1.2)
+    pub version: u16,
+    /// cipher suite chosen by the server
+    pub cipher_suite: u16,
+    /// compression method chosen by the server
+    pub compression_method: u8,
+    /// extension list, in the server order
+    pub extension_list: Vec<Extension>,
+}
+
+impl ServerHello {
+    /// Parse the body of a ServerHello handshake message.
+    pub fn parse(body: &[u8]) -> Result<Self, TlsError> {
+        let mut reader = ByteReader::new(body);
+        let version = reader.read_u16("version")?;
+        reader.read_bytes(32, "random")?;
+        reader.read_vector_u8("session id")?;
+        let cipher_suite = reader.read_u16("cipher suite")?;
+        let compression_method = reader.read_u8("compression method")?;
+        let extension_list = if reader.is_empty() {
+            Vec::new()
+        } else {
+            parse_extension_list(reader.read_vector_u16("extensions")?)?
+        };
+
+        Ok(Self {
+            version,
+            cipher_suite,
+            compression_method,
+            extension_list,
+        })
+    }
+
+    /// Returns the data of the first extension of the given type.
+    pub fn extension(&self, extension_type: u16) -> Option<&[u8]> {
+        find_extension(&self.extension_list, extension_type)
+    }
+
+    /// Returns the version selected by the supported versions extension, if any.
+    pub fn supported_version(&self) -> Result<Option<u16>, TlsError> {
+        match self.extension(EXTENSION_SUPPORTED_VERSIONS) {
+            Some(data) => {
+                let mut reader = ByteReader::new(data);
+                let version = reader.read_u16("supported version")?;
+                reader.finish("supported version")?;
+                Ok(Some(version))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the protocol selected by the ALPN extension, if any.
+    pub fn alpn(&self) -> Result<Option<Vec<u8>>, TlsError> {
+        Ok(alpn_list(self.extension(EXTENSION_ALPN))?.into_iter().next())
+    }
+}
+
+/// A hello message, sent by the client or by the server.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Hello {
+    /// ClientHello, sent in the forward direction
+    Client(ClientHello),
+    /// ServerHello, sent in the backward direction
+    Server(ServerHello),
+}
+
+/// The state of the TLS parsing of a flow.
+/// The first client payloads are parsed until their ClientHello or an error,
+/// then the first server payloads until their ServerHello or an error.
+#[derive(Clone, Debug, Default)]
+pub struct TlsInspector {
+    forward_reader: HandshakeReader,
+    backward_reader: HandshakeReader,
+    /// `true` once the ClientHello is parsed, or can't be
+    forward_done: bool,
+    /// `true` once the ServerHello is parsed, or can't be
+    backward_done: bool,
+    /// `true` if the ClientHello is parsed
+    client_hello_seen: bool,
+}
+
+impl TlsInspector {
+    /// Provide an inspector waiting for the first client payload.
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    /// Returns `true` while a payload in the given direction may bring a hello message.
+    pub fn is_waiting(&self, forward: bool) -> bool {
+        if forward {
+            !self.forward_done
+        } else {
+            self.client_hello_seen && !self.backward_done
+        }
+    }
+
+    /// Add the payload of a packet, and returns the hello message of its direction once complete.
+    /// After an error, or once the hello message is returned, the payloads of the direction are ignored.
+    pub fn inspect(&mut self, forward: bool, payload: &[u8]) -> Result<Option<Hello>, TlsError> {
+        if !self.is_waiting(forward) || payload.is_empty() {
+            return Ok(None);
+        }
+        let (reader, expected_type) = if forward {
+            (&mut self.forward_reader, HANDSHAKE_TYPE_CLIENT_HELLO)
+        } else {
+            (&mut self.backward_reader, HANDSHAKE_TYPE_SERVER_HELLO)
+        };
+        let result = read_hello(reader, expected_type, payload);
+        if !matches!(result, Ok(None)) {
+            *reader = HandshakeReader::new();
+            if forward {
+                self.forward_done = true;
+                self.client_hello_seen = result.is_ok();
+            } else {
+                self.backward_done = true;
+            }
+        }
+        result
+    }
+}
+
+/// Push a payload to the reader, and parse its first handshake message, of the expected type.
+fn read_hello(reader: &mut HandshakeReader, expected_type: u8, payload: &[u8]) -> Result<Option<Hello>, TlsError> {
+    let message = match reader.push(payload)?.into_iter().next() {
+        Some(message) => message,
+        None => return Ok(None),
+    };
+    match message.handshake_type {
+        handshake_type if handshake_type != expected_type => Err(TlsError::UnexpectedMessage(handshake_type)),
+        HANDSHAKE_TYPE_CLIENT_HELLO => Ok(Some(Hello::Client(ClientHello::parse(&message.body)?))),
+        _ => Ok(Some(Hello::Server(ServerHello::parse(&message.body)?))),
+    }
+}
+
+fn find_extension(extension_list: &[Extension], extension_type: u16) -> Option<&[u8]> {
+    extension_list
+        .iter()
+        .find(|extension| extension.extension_type == extension_type)
+        .map(|extension| extension.data.as_slice())
+}
+
+/// Parse the protocol list of an ALPN extension, empty without extension.
+fn alpn_list(data: Option<&[u8]>) -> Result<Vec<Vec<u8>>, TlsError> {
+    let data = match data {
+        Some(data) => data,
+        None => return Ok(Vec::new()),
+    };
+    let mut list_reader = ByteReader::new(data);
+    let mut reader = ByteReader::new(list_reader.read_vector_u16("ALPN protocol list")?);
+    list_reader.finish("ALPN protocol list")?;
+    let mut protocol_list = Vec::new();
+    while !reader.is_empty() {
+        let protocol = reader.read_vector_u8("ALPN protocol")?;
+        if protocol.is_empty() {
+            return Err(malformed("ALPN protocol", "empty protocol name"));
+        }
+        protocol_list.push(protocol.to_vec());
+    }
+    Ok(protocol_list)
+}
+
+/// Parse a u16 list extension, whose list length takes one or two bytes, empty without extension.
+fn extension_u16_list(data: Option<&[u8]>, one_byte_length: bool, field: &'static str) -> Result<Vec<u16>, TlsError> {
+    let data = match data {
+        Some(data) => data,
+        None => return Ok(Vec::new()),
+    };
+    let mut reader = ByteReader::new(data);
+    let list = if one_byte_length {
+        reader.read_vector_u8(field)?
+    } else {
+        reader.read_vector_u16(field)?
+    };
+    reader.finish(field)?;
+    if list.len() % 2 != 0 {
+        return Err(malformed(field, "odd length"));
+    }
+    Ok(u16_list(list))
+}
+
+/// Parse an extension list, without its length.
+fn parse_extension_list(data: &[u8]) -> Result<Vec<Extension>, TlsError> {
+    let mut reader = ByteReader::new(data);
+    let mut extension_list = Vec::new();
+    while !reader.is_empty() {
+        let extension_type = reader.read_u16("extension type")?;
+        let data = reader.read_vector_u16("extension")?.to_vec();
+        extension_list.push(Extension { extension_type, data });
+    }
+    Ok(extension_list)
+}
+
+/// Convert big endian bytes to a list of u16.
+fn u16_list(data: &[u8]) -> Vec<u16> {
+    data.chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect()
+}
+
+fn malformed(field: &'static str, reason: &'static str) -> TlsError {
+    TlsError::Malformed { field, reason }
+}
+
+/// A reader of the big endian fields of a message.
+struct ByteReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.offset >= self.data.len()
+    }
+
+    fn read_bytes(&mut self, length: usize, field: &'static str) -> Result<&'a [u8], TlsError> {
+        let available = self.data.len() - self.offset;
+        if available < length {
+            return Err(TlsError::Truncated {
+                field,
+                expected: length,
+                actual: available,
+            });
+        }
+        let bytes = &self.data[self.offset..self.offset + length];
+        self.offset += length;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self, field: &'static str) -> Result<u8, TlsError> {
+        Ok(self.read_bytes(1, field)?[0])
+    }
+
+    fn read_u16(&mut self, field: &'static str) -> Result<u16, TlsError> {
+        let bytes = self.read_bytes(2, field)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Returns an error if some bytes remain.
+    fn finish(&self, field: &'static str) -> Result<(), TlsError> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(malformed(field, "trailing bytes"))
+        }
+    }
+
+    /// Read a vector with a one byte length.
+    fn read_vector_u8(&mut self, field: &'static str) -> Result<&'a [u8], TlsError> {
+        let length = self.read_u8(field)? as usize;
+        self.read_bytes(length, field)
+    }
+
+    /// Read a vector with a two bytes length.
+    fn read_vector_u16(&mut self, field: &'static str) -> Result<&'a [u8], TlsError> {
+        let length = self.read_u16(field)? as usize;
+        self.read_bytes(length, field)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::flow_id::FlowId;
+    use crate::generator::Generator;
+    use crate::packet::Packet;
+    use crate::stream::TcpReassembler;
+    use crate::tls::{
+        ClientHello, HandshakeReader, Hello, TlsError, TlsInspector, EXTENSION_SERVER_NAME,
+        EXTENSION_SUPPORTED_VERSIONS,
+    };
+
+    pub(crate) fn with_u16_length(data: &[u8]) -> Vec<u8> {
+        let mut vector = (data.len() as u16).to_be_bytes().to_vec();
+        vector.extend_from_slice(data);
+        vector
+    }
+
+    pub(crate) fn server_name_extension(host_name: &str) -> Vec<u8> {
+        let mut entry = vec![0];
+        entry.extend(with_u16_length(host_name.as_bytes()));
+        with_u16_length(&entry)
+    }
+
+    /// a ClientHello body, with the given cipher suites and extensions
+    pub(crate) fn client_hello_body(
+        version: u16,
+        cipher_suite_list: &[u16],
+        extension_list: &[(u16, Vec<u8>)],
+    ) -> Vec<u8> {
+        let mut body = version.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0x42; 32]);
+        // session id
+        body.push(0);
+        let cipher_suites: Vec<u8> = cipher_suite_list
+            .iter()
+            .flat_map(|c| c.to_be_bytes().to_vec())
+            .collect();
+        body.extend(with_u16_length(&cipher_suites));
+        // null compression
+        body.extend_from_slice(&[1, 0]);
+        body.extend(extension_list_bytes(extension_list));
+        body
+    }
+
+    /// a ServerHello body, with the given cipher suite and extensions
+    pub(crate) fn server_hello_body(version: u16, cipher_suite: u16, extension_list: &[(u16, Vec<u8>)]) -> Vec<u8> {
+        let mut body = version.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0x24; 32]);
+        // session id, cipher suite then null compression
+        body.push(0);
+        body.extend_from_slice(&cipher_suite.to_be_bytes());
+        body.push(0);
+        body.extend(extension_list_bytes(extension_list));
+        body
+    }
+
+    fn extension_list_bytes(extension_list: &[(u16, Vec<u8>)]) -> Vec<u8> {
+        let mut extensions = Vec::new();
+        for (extension_type, data) in extension_list {
+            extensions.extend_from_slice(&extension_type.to_be_bytes());
+            extensions.extend(with_u16_length(data));
+        }
+        with_u16_length(&extensions)
+    }
+
+    /// a handshake record holding the given handshake message
+    pub(crate) fn handshake_record(handshake_type: u8, body: &[u8]) -> Vec<u8> {
+        let mut message = vec![handshake_type];
+        message.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        message.extend_from_slice(body);
+        let mut record = vec![22, 0x03, 0x01];
+        record.extend(with_u16_length(&message));
+        record
+    }
+
+    fn client_hello_record(host_name: &str) -> Vec<u8> {
+        let body = client_hello_body(
+            0x0303,
+            &[0x1301, 0xC02F],
+            &[(EXTENSION_SERVER_NAME, server_name_extension(host_name))],
+        );
+        handshake_record(1, &body)
+    }
+
+    #[test]
+    fn test_new() {
+        let inspector = TlsInspector::new();
+        assert!(inspector.is_waiting(true));
+        assert!(!inspector.is_waiting(false));
+    }
+
+    #[test]
+    fn it_can_parse_a_client_hello() {
+        let mut reader = HandshakeReader::new();
+        let message_list = reader.push(&client_hello_record("www.example.com")).unwrap();
+        assert_eq!(message_list.len(), 1);
+        assert_eq!(message_list[0].handshake_type, 1);
+
+        let client_hello = ClientHello::parse(&message_list[0].body).unwrap();
+        assert_eq!(client_hello.version, 0x0303);
+        assert_eq!(client_hello.cipher_suite_list, vec![0x1301, 0xC02F]);
+        assert_eq!(client_hello.compression_method_list, vec![0]);
+        assert_eq!(client_hello.server_name(), Ok(Some("www.example.com".to_string())));
+    }
+
+    #[test]
+    fn it_can_parse_a_client_hello_split_across_segments() {
+        let record = client_hello_record("mtalk.google.com");
+        let mut inspector = TlsInspector::new();
+        for byte in &record[..record.len() - 1] {
+            assert_eq!(inspector.inspect(true, &[*byte]), Ok(None));
+        }
+        // a backward payload doesn't interfere
+        assert_eq!(inspector.inspect(false, &[0x17, 0x03]), Ok(None));
+        let client_hello = match inspector.inspect(true, &record[record.len() - 1..]) {
+            Ok(Some(Hello::Client(client_hello))) => client_hello,
+            result => panic!("unexpected {:?}", result),
+        };
+        assert_eq!(client_hello.server_name(), Ok(Some("mtalk.google.com".to_string())));
+        assert!(!inspector.is_waiting(true));
+        assert!(inspector.is_waiting(false));
+    }
+
+    #[test]
+    fn it_can_parse_a_server_hello_after_a_client_hello() {
+        let mut inspector = TlsInspector::new();
+        inspector
+            .inspect(true, &client_hello_record("www.example.com"))
+            .unwrap();
+
+        let body = server_hello_body(0x0303, 0x1301, &[(EXTENSION_SUPPORTED_VERSIONS, vec![0x03, 0x04])]);
+        let mut payload = handshake_record(2, &body);
+        // the certificate message which follows is ignored
+        payload.extend(handshake_record(11, &[0; 3]));
+        let server_hello = match inspector.inspect(false, &payload) {
+            Ok(Some(Hello::Server(server_hello))) => server_hello,
+            result => panic!("unexpected {:?}", result),
+        };
+        assert_eq!(server_hello.version, 0x0303);
+        assert_eq!(server_hello.cipher_suite, 0x1301);
+        assert_eq!(server_hello.supported_version(), Ok(Some(0x0304)));
+        assert_eq!(server_hello.alpn(), Ok(None));
+        assert!(!inspector.is_waiting(false));
+    }
+
+    #[test]
+    fn it_can_parse_a_client_hello_split_across_records() {
+        let message = &client_hello_record("www.example.com")[5..];
+        let mut payload = Vec::new();
+        for fragment in message.chunks(10) {
+            payload.extend_from_slice(&[22, 0x03, 0x01, 0, fragment.len() as u8]);
+            payload.extend_from_slice(fragment);
+        }
+        let mut reader = HandshakeReader::new();
+        let message_list = reader.push(&payload).unwrap();
+        assert_eq!(message_list.len(), 1);
+        assert_eq!(message_list[0].body.len(), message.len() - 4);
+    }
+
+    #[test]
+    fn it_cannot_parse_a_malformed_server_name_extension() {
+        let mut extension = server_name_extension("www.example.com");
+        // the list length is greater than the extension data
+        extension[1] += 1;
+        let body = client_hello_body(0x0303, &[0x1301], &[(EXTENSION_SERVER_NAME, extension)]);
+        let client_hello = ClientHello::parse(&body).unwrap();
+        assert_eq!(
+            client_hello.server_name(),
+            Err(TlsError::Truncated {
+                field: "server name list",
+                expected: 19,
+                actual: 18
+            })
+        );
+
+        let body = client_hello_body(0x0303, &[0x1301], &[(EXTENSION_SERVER_NAME, server_name_extension(""))]);
+        assert_eq!(
+            ClientHello::parse(&body).unwrap().server_name(),
+            Err(TlsError::Malformed {
+                field: "server name",
+                reason: "empty host name"
+            })
+        );
+    }
+
+    #[test]
+    fn it_cannot_parse_a_truncated_extension_list() {
+        let mut body = client_hello_body(
+            0x0303,
+            &[0x1301],
+            &[(EXTENSION_SERVER_NAME, server_name_extension("a"))],
+        );
+        body.pop();
+        assert!(matches!(
+            ClientHello::parse(&body),
+            Err(TlsError::Truncated {
+                field: "extensions",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn it_cannot_inspect_a_payload_other_than_tls() {
+        let mut inspector = TlsInspector::new();
+        assert_eq!(
+            inspector.inspect(true, b"GET / HTTP/1.1\r\n"),
+            Err(TlsError::NotHandshake(b'G'))
+        );
+        // the next payloads are ignored
+        assert_eq!(
+            inspector.inspect(true, &client_hello_record("www.example.com")),
+            Ok(None)
+        );
+
+        let mut inspector = TlsInspector::new();
+        assert_eq!(
+            inspector.inspect(true, &handshake_record(2, &[0; 38])),
+            Err(TlsError::UnexpectedMessage(2))
+        );
+    }
+
+    #[test]
+    fn it_can_fill_the_sni_of_a_tcp_flow() {
+        let client_flow_id = FlowId::new(6, "10.0.0.1", "10.0.0.2", 42254, 443);
+        let server_flow_id = FlowId::new(6, "10.0.0.2", "10.0.0.1", 443, 42254);
+        let record = client_hello_record("www.example.com");
+        let mut generator = Generator::new();
+        generator.add_packet_with_payload(client_flow_id, Packet::new(), &record[..20]);
+        generator.add_packet_with_payload(server_flow_id, Packet::new(), &[]);
+        assert!(generator.get_mut(&client_flow_id).unwrap().sni.is_none());
+        generator.add_packet_with_payload(client_flow_id, Packet::new(), &record[20..]);
+        assert_eq!(
+            generator.get_mut(&client_flow_id).unwrap().sni.as_deref(),
+            Some("www.example.com")
+        );
+        let flow_information = generator.get_mut(&client_flow_id).unwrap();
+        assert_eq!(flow_information.ja3.as_deref(), Some("771,4865-49199,0,,"));
+        assert!(flow_information.ja4.as_deref().unwrap().starts_with("t12d0201"));
+        assert!(flow_information.ja3s.is_none());
+        let server_hello = server_hello_body(0x0303, 0xC02F, &[]);
+        generator.add_packet_with_payload(server_flow_id, Packet::new(), &handshake_record(2, &server_hello));
+        let flow_information = generator.get_mut(&client_flow_id).unwrap();
+        assert_eq!(flow_information.ja3s.as_deref(), Some("771,49199,"));
+        assert_eq!(flow_information.ja4s.as_deref(), Some("t120000_c02f_000000000000"));
+
+        // not over UDP
+        let udp_flow_id = FlowId::new(17, "10.0.0.1", "10.0.0.2", 42254, 443);
+        generator.add_packet_with_payload(udp_flow_id, Packet::new(), &record);
+        assert!(generator.get_mut(&udp_flow_id).unwrap().sni.is_none());
+    }
+
+    #[test]
+    fn it_can_fill_the_sni_of_a_retransmitted_client_hello() {
+        let client_flow_id = FlowId::new(6, "10.0.0.1", "10.0.0.2", 42254, 443);
+        let record = client_hello_record("www.example.com");
+        let segment = |sequence_number| {
+            let mut packet = Packet::new();
+            packet.sequence_number = Some(sequence_number);
+            packet
+        };
+        let mut generator = Generator::new();
+        generator.tcp_reassembler = Some(TcpReassembler::new());
+        generator.add_packet_with_payload(client_flow_id, segment(1000), &record[..20]);
+        // the beginning of the ClientHello is retransmitted, then its end comes
+        generator.add_packet_with_payload(client_flow_id, segment(1000), &record[..20]);
+        generator.add_packet_with_payload(client_flow_id, segment(1020), &record[20..]);
+        assert_eq!(
+            generator.get_mut(&client_flow_id).unwrap().sni.as_deref(),
+            Some("www.example.com")
+        );
+    }
+
+    #[test]
+    fn test_tls_error_display() {
+        assert_eq!(
+            TlsError::Malformed {
+                field: "server name",
+                reason: "empty host name"
+            }
+            .to_string(),
+            "malformed TLS server name: empty host name"
+        );
+    }
+}
@@ -0,0 +1,532 @@
+use std::collections::vec_deque::Drain;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use log::debug;
+
+use crate::decoder::IPPROTO_TCP;
+use crate::direction::DirectionPolicy;
+use crate::flow_id::FlowId;
+use crate::flow_information::FlowInformation;
+use crate::packet::Packet;
+use crate::stream::TcpReassembler;
+use crate::tcp_state::{EndReason, TcpState, TcpStateMachine};
+
+/// default idle timeout, as the NetFlow inactive timeout
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(15);
+/// default active timeout, as the NetFlow active timeout
+pub const DEFAULT_ACTIVE_TIMEOUT: Duration = Duration::from_secs(1800);
+/// the flows are checked for a timeout at most once by sweep interval
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A flow followed by the tracker.
+#[derive(Debug)]
+struct TrackedFlow {
+    flow_information: FlowInformation,
+    first_timestamp: Duration,
+    last_timestamp: Duration,
+    /// state of a TCP flow
+    tcp_state_machine: Option<TcpStateMachine>,
+}
+
+/// A streaming flow tracker.
+/// The packets are added one at a time, in capture order,
+/// and their timestamps drive the tracker clock.
+/// A flow ends when no packet is seen during the idle timeout,
+/// or when it lasts longer than the active timeout (the next packets then start a new flow),
+/// as the NetFlow and IPFIX exporters do.
+/// A TCP flow also ends as soon as its connection is closed or reset,
+/// and a new SYN on its 5-tuple starts a new flow.
+/// The finished flows are queued until they are drained.
+#[derive(Debug)]
+pub struct FlowTracker {
+    /// maximum duration without packet of a flow
+    pub idle_timeout: Duration,
+    /// maximum duration of a flow
+    pub active_timeout: Duration,
+    /// policy choosing the client of the flows
+    pub direction_policy: DirectionPolicy,
+    /// reassembler of the TCP byte streams, the payloads being parsed in sequence order when set
+    pub tcp_reassembler: Option<TcpReassembler>,
+    flow_map: HashMap<FlowId, TrackedFlow>,
+    finished_list: VecDeque<(FlowId, FlowInformation)>,
+    now: Duration,
+    last_sweep: Duration,
+}
+
+impl Default for FlowTracker {
+    /// Create a tracker with the default NetFlow timeouts.
+    fn default() -> Self {
+        Self::new(DEFAULT_IDLE_TIMEOUT, DEFAULT_ACTIVE_TIMEOUT)
+    }
+}
+
+impl FlowTracker {
+    /// Provide a tracker without flow, with the given timeouts.
+    pub fn new(idle_timeout: Duration, active_timeout: Duration) -> Self {
+        Self {
+            idle_timeout,
+            active_timeout,
+            direction_policy: DirectionPolicy::default(),
+            tcp_reassembler: None,
+            flow_map: HashMap::new(),
+            finished_list: VecDeque::new(),
+            now: Duration::default(),
+            last_sweep: Duration::default(),
+        }
+    }
+
+    /// Returns the number of flows in progress.
+    pub fn len(&self) -> usize {
+        self.flow_map.len()
+    }
+
+    /// Returns `true` if no flow is in progress.
+    pub fn is_empty(&self) -> bool {
+        self.flow_map.is_empty()
+    }
+
+    /// Add a packet to its flow, ending the flows which timed out on the way.
+    /// The packet is a forward one when it is sent by the client chosen by the direction policy,
+    /// a backward one otherwise.
+    pub fn add_packet(&mut self, flow_id: FlowId, packet: Packet) {
+        self.add_packet_with_payload(flow_id, packet, &[]);
+    }
+
+    /// Add a packet as `add_packet` does, then parse its TCP payload to fill the SNI.
+    /// With a TCP reassembler, the payload is first put back in sequence order with the previous ones
+    /// and the reassembled streams are kept with the flow information when the flow ends.
+    /// A payload which can't be parsed is skipped.
+    pub fn add_packet_with_payload(&mut self, flow_id: FlowId, packet: Packet, payload: &[u8]) {
+        let timestamp = packet.timestamp;
+        if timestamp > self.now {
+            self.now = timestamp;
+        }
+        if self.now >= self.last_sweep + SWEEP_INTERVAL {
+            self.expire(self.now);
+        }
+
+        // the flow may have timed out since the last sweep, or its 5-tuple be reused
+        if let Some(tracked_flow) = self.flow_map.get(&flow_id) {
+            let end_reason = match self.timeout_reason(tracked_flow, timestamp) {
+                Some(end_reason) => Some(end_reason),
+                None => match &tracked_flow.tcp_state_machine {
+                    Some(tcp_state_machine)
+                        if tcp_state_machine.is_new_connection(&packet.flag_list, packet.sequence_number) =>
+                    {
+                        Some(EndReason::NewConnection)
+                    }
+                    _ => None,
+                },
+            };
+            if let Some(end_reason) = end_reason {
+                self.finish(&flow_id, end_reason);
+            }
+        }
+
+        let tracked_flow = self.flow_map.entry(flow_id).or_insert_with(|| TrackedFlow {
+            flow_information: FlowInformation::new(),
+            first_timestamp: timestamp,
+            last_timestamp: timestamp,
+            tcp_state_machine: if flow_id.transport_protocol == IPPROTO_TCP {
+                Some(TcpStateMachine::new())
+            } else {
+                None
+            },
+        });
+        if timestamp > tracked_flow.last_timestamp {
+            tracked_flow.last_timestamp = timestamp;
+        }
+        let forward = tracked_flow
+            .flow_information
+            .resolve_forward(&flow_id, &packet, self.direction_policy);
+        if let Some(tcp_state_machine) = &mut tracked_flow.tcp_state_machine {
+            tcp_state_machine.update_with_sequence_number(&packet.flag_list, forward, packet.sequence_number);
+            let flow_information = &mut tracked_flow.flow_information;
+            let mut add_payload = |payload: &[u8]| {
+                if let Err(error) = flow_information.add_payload(forward, payload) {
+                    debug!("flow {} payload skipped: {}", flow_id, error);
+                }
+            };
+            match &mut self.tcp_reassembler {
+                Some(tcp_reassembler) => tcp_reassembler.add_segment(&flow_id, forward, &packet, payload, add_payload),
+                None => add_payload(payload),
+            }
+        }
+        tracked_flow
+            .flow_information
+            .push_packet(&flow_id, packet, self.direction_policy);
+
+        // a closed or reset connection ends immediately
+        let end_reason = match tracked_flow.tcp_state_machine.as_ref().map(TcpStateMachine::state) {
+            Some(TcpState::Closed) => Some(EndReason::Close),
+            Some(TcpState::Reset) => Some(EndReason::Reset),
+            _ => None,
+        };
+        if let Some(end_reason) = end_reason {
+            self.finish(&flow_id, end_reason);
+        }
+    }
+
+    /// End the flows which timed out at the given time.
+    /// The flows ended together are queued by start time.
+    pub fn expire(&mut self, now: Duration) {
+        self.last_sweep = now;
+        let mut expired_list: Vec<(FlowId, EndReason, Duration)> = self
+            .flow_map
+            .iter()
+            .filter_map(|(flow_id, tracked_flow)| {
+                self.timeout_reason(tracked_flow, now)
+                    .map(|end_reason| (*flow_id, end_reason, tracked_flow.first_timestamp))
+            })
+            .collect();
+        expired_list.sort_by_key(|(_, _, first_timestamp)| *first_timestamp);
+        for (flow_id, end_reason, _) in expired_list {
+            self.finish(&flow_id, end_reason);
+        }
+    }
+
+    /// End every flow in progress, as at the end of a capture.
+    pub fn flush(&mut self) {
+        let mut flow_list: Vec<(FlowId, Duration)> = self
+            .flow_map
+            .iter()
+            .map(|(flow_id, tracked_flow)| (*flow_id, tracked_flow.first_timestamp))
+            .collect();
+        flow_list.sort_by_key(|(_, first_timestamp)| *first_timestamp);
+        for (flow_id, _) in flow_list {
+            self.finish(&flow_id, EndReason::EndOfCapture);
+        }
+    }
+
+    /// Returns the number of finished flows not drained yet.
+    pub fn finished_len(&self) -> usize {
+        self.finished_list.len()
+    }
+
+    /// Removes the finished flows, in the order they ended, and returns them in an iterator.
+    pub fn drain_finished(&mut self) -> Drain<'_, (FlowId, FlowInformation)> {
+        self.finished_list.drain(..)
+    }
+
+    /// Removes the finished flows, in the order they ended, and passes each one to the callback.
+    pub fn for_each_finished<F: FnMut(FlowId, FlowInformation)>(&mut self, mut callback: F) {
+        for (flow_id, flow_information) in self.finished_list.drain(..) {
+            callback(flow_id, flow_information);
+        }
+    }
+
+    /// Returns the reason of the end of the flow if it timed out at the given time.
+    fn timeout_reason(&self, tracked_flow: &TrackedFlow, now: Duration) -> Option<EndReason> {
+        let end_reason = if now.saturating_sub(tracked_flow.last_timestamp) >= self.idle_timeout {
+            EndReason::IdleTimeout
+        } else if now.saturating_sub(tracked_flow.first_timestamp) >= self.active_timeout {
+            EndReason::ActiveTimeout
+        } else {
+            return None;
+        };
+        match tracked_flow.tcp_state_machine.as_ref().map(TcpStateMachine::state) {
+            Some(state) if state.is_half_open() => Some(EndReason::HalfOpen),
+            // both FIN seen, only the last acknowledgment is missing
+            Some(TcpState::Closing) => Some(EndReason::Close),
+            _ => Some(end_reason),
+        }
+    }
+
+    /// Move a flow in progress to the finished ones.
+    fn finish(&mut self, flow_id: &FlowId, end_reason: EndReason) {
+        if let Some((key, tracked_flow)) = self.flow_map.remove_entry(flow_id) {
+            let mut flow_information = tracked_flow.flow_information;
+            flow_information.tcp_state = tracked_flow.tcp_state_machine.as_ref().map(TcpStateMachine::state);
+            flow_information.end_reason = Some(end_reason);
+            flow_information.tcp_stream = self
+                .tcp_reassembler
+                .as_mut()
+                .and_then(|tcp_reassembler| tcp_reassembler.remove(&key));
+            self.finished_list.push_back((key, flow_information));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::direction::DirectionPolicy;
+    use crate::flag::Flag;
+    use crate::flow_id::FlowId;
+    use crate::packet::Packet;
+    use crate::stream::TcpReassembler;
+    use crate::tcp_state::{EndReason, TcpState};
+    use crate::tls::tests::{client_hello_body, handshake_record, server_name_extension};
+    use crate::tracker::FlowTracker;
+
+    fn packet_at(seconds: u64) -> Packet {
+        let mut packet = Packet::new();
+        packet.timestamp = Duration::from_secs(seconds);
+        packet.position = seconds as usize;
+        packet
+    }
+
+    fn tcp_packet_at(seconds: u64, flags: &[Flag]) -> Packet {
+        let mut packet = packet_at(seconds);
+        packet.flag_list = flags.iter().cloned().collect();
+        packet
+    }
+
+    fn syn_at(seconds: u64, sequence_number: u32) -> Packet {
+        let mut packet = tcp_packet_at(seconds, &[Flag::SYN]);
+        packet.sequence_number = Some(sequence_number);
+        packet
+    }
+
+    fn client_flow_id() -> FlowId {
+        FlowId::new(6, "10.0.0.1", "10.0.0.2", 42254, 443)
+    }
+
+    fn server_flow_id() -> FlowId {
+        FlowId::new(6, "10.0.0.2", "10.0.0.1", 443, 42254)
+    }
+
+    fn add_handshake(tracker: &mut FlowTracker) {
+        tracker.add_packet(client_flow_id(), syn_at(1, 1000));
+        tracker.add_packet(server_flow_id(), tcp_packet_at(1, &[Flag::SYN, Flag::ACK]));
+        tracker.add_packet(client_flow_id(), tcp_packet_at(1, &[Flag::ACK]));
+    }
+
+    fn forward_flow_id() -> FlowId {
+        FlowId::new(17, "127.0.0.1", "192.168.0.1", 8001, 8002)
+    }
+
+    fn backward_flow_id() -> FlowId {
+        FlowId::new(17, "192.168.0.1", "127.0.0.1", 8002, 8001)
+    }
+
+    #[test]
+    fn test_default() {
+        let tracker = FlowTracker::default();
+        assert_eq!(tracker.idle_timeout, Duration::from_secs(15));
+        assert_eq!(tracker.active_timeout, Duration::from_secs(1800));
+        assert!(tracker.is_empty());
+        assert_eq!(tracker.finished_len(), 0);
+    }
+
+    #[test]
+    fn it_can_group_forward_and_backward_packets() {
+        let mut tracker = FlowTracker::new(Duration::from_secs(10), Duration::from_secs(100));
+        tracker.add_packet(forward_flow_id(), packet_at(1));
+        tracker.add_packet(backward_flow_id(), packet_at(2));
+        tracker.add_packet(forward_flow_id(), packet_at(3));
+        assert_eq!(tracker.len(), 1);
+        assert_eq!(tracker.finished_len(), 0);
+
+        tracker.flush();
+        let finished_list: Vec<_> = tracker.drain_finished().collect();
+        assert_eq!(finished_list.len(), 1);
+        let (flow_id, flow_information) = &finished_list[0];
+        assert_eq!(flow_id.src_port, 8001);
+        assert_eq!(flow_information.forward_packet_list.len(), 2);
+        assert_eq!(flow_information.backward_packet_list.len(), 1);
+        assert!(tracker.is_empty());
+    }
+
+    #[test]
+    fn it_can_end_an_idle_flow() {
+        let mut tracker = FlowTracker::new(Duration::from_secs(10), Duration::from_secs(100));
+        tracker.add_packet(forward_flow_id(), packet_at(1));
+        let other_flow_id = FlowId::new(6, "10.0.0.1", "10.0.0.2", 1234, 80);
+        tracker.add_packet(other_flow_id, packet_at(5));
+        // the first flow is idle for 10 seconds, the other one for 6 seconds
+        tracker.add_packet(other_flow_id, packet_at(11));
+
+        assert_eq!(tracker.len(), 1);
+        let finished_list: Vec<_> = tracker.drain_finished().collect();
+        assert_eq!(finished_list.len(), 1);
+        assert_eq!(finished_list[0].0, forward_flow_id());
+        assert_eq!(finished_list[0].1.end_reason, Some(EndReason::IdleTimeout));
+        assert_eq!(finished_list[0].1.tcp_state, None);
+
+        // a late packet of the ended flow starts a new one
+        tracker.add_packet(backward_flow_id(), packet_at(12));
+        assert_eq!(tracker.len(), 2);
+    }
+
+    #[test]
+    fn it_can_end_an_active_flow() {
+        let mut tracker = FlowTracker::new(Duration::from_secs(10), Duration::from_secs(20));
+        for seconds in 0..25 {
+            tracker.add_packet(forward_flow_id(), packet_at(seconds));
+        }
+        assert_eq!(tracker.len(), 1);
+
+        let mut finished_list = Vec::new();
+        tracker.for_each_finished(|flow_id, flow_information| finished_list.push((flow_id, flow_information)));
+        assert_eq!(finished_list.len(), 1);
+        let flow_information = &finished_list[0].1;
+        assert_eq!(flow_information.forward_packet_list.len(), 20);
+        assert_eq!(flow_information.forward_packet_list.last().unwrap().position, 19);
+        assert_eq!(flow_information.end_reason, Some(EndReason::ActiveTimeout));
+
+        tracker.flush();
+        let (_, flow_information) = tracker.drain_finished().next().unwrap();
+        assert_eq!(flow_information.forward_packet_list.len(), 5);
+        assert_eq!(flow_information.forward_packet_list[0].position, 20);
+    }
+
+    #[test]
+    fn it_can_end_flows_at_a_given_time() {
+        let mut tracker = FlowTracker::new(Duration::from_secs(10), Duration::from_secs(100));
+        tracker.add_packet(forward_flow_id(), packet_at(1));
+        tracker.add_packet(FlowId::new(6, "10.0.0.1", "10.0.0.2", 1234, 80), packet_at(2));
+
+        tracker.expire(Duration::from_secs(11));
+        assert_eq!(tracker.len(), 1);
+        assert_eq!(tracker.finished_len(), 1);
+
+        tracker.expire(Duration::from_secs(12));
+        assert!(tracker.is_empty());
+        let finished_list: Vec<_> = tracker.drain_finished().collect();
+        assert_eq!(finished_list.len(), 2);
+        assert_eq!(finished_list[0].0, forward_flow_id());
+    }
+
+    #[test]
+    fn it_can_end_a_closed_tcp_flow_immediately() {
+        let mut tracker = FlowTracker::default();
+        add_handshake(&mut tracker);
+        tracker.add_packet(client_flow_id(), tcp_packet_at(2, &[Flag::FIN, Flag::ACK]));
+        tracker.add_packet(server_flow_id(), tcp_packet_at(2, &[Flag::FIN, Flag::ACK]));
+        assert_eq!(tracker.finished_len(), 0);
+        tracker.add_packet(client_flow_id(), tcp_packet_at(2, &[Flag::ACK]));
+
+        assert!(tracker.is_empty());
+        let (flow_id, flow_information) = tracker.drain_finished().next().unwrap();
+        assert_eq!(flow_id.src_port, 42254);
+        assert_eq!(flow_information.forward_packet_list.len(), 4);
+        assert_eq!(flow_information.backward_packet_list.len(), 2);
+        assert_eq!(flow_information.tcp_state, Some(TcpState::Closed));
+        assert_eq!(flow_information.end_reason, Some(EndReason::Close));
+    }
+
+    #[test]
+    fn it_can_end_a_reset_tcp_flow_immediately() {
+        let mut tracker = FlowTracker::default();
+        add_handshake(&mut tracker);
+        tracker.add_packet(server_flow_id(), tcp_packet_at(2, &[Flag::RST]));
+
+        assert!(tracker.is_empty());
+        let (_, flow_information) = tracker.drain_finished().next().unwrap();
+        assert_eq!(flow_information.backward_packet_list.len(), 2);
+        assert_eq!(flow_information.tcp_state, Some(TcpState::Reset));
+        assert_eq!(flow_information.end_reason, Some(EndReason::Reset));
+    }
+
+    #[test]
+    fn it_can_keep_a_retransmitted_syn_in_its_flow() {
+        let mut tracker = FlowTracker::default();
+        tracker.add_packet(client_flow_id(), syn_at(1, 1000));
+        tracker.add_packet(server_flow_id(), tcp_packet_at(1, &[Flag::SYN, Flag::ACK]));
+        // the SYN-ACK was lost on its way to the client
+        tracker.add_packet(client_flow_id(), syn_at(2, 1000));
+
+        assert_eq!(tracker.len(), 1);
+        assert!(tracker.drain_finished().next().is_none());
+        tracker.flush();
+        let (_, flow_information) = tracker.drain_finished().next().unwrap();
+        assert_eq!(flow_information.forward_packet_list.len(), 2);
+        assert_eq!(flow_information.backward_packet_list.len(), 1);
+    }
+
+    #[test]
+    fn it_can_start_a_new_flow_on_a_reused_tcp_tuple() {
+        let mut tracker = FlowTracker::default();
+        add_handshake(&mut tracker);
+        tracker.add_packet(client_flow_id(), syn_at(2, 5000));
+
+        assert_eq!(tracker.len(), 1);
+        let (_, flow_information) = tracker.drain_finished().next().unwrap();
+        assert_eq!(flow_information.forward_packet_list.len(), 2);
+        assert_eq!(flow_information.tcp_state, Some(TcpState::Established));
+        assert_eq!(flow_information.end_reason, Some(EndReason::NewConnection));
+
+        tracker.flush();
+        let (_, flow_information) = tracker.drain_finished().next().unwrap();
+        assert_eq!(flow_information.forward_packet_list.len(), 1);
+        assert_eq!(flow_information.tcp_state, Some(TcpState::SynSent));
+        assert_eq!(flow_information.end_reason, Some(EndReason::EndOfCapture));
+    }
+
+    #[test]
+    fn it_can_start_a_new_flow_on_a_tcp_tuple_picked_up_in_the_middle() {
+        let mut tracker = FlowTracker::default();
+        tracker.add_packet(client_flow_id(), tcp_packet_at(1, &[Flag::ACK]));
+        tracker.add_packet(server_flow_id(), tcp_packet_at(1, &[Flag::ACK]));
+        // neither the SYN of the first connection nor the sequence number of the new one are known
+        tracker.add_packet(client_flow_id(), tcp_packet_at(2, &[Flag::SYN]));
+
+        assert_eq!(tracker.len(), 1);
+        let (_, flow_information) = tracker.drain_finished().next().unwrap();
+        assert_eq!(flow_information.forward_packet_list.len(), 1);
+        assert_eq!(flow_information.backward_packet_list.len(), 1);
+        assert_eq!(flow_information.end_reason, Some(EndReason::NewConnection));
+    }
+
+    #[test]
+    fn it_can_end_a_half_open_tcp_flow() {
+        let mut tracker = FlowTracker::new(Duration::from_secs(10), Duration::from_secs(100));
+        tracker.add_packet(client_flow_id(), tcp_packet_at(1, &[Flag::SYN]));
+        tracker.add_packet(client_flow_id(), tcp_packet_at(2, &[Flag::SYN]));
+        tracker.expire(Duration::from_secs(12));
+
+        let (_, flow_information) = tracker.drain_finished().next().unwrap();
+        assert_eq!(flow_information.forward_packet_list.len(), 2);
+        assert_eq!(flow_information.tcp_state, Some(TcpState::SynSent));
+        assert_eq!(flow_information.end_reason, Some(EndReason::HalfOpen));
+    }
+
+    #[test]
+    fn it_can_orient_a_flow_caught_after_its_syn() {
+        let mut tracker = FlowTracker::new(Duration::from_secs(10), Duration::from_secs(100));
+        tracker.direction_policy = DirectionPolicy::SynSender;
+        tracker.add_packet(server_flow_id(), tcp_packet_at(1, &[Flag::SYN, Flag::ACK]));
+        tracker.add_packet(client_flow_id(), tcp_packet_at(1, &[Flag::ACK]));
+
+        tracker.flush();
+        let (_, flow_information) = tracker.drain_finished().next().unwrap();
+        assert_eq!(flow_information.client, Some(client_flow_id().source()));
+        assert_eq!(flow_information.forward_packet_list.len(), 1);
+        assert_eq!(flow_information.backward_packet_list.len(), 1);
+        assert_eq!(flow_information.tcp_state, Some(TcpState::Established));
+    }
+
+    #[test]
+    fn it_can_reassemble_the_tcp_streams_of_a_flow() {
+        let mut tracker = FlowTracker::new(Duration::from_secs(10), Duration::from_secs(100));
+        tracker.tcp_reassembler = Some(TcpReassembler::new());
+        let body = client_hello_body(0x0303, &[0x1301], &[(0, server_name_extension("www.example.com"))]);
+        let record = handshake_record(1, &body);
+        let segment = |seconds, sequence_number| {
+            let mut packet = tcp_packet_at(seconds, &[Flag::ACK]);
+            packet.sequence_number = Some(sequence_number);
+            packet
+        };
+
+        let mut packet = tcp_packet_at(1, &[Flag::SYN]);
+        packet.sequence_number = Some(1000);
+        tracker.add_packet(client_flow_id(), packet);
+        // the end of the ClientHello comes first, then its beginning is retransmitted
+        tracker.add_packet_with_payload(client_flow_id(), segment(2, 1021), &record[20..]);
+        tracker.add_packet_with_payload(client_flow_id(), segment(2, 1001), &record[..20]);
+        tracker.add_packet_with_payload(client_flow_id(), segment(3, 1001), &record[..20]);
+        tracker.add_packet_with_payload(server_flow_id(), segment(3, 5000), b"data");
+
+        tracker.flush();
+        let (_, flow_information) = tracker.drain_finished().next().unwrap();
+        assert_eq!(flow_information.sni.as_deref(), Some("www.example.com"));
+        let tcp_stream = flow_information.tcp_stream.unwrap();
+        assert_eq!(tcp_stream.client.data, record);
+        assert_eq!(tcp_stream.client.retransmitted_length, 20);
+        assert_eq!(tcp_stream.server.data, b"data");
+        assert!(tracker.tcp_reassembler.unwrap().is_empty());
+    }
+}